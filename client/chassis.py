@@ -1,30 +1,52 @@
 import multiprocessing
+import queue
 import time
 import os
 import sys
 is_arm_platform = not os.uname().machine == 'x86_64'
 if is_arm_platform:
     import OPi.GPIO as GPIO
+from move_msg import MoveMsg, STEPS_RUNNING, STEPS_COMPLETED, STEPS_INTERRUPTED
+
+# how often the steps move progress is reported, in seconds
+STEPS_PROGRESS_INTERVAL = 0.2
 
 
 def init_control_pins(control_pins):
     GPIO.setup(control_pins, GPIO.OUT, initial=GPIO.LOW)
 
 
-def rotate_wheels_process_func(events):
+def rotate_wheels_process_func(events, steps_queue, progress_queue, encoder_ticks):
     # sys.stdout = open(str(os.getpid()) + ".out", "a")
     # sys.stderr = open(str(os.getpid()) + "_error.out", "a")
     # print('Chassis init')
-    chassis = ChassisProcess(events)
+    chassis = ChassisProcess(events, steps_queue, progress_queue, encoder_ticks)
     while chassis.is_active():
         chassis.update_wheels_config()
-        chassis.rotate_wheels()
+        if chassis.steps_move:
+            chassis.step_wheels()
+        else:
+            chassis.rotate_wheels()
     chassis.dectivate()
     # print("Chassis deactivated")
 
 
+class StepsMove:
+    def __init__(self, move_id, left_steps, right_steps, rate):
+        self.move_id = move_id
+        self.left_steps = left_steps
+        self.right_steps = right_steps
+        self.rate = rate
+        self.left_done = 0
+        self.right_done = 0
+        self.tick = 0
+        self.ticks_num = max(abs(left_steps), abs(right_steps))
+        self.start_time = time.monotonic()
+        self.report_time = self.start_time
+
+
 class ChassisProcess:
-    def __init__(self, events):
+    def __init__(self, events, steps_queue, progress_queue, encoder_ticks):
         self.left_control_pins = [3, 5, 7, 16]
         self.right_control_pins = [15, 19, 21, 23]
         self.right_halfstep_seq = [
@@ -58,6 +80,12 @@ class ChassisProcess:
         self.left_wheel_backward_event = events[3]
         self.right_wheel_backward_event = events[4]
 
+        self.steps_queue = steps_queue
+        self.progress_queue = progress_queue
+        self.steps_move = None
+        # half-steps output to every wheel, reported as encoder ticks
+        self.left_ticks, self.right_ticks = encoder_ticks
+
     def is_active(self):
         return not self.stop_event.is_set()
 
@@ -76,26 +104,103 @@ class ChassisProcess:
         else:
             self.right_wheel_enabled = False
 
+        # None cancels the current steps move
+        try:
+            while True:
+                steps_cmd = self.steps_queue.get_nowait()
+                if self.steps_move:
+                    self.finish_steps_move(STEPS_INTERRUPTED)
+                if steps_cmd:
+                    self.steps_move = StepsMove(*steps_cmd)
+        except queue.Empty:
+            pass
+
+    def report_steps_progress(self, status):
+        move = self.steps_move
+        self.progress_queue.put(
+            (move.move_id, move.left_done, move.right_done, status))
+
+    def finish_steps_move(self, status):
+        self.report_steps_progress(status)
+        self.steps_move = None
+        self.release_coils()
+
+    def release_coils(self):
+        if is_arm_platform:
+            for pin in range(4):
+                GPIO.output(self.left_control_pins[pin], GPIO.LOW)
+                GPIO.output(self.right_control_pins[pin], GPIO.LOW)
+
+    def output_halfstep(self, control_pins, halfstep_seq, step_index):
+        if is_arm_platform:
+            for pin in range(4):
+                GPIO.output(control_pins[pin], halfstep_seq[step_index][pin])
+
+    def step_wheels(self):
+        move = self.steps_move
+        if move.tick >= move.ticks_num or move.rate == 0:
+            self.finish_steps_move(STEPS_COMPLETED)
+            return
+
+        # the wheel with fewer steps is spread evenly over the move
+        move.tick += 1
+        if move.left_done < move.tick * abs(move.left_steps) // move.ticks_num:
+            direction = 1 if move.left_steps > 0 else -1
+            self.left_step_index = (self.left_step_index + direction) % 8
+            self.output_halfstep(self.left_control_pins,
+                                 self.left_halfstep_seq, self.left_step_index)
+            move.left_done += 1
+            self.left_ticks.value += direction
+        if move.right_done < move.tick * abs(move.right_steps) // move.ticks_num:
+            direction = 1 if move.right_steps > 0 else -1
+            self.right_step_index = (self.right_step_index + direction) % 8
+            self.output_halfstep(self.right_control_pins,
+                                 self.right_halfstep_seq, self.right_step_index)
+            move.right_done += 1
+            self.right_ticks.value += direction
+
+        now = time.monotonic()
+        if now - move.report_time >= STEPS_PROGRESS_INTERVAL:
+            move.report_time = now
+            self.report_steps_progress(STEPS_RUNNING)
+
+        delay = move.start_time + move.tick / move.rate - now
+        if delay > 0:
+            time.sleep(delay)
+
     def rotate_wheels(self):
+        left_direction = -1 if self.left_wheel_backward_event.is_set() else 1
+        right_direction = -1 if self.right_wheel_backward_event.is_set() else 1
         for halfstep in range(8):
             for pin in range(4):
-                self.out_pins[0] = self.left_control_pins[pin] 
-                self.out_pins[1] = self.right_control_pins[pin] 
-                
+                self.out_pins[0] = self.left_control_pins[pin]
+                self.out_pins[1] = self.right_control_pins[pin]
+
                 if self.left_wheel_enabled:
-                    self.out_values[0] = self.left_halfstep_seq[halfstep][pin]
+                    index = (self.left_step_index + left_direction) % 8
+                    self.out_values[0] = self.left_halfstep_seq[index][pin]
                 else:
                     self.out_values[0] = GPIO.LOW
 
                 if self.right_wheel_enabled:
-                    self.out_values[1] = self.right_halfstep_seq[halfstep][pin]
+                    index = (self.right_step_index + right_direction) % 8
+                    self.out_values[1] = self.right_halfstep_seq[index][pin]
                 else:
-                    self.out_values[1] = GPIO.LOW 
+                    self.out_values[1] = GPIO.LOW
     #                print('{} - {}', self.out_pins, self.out_values)
                 if is_arm_platform:
                     GPIO.output(self.out_pins[0], self.out_values[0])
                     GPIO.output(self.out_pins[1], self.out_values[1])
             #time.sleep(0.01)
+            # only the half-steps which reached the motors are counted as ticks
+            if self.left_wheel_enabled:
+                self.left_step_index = (self.left_step_index + left_direction) % 8
+                if is_arm_platform:
+                    self.left_ticks.value += left_direction
+            if self.right_wheel_enabled:
+                self.right_step_index = (self.right_step_index + right_direction) % 8
+                if is_arm_platform:
+                    self.right_ticks.value += right_direction
 
     def rotate_right_motor(self):
         if is_arm_platform:
@@ -122,8 +227,13 @@ class Chassis:
                   self.left_wheel_backward_event,
                   self.right_wheel_backward_event,
                   ]
+        self.steps_queue = multiprocessing.Queue()
+        self.progress_queue = multiprocessing.Queue()
+        self.encoder_ticks = (multiprocessing.Value('i', 0),
+                              multiprocessing.Value('i', 0))
         self.wheels_process = multiprocessing.Process(
-            name='wheels_process', target=rotate_wheels_process_func, args=(events,))
+            name='wheels_process', target=rotate_wheels_process_func,
+            args=(events, self.steps_queue, self.progress_queue, self.encoder_ticks))
 
     def activate(self):
         self.wheels_process.start()
@@ -133,6 +243,7 @@ class Chassis:
         self.wheels_process.join()
 
     def move(self, msg):
+        self.steps_queue.put(None)
         if msg.left_speed != 0:
             self.enable_left_wheel_event.set()
         else:
@@ -152,3 +263,35 @@ class Chassis:
             self.right_wheel_backward_event.set()
         else:
             self.right_wheel_backward_event.clear()
+
+    def move_steps(self, msg):
+        self.enable_left_wheel_event.clear()
+        self.enable_right_wheel_event.clear()
+        self.steps_queue.put(
+            (msg.move_id, msg.left_steps, msg.right_steps, msg.rate))
+
+    def get_steps_progress(self, timeout):
+        try:
+            return self.progress_queue.get(timeout=timeout)
+        except queue.Empty:
+            return None
+
+    def move_wheels(self, msg):
+        # the chassis has two steppers, the front wheels stand for the sides
+        # and sideways motion of mecanum wheels is not possible
+        if len(msg.wheels) < 2:
+            print('Wrong number of wheels {}'.format(len(msg.wheels)))
+            return
+        if len(msg.wheels) == 4 and (msg.wheels[0] != msg.wheels[2] or msg.wheels[1] != msg.wheels[3]):
+            print('Rear wheels speeds differ from front ones, only front ones are used')
+        move_msg = MoveMsg()
+        move_msg.left_speed, move_msg.left_dir = msg.wheels[0]
+        move_msg.right_speed, move_msg.right_dir = msg.wheels[1]
+        self.move(move_msg)
+
+    # the ticks count the half-steps sent to the motors, there are none without GPIO
+    def has_encoder_ticks(self):
+        return is_arm_platform
+
+    def get_encoder_ticks(self):
+        return self.encoder_ticks[0].value, self.encoder_ticks[1].value
@@ -43,13 +43,13 @@ class Client:
             self.is_closed = True
 
 
-    def init(self, host, port):
+    def init(self, host, port, drive_type=None):
         self.socket = sock.socket(sock.AF_INET, sock.SOCK_STREAM)
         self.socket.connect((host, port))
         self.is_closed = False 
 
         # handshakes
-        hello_msg = HelloMsg()
+        hello_msg = HelloMsg(drive_type)
         self.send_msg(hello_msg)
         hello_msg = self.recv_msg()
         if MessageId.HELLO == hello_msg.id():
@@ -0,0 +1,41 @@
+import math
+try:
+    import smbus2
+except ImportError:
+    smbus2 = None
+
+MPU6050_ADDRESS = 0x68
+PWR_MGMT_1 = 0x6b
+ACCEL_XOUT_H = 0x3b
+GYRO_ZOUT_H = 0x47
+# default ranges: +-2 g and +-250 deg/s
+ACCEL_SCALE = 9.80665 / 16384.0
+GYRO_SCALE = math.radians(1.0) / 131.0
+# samples averaged at start to find the gyro bias, the robot should stand still
+CALIBRATION_SAMPLES = 100
+
+
+class Imu:
+    # MPU-6050 on the I2C bus, mounted with x forward and z up
+    def __init__(self, bus_number):
+        if not smbus2:
+            raise RuntimeError('smbus2 module is required for the IMU')
+        self.bus = smbus2.SMBus(bus_number)
+        self.bus.write_byte_data(MPU6050_ADDRESS, PWR_MGMT_1, 0)
+        self.gyro_bias = 0.0
+        self.gyro_bias = sum(self.read()[0]
+                             for _ in range(CALIBRATION_SAMPLES)) / CALIBRATION_SAMPLES
+
+    def read_word(self, register):
+        data = self.bus.read_i2c_block_data(MPU6050_ADDRESS, register, 2)
+        return int.from_bytes(data, byteorder='big', signed=True)
+
+    def read(self):
+        # returns (gyro_z, accel_x, accel_y)
+        gyro_z = self.read_word(GYRO_ZOUT_H) * GYRO_SCALE - self.gyro_bias
+        accel_x = self.read_word(ACCEL_XOUT_H) * ACCEL_SCALE
+        accel_y = self.read_word(ACCEL_XOUT_H + 2) * ACCEL_SCALE
+        return gyro_z, accel_x, accel_y
+
+    def close(self):
+        self.bus.close()
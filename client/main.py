@@ -5,13 +5,15 @@ import threading
 import time
 
 from client import Client
-from message import MessageId, HelloMsg, StopMsg
+from message import MessageId, DriveType, HelloMsg, StopMsg
 from camera_msg import *
 from image_msg import *
 from move_msg import *
 from camera_prop_msg import *
+from telemetry_msg import *
 
 from chassis import Chassis
+from imu import Imu
 
 cameras = dict()
 cameras_dict_lock = threading.RLock()
@@ -19,6 +21,10 @@ cameras_locks = dict()
 cameras_encoding = dict()
 fps = 30
 stop_event = threading.Event()
+timed_move_lock = threading.RLock()
+timed_move = None  # (move_id, timer) of the running timed move
+# how often the encoders and IMU readings are sent, in seconds
+TELEMETRY_INTERVAL = 0.05
 
 
 def image_capture_thread_func(client):
@@ -37,6 +43,28 @@ def image_capture_thread_func(client):
         done = stop_event.is_set()
 
 
+def steps_progress_thread_func(client, chassis):
+    while not stop_event.is_set():
+        progress = chassis.get_steps_progress(0.1)
+        if progress:
+            client.send_msg(StepsProgressMsg(*progress))
+
+
+def telemetry_thread_func(client, chassis, imu):
+    start_time = time.monotonic()
+    while not stop_event.is_set():
+        timestamp_ms = int((time.monotonic() - start_time) * 1000)
+        if chassis.has_encoder_ticks():
+            left_ticks, right_ticks = chassis.get_encoder_ticks()
+            client.send_msg(WheelEncodersMsg(timestamp_ms, left_ticks, right_ticks))
+        if imu:
+            try:
+                client.send_msg(ImuMsg(timestamp_ms, *imu.read()))
+            except OSError as err:
+                print('Failed to read the IMU: {}'.format(err))
+        time.sleep(TELEMETRY_INTERVAL)
+
+
 def get_msg_obj(msg_id):
     result = {
         MessageId.HELLO: HelloMsg(),
@@ -48,7 +76,10 @@ def get_msg_obj(msg_id):
         MessageId.GET_CAMERA_PROP: GetCameraPropMsg(),
         MessageId.SEND_CAMERA_PROP: SendCameraPropMsg(),
         MessageId.STOP: StopMsg(),
-        MessageId.SET_CAMERA_PROP: SetCameraPropMsg()
+        MessageId.SET_CAMERA_PROP: SetCameraPropMsg(),
+        MessageId.TIMED_MOVE: TimedMoveMsg(),
+        MessageId.MOVE_STEPS: MoveStepsMsg(),
+        MessageId.MULTI_MOVE: MultiMoveMsg()
     }.get(msg_id)
     if not result:
         print('Unknown msg_id {}'.format(msg_id))
@@ -178,23 +209,74 @@ def process_camera_prop(msg):
     return response
 
 
-def process_move(chassis, msg):
+def interrupt_timed_move(client):
+    global timed_move
+    with timed_move_lock:
+        if timed_move:
+            move_id, timer = timed_move
+            timer.cancel()
+            timed_move = None
+            client.send_msg(MoveDoneMsg(move_id, False))
+
+
+def finish_timed_move(chassis, client, move_id):
+    global timed_move
+    with timed_move_lock:
+        if not timed_move or timed_move[0] != move_id:
+            return
+        timed_move = None
+        print("Timed move {} done".format(move_id))
+        chassis.move(MoveMsg())
+        client.send_msg(MoveDoneMsg(move_id, True))
+
+
+def process_move(chassis, client, msg):
     print("Moving left {}:{} right {}:{}".format(
         msg.left_speed, msg.left_dir, msg.right_speed, msg.right_dir))
+    interrupt_timed_move(client)
     chassis.move(msg)
 
 
+def process_timed_move(chassis, client, msg):
+    global timed_move
+    print("Moving left {}:{} right {}:{} for {} ms".format(
+        msg.left_speed, msg.left_dir, msg.right_speed, msg.right_dir, msg.duration_ms))
+    with timed_move_lock:
+        interrupt_timed_move(client)
+        timer = threading.Timer(
+            msg.duration_ms / 1000.0, finish_timed_move, args=(chassis, client, msg.move_id))
+        timed_move = (msg.move_id, timer)
+        chassis.move(msg)
+        timer.start()
+
+
+def process_move_steps(chassis, client, msg):
+    print("Moving steps left {} right {} at {} steps/s".format(
+        msg.left_steps, msg.right_steps, msg.rate))
+    interrupt_timed_move(client)
+    chassis.move_steps(msg)
+
+
+def process_multi_move(chassis, client, msg):
+    print("Moving wheels {}".format(msg.wheels))
+    interrupt_timed_move(client)
+    chassis.move_wheels(msg)
+
+
 def process_stop(msg):
     print('Stopping...')
     return StopMsg()
 
 
-def process_message(msg, args):
+def process_message(msg, chassis, client):
     # print("Processing msg id : {}".format(msg.id()))
     result = {
         MessageId.CAPTURE_IMAGE: process_capture_image,
         MessageId.GET_CAMERA_LIST: process_get_camera_list,
-        MessageId.MOVE: lambda msg: process_move(args, msg),
+        MessageId.MOVE: lambda msg: process_move(chassis, client, msg),
+        MessageId.TIMED_MOVE: lambda msg: process_timed_move(chassis, client, msg),
+        MessageId.MOVE_STEPS: lambda msg: process_move_steps(chassis, client, msg),
+        MessageId.MULTI_MOVE: lambda msg: process_multi_move(chassis, client, msg),
         MessageId.GET_CAMERA_PROP: process_camera_prop,
         MessageId.STOP: process_stop,
         MessageId.SET_CAMERA_PROP: process_set_camera_prop,
@@ -207,6 +289,10 @@ def main():
     parser.add_argument('host', action="store")
     parser.add_argument('--port', action="store", dest="port", default=2345,
                         type=int, required=False)
+    parser.add_argument('--drive', action="store", dest="drive", default='differential',
+                        choices=['differential', 'skid-steer', 'mecanum'], required=False)
+    parser.add_argument('--imu-bus', action="store", dest="imu_bus", default=None,
+                        type=int, required=False, help='I2C bus of the MPU-6050 IMU')
     args = parser.parse_args()
     host = args.host
     port = args.port
@@ -216,21 +302,41 @@ def main():
     else:
         chassis = Chassis()
         chassis.activate()
+        imu = Imu(args.imu_bus) if args.imu_bus is not None else None
 
-        client = Client(lambda msg: process_message(msg, chassis), get_msg_obj)
-        client.init(host, port)
+        client = Client(lambda msg: process_message(
+            msg, chassis, client), get_msg_obj)
+        drive_type = {
+            'differential': DriveType.DIFFERENTIAL,
+            'skid-steer': DriveType.SKID_STEER,
+            'mecanum': DriveType.MECANUM
+        }[args.drive]
+        client.init(host, port, drive_type)
 
         capture_thread = threading.Thread(
             target=image_capture_thread_func, args=(client,))
         capture_thread.start()
+        progress_thread = threading.Thread(
+            target=steps_progress_thread_func, args=(client, chassis))
+        progress_thread.start()
+        telemetry_thread = threading.Thread(
+            target=telemetry_thread_func, args=(client, chassis, imu))
+        telemetry_thread.start()
 
         done = False
         while not done:
             done = not client.process_recv_message()
 
+        with timed_move_lock:
+            if timed_move:
+                timed_move[1].cancel()
         chassis.dectivate()
         stop_event.set()
         capture_thread.join()
+        progress_thread.join()
+        telemetry_thread.join()
+        if imu:
+            imu.close()
         for _, cam in cameras.items():
             cam.release()
         client.close()
@@ -12,6 +12,19 @@ class MessageId(IntEnum):
     SEND_CAMERA_PROP = 8
     STOP = 9
     SET_CAMERA_PROP = 10
+    TIMED_MOVE = 11
+    MOVE_DONE = 12
+    MOVE_STEPS = 13
+    STEPS_PROGRESS = 14
+    MULTI_MOVE = 15
+    WHEEL_ENCODERS = 16
+    IMU = 17
+
+
+class DriveType(IntEnum):
+    DIFFERENTIAL = 1
+    SKID_STEER = 2
+    MECANUM = 3
 
 
 class Message:
@@ -46,13 +59,17 @@ class SendMessage(Message):
 
 
 class HelloMsg(Message):
-    def __init__(self):
+    # the robot reports its drive type, the console answers without it
+    def __init__(self, drive_type=None):
         super().__init__(MessageId.HELLO)
+        self.drive_type = drive_type
 
     def size(self):
-        return 0
+        return 1 if self.drive_type else 0
 
     def to_bytes(self):
+        if self.drive_type:
+            return int(self.drive_type).to_bytes(1, byteorder='big')
         return None
 
     def from_bytes(self, data):
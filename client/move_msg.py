@@ -1,5 +1,5 @@
 
-from message import MessageId, RecvMessage
+from message import MessageId, RecvMessage, SendMessage
 
 
 class MoveMsg(RecvMessage):
@@ -15,3 +15,75 @@ class MoveMsg(RecvMessage):
         self.left_dir = int.from_bytes(data[1:2], byteorder='big')
         self.right_speed = int.from_bytes(data[2:3], byteorder='big')
         self.right_dir = int.from_bytes(data[3:4], byteorder='big')
+
+
+class TimedMoveMsg(RecvMessage):
+    def __init__(self):
+        super(TimedMoveMsg, self).__init__(MessageId.TIMED_MOVE)
+        self.move_id = 0
+        self.left_speed = 0
+        self.left_dir = 0
+        self.right_speed = 0
+        self.right_dir = 0
+        self.duration_ms = 0
+
+    def from_bytes(self, data):
+        self.move_id = int.from_bytes(data[0:4], byteorder='big')
+        self.left_speed = int.from_bytes(data[4:5], byteorder='big')
+        self.left_dir = int.from_bytes(data[5:6], byteorder='big')
+        self.right_speed = int.from_bytes(data[6:7], byteorder='big')
+        self.right_dir = int.from_bytes(data[7:8], byteorder='big')
+        self.duration_ms = int.from_bytes(data[8:12], byteorder='big')
+
+
+class MoveDoneMsg(SendMessage):
+    def __init__(self, move_id, completed):
+        super(MoveDoneMsg, self).__init__(MessageId.MOVE_DONE)
+        self.add_bytes(move_id.to_bytes(4, byteorder='big'))
+        self.add_bytes((1 if completed else 0).to_bytes(1, byteorder='big'))
+
+
+class MoveStepsMsg(RecvMessage):
+    def __init__(self):
+        super(MoveStepsMsg, self).__init__(MessageId.MOVE_STEPS)
+        self.move_id = 0
+        self.left_steps = 0
+        self.right_steps = 0
+        self.rate = 0
+
+    def from_bytes(self, data):
+        self.move_id = int.from_bytes(data[0:4], byteorder='big')
+        self.left_steps = int.from_bytes(
+            data[4:8], byteorder='big', signed=True)
+        self.right_steps = int.from_bytes(
+            data[8:12], byteorder='big', signed=True)
+        self.rate = int.from_bytes(data[12:14], byteorder='big')
+
+
+STEPS_RUNNING = 0
+STEPS_COMPLETED = 1
+STEPS_INTERRUPTED = 2
+
+
+class StepsProgressMsg(SendMessage):
+    def __init__(self, move_id, left_steps, right_steps, status):
+        super(StepsProgressMsg, self).__init__(MessageId.STEPS_PROGRESS)
+        self.add_bytes(move_id.to_bytes(4, byteorder='big'))
+        self.add_bytes(left_steps.to_bytes(4, byteorder='big'))
+        self.add_bytes(right_steps.to_bytes(4, byteorder='big'))
+        self.add_bytes(status.to_bytes(1, byteorder='big'))
+
+
+class MultiMoveMsg(RecvMessage):
+    def __init__(self):
+        super(MultiMoveMsg, self).__init__(MessageId.MULTI_MOVE)
+        # (speed, dir) for every wheel, front left, front right, rear left, rear right
+        self.wheels = []
+
+    def from_bytes(self, data):
+        count = int.from_bytes(data[0:1], byteorder='big')
+        self.wheels = []
+        for i in range(count):
+            speed = int.from_bytes(data[1 + 2 * i:2 + 2 * i], byteorder='big')
+            dir = int.from_bytes(data[2 + 2 * i:3 + 2 * i], byteorder='big')
+            self.wheels.append((speed, dir))
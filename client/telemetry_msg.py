@@ -0,0 +1,20 @@
+from message import MessageId, SendMessage
+
+
+class WheelEncodersMsg(SendMessage):
+    # half-steps counted since the start, timestamp in ms of the robot clock
+    def __init__(self, timestamp_ms, left_ticks, right_ticks):
+        super(WheelEncodersMsg, self).__init__(MessageId.WHEEL_ENCODERS)
+        self.add_bytes((timestamp_ms & 0xffffffff).to_bytes(4, byteorder='big'))
+        self.add_bytes(left_ticks.to_bytes(4, byteorder='big', signed=True))
+        self.add_bytes(right_ticks.to_bytes(4, byteorder='big', signed=True))
+
+
+class ImuMsg(SendMessage):
+    # gyro_z in rad/s counterclockwise, accelerations in m/s^2, x forward and y to the left
+    def __init__(self, timestamp_ms, gyro_z, accel_x, accel_y):
+        super(ImuMsg, self).__init__(MessageId.IMU)
+        self.add_bytes((timestamp_ms & 0xffffffff).to_bytes(4, byteorder='big'))
+        for value in [gyro_z, accel_x, accel_y]:
+            self.add_bytes(int(round(value * 1000)).to_bytes(
+                4, byteorder='big', signed=True))
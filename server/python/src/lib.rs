@@ -0,0 +1,484 @@
+extern crate netbot_core;
+extern crate numpy;
+extern crate pyo3;
+
+use netbot_core::geofence::parse_geofence;
+use netbot_core::kinematics::WheelCommand;
+use netbot_core::mission::{parse_mission, MissionState};
+use netbot_core::robot::{Robot as CoreRobot, RobotErrors, StepsStatus};
+use netbot_core::route::Route;
+use netbot_core::script::{ScriptConfig, ScriptEvent};
+use netbot_core::server::ServerErrors;
+use numpy::PyArray1;
+use pyo3::exceptions::{PyRuntimeError, PyTimeoutError};
+use pyo3::prelude::*;
+use std::error::Error;
+use std::net::Ipv4Addr;
+use std::thread;
+use std::time::Duration;
+
+fn to_py_err(err: Box<dyn Error>) -> PyErr {
+    let is_timeout = match err.downcast_ref::<RobotErrors>() {
+        Some(RobotErrors::Timeout) => true,
+        _ => match err.downcast_ref::<ServerErrors>() {
+            Some(ServerErrors::ConnectionTimeout) | Some(ServerErrors::ReceiveTimeout) => true,
+            _ => false,
+        },
+    };
+    if is_timeout {
+        PyTimeoutError::new_err(err.to_string())
+    } else {
+        PyRuntimeError::new_err(err.to_string())
+    }
+}
+
+const ROBOT_UPDATE_INTERVAL: Duration = Duration::from_millis(20);
+
+fn to_duration(timeout: Option<f64>) -> Option<Duration> {
+    timeout.map(|seconds| Duration::from_secs_f64(seconds.max(0.0)))
+}
+
+/// Connection to the robot client, the console side listens and the robot connects.
+#[pyclass]
+struct Robot {
+    robot: CoreRobot,
+    camera_list: Option<Vec<u8>>,
+}
+
+#[pymethods]
+impl Robot {
+    #[new]
+    fn new() -> PyResult<Self> {
+        Ok(Robot {
+            robot: CoreRobot::new().map_err(to_py_err)?,
+            camera_list: None,
+        })
+    }
+
+    /// Waits for the robot client on the given address, `timeout` is in seconds.
+    #[args(port = "2345", timeout = "None")]
+    fn listen(&mut self, py: Python, addr: &str, port: u16, timeout: Option<f64>) -> PyResult<()> {
+        let addr = addr
+            .parse::<Ipv4Addr>()
+            .map_err(|err| PyRuntimeError::new_err(err.to_string()))?;
+        let robot = &mut self.robot;
+        py.allow_threads(|| {
+            robot
+                .init_timeout(addr, port, to_duration(timeout))
+                .map_err(to_py_err)
+        })
+    }
+
+    /// Requests the camera list, the robot starts sending frames only after this call.
+    #[args(timeout = "10.0")]
+    fn camera_list(&mut self, py: Python, timeout: f64) -> PyResult<Vec<u8>> {
+        if let Some(camera_list) = &self.camera_list {
+            return Ok(camera_list.clone());
+        }
+        let robot = &mut self.robot;
+        let camera_list = py.allow_threads(|| -> PyResult<Vec<u8>> {
+            robot.ask_camera_list().map_err(to_py_err)?;
+            robot
+                .wait_camera_list(Duration::from_secs_f64(timeout))
+                .map_err(to_py_err)
+        })?;
+        self.camera_list = Some(camera_list.clone());
+        Ok(camera_list)
+    }
+
+    /// Supported resolutions of the camera as (width, height) tuples.
+    #[args(timeout = "10.0")]
+    fn resolutions(
+        &mut self,
+        py: Python,
+        camera_id: u8,
+        timeout: f64,
+    ) -> PyResult<Vec<(i32, i32)>> {
+        let robot = &mut self.robot;
+        py.allow_threads(|| -> PyResult<Vec<(i32, i32)>> {
+            robot.ask_camera_prop(camera_id).map_err(to_py_err)?;
+            robot
+                .wait_camera_resolutions(camera_id, Duration::from_secs_f64(timeout))
+                .map_err(to_py_err)
+        })
+    }
+
+    /// Zero width, height or fps keeps the current camera value.
+    #[args(width = "0", height = "0", fps = "0", encode = "false")]
+    fn set_camera_prop(
+        &mut self,
+        camera_id: u8,
+        width: u16,
+        height: u16,
+        fps: u8,
+        encode: bool,
+    ) -> PyResult<()> {
+        self.robot
+            .ask_set_camera_prop(camera_id, width, height, fps, encode)
+            .map_err(to_py_err)
+    }
+
+    /// Latest full resolution frame as a height x width x channels uint8 array in RGB order.
+    fn frame<'py>(&self, py: Python<'py>, camera_id: u8) -> PyResult<Option<&'py PyAny>> {
+        let frame = self.robot.get_frame(camera_id).map_err(to_py_err)?;
+        match frame {
+            Some(frame) => {
+                let array = PyArray1::from_vec(py, frame.data).reshape([
+                    frame.height,
+                    frame.width,
+                    frame.channels,
+                ])?;
+                Ok(Some(array.as_ref()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Width, height, channels, encoding and sequence number of the latest frame.
+    fn frame_info(&self, camera_id: u8) -> Option<(u16, u16, u16, bool, u64)> {
+        self.robot.get_frame_info(camera_id).map(|info| {
+            (
+                info.width,
+                info.height,
+                info.channels,
+                info.encoded,
+                info.number,
+            )
+        })
+    }
+
+    /// Processes the time based events: the motion watchdog, speed ramp, geofence and
+    /// timed move ends. Should be called every few tens of milliseconds while the robot
+    /// moves, the blocking methods like `run_mission` call it by themselves.
+    fn update(&mut self) {
+        self.robot.update();
+    }
+
+    /// Stops the robot when a move is not sent again within the timeout in seconds,
+    /// None disables it. It works only while `update` is called.
+    fn set_motion_watchdog(&mut self, timeout: Option<f64>) {
+        self.robot.set_motion_watchdog(to_duration(timeout));
+    }
+
+    /// Keeps the last move going for another watchdog timeout.
+    fn refresh_motion(&mut self) {
+        self.robot.refresh_motion();
+    }
+
+    /// Drives until the next command, see `update` for the limits applied over time.
+    fn move_bot(
+        &mut self,
+        left_speed: u8,
+        left_dir: u8,
+        right_speed: u8,
+        right_dir: u8,
+    ) -> PyResult<()> {
+        self.robot
+            .ask_move_bot(left_speed, left_dir, right_speed, right_dir)
+            .map_err(to_py_err)
+    }
+
+    /// Moves for the duration in seconds, the robot stops by itself. Returns the move id.
+    fn move_for(
+        &mut self,
+        left_speed: u8,
+        left_dir: u8,
+        right_speed: u8,
+        right_dir: u8,
+        duration: f64,
+    ) -> PyResult<u32> {
+        let cmd = WheelCommand {
+            left_speed: left_speed,
+            left_dir: left_dir,
+            right_speed: right_speed,
+            right_dir: right_dir,
+        };
+        self.robot
+            .move_for(cmd, Duration::from_secs_f64(duration.max(0.0)))
+            .map_err(to_py_err)
+    }
+
+    /// Waits for the timed move end, returns False if it was interrupted by another command.
+    #[args(timeout = "10.0")]
+    fn wait_move_done(&mut self, py: Python, move_id: u32, timeout: f64) -> PyResult<bool> {
+        let robot = &mut self.robot;
+        py.allow_threads(|| {
+            robot
+                .wait_move_done(move_id, Duration::from_secs_f64(timeout))
+                .map_err(to_py_err)
+        })
+    }
+
+    /// Moves the wheels by exact half-steps at the rate in half-steps per second,
+    /// returns (left_steps, right_steps, completed) when the move is done.
+    fn move_steps(
+        &mut self,
+        py: Python,
+        left_steps: i32,
+        right_steps: i32,
+        rate: u16,
+    ) -> PyResult<(u32, u32, bool)> {
+        let robot = &mut self.robot;
+        let progress = py.allow_threads(|| {
+            robot
+                .move_steps(left_steps, right_steps, rate)
+                .map_err(to_py_err)
+        })?;
+        Ok((
+            progress.left_steps,
+            progress.right_steps,
+            progress.status == StepsStatus::Completed,
+        ))
+    }
+
+    /// Starts the steps move without waiting and returns its id, `update` follows the
+    /// progress and stops the robot when the move does not finish in time.
+    fn start_move_steps(&mut self, left_steps: i32, right_steps: i32, rate: u16) -> PyResult<u32> {
+        self.robot
+            .start_move_steps(left_steps, right_steps, rate)
+            .map_err(to_py_err)
+    }
+
+    /// Returns (left_steps, right_steps, running) of the steps move, None before the robot
+    /// reports it.
+    fn steps_move_progress(&self, move_id: u32) -> Option<(u32, u32, bool)> {
+        self.robot.get_steps_move_progress(move_id).map(|progress| {
+            (
+                progress.left_steps,
+                progress.right_steps,
+                progress.status == StepsStatus::Running,
+            )
+        })
+    }
+
+    /// Drives straight by the distance in meters, returns True if the move completed.
+    fn drive_distance(&mut self, py: Python, meters: f64) -> PyResult<bool> {
+        let robot = &mut self.robot;
+        let progress = py.allow_threads(|| robot.drive_distance(meters).map_err(to_py_err))?;
+        Ok(progress.status == StepsStatus::Completed)
+    }
+
+    /// Turns in place by the angle in degrees, positive to the left.
+    fn turn_angle(&mut self, py: Python, degrees: f64) -> PyResult<bool> {
+        let robot = &mut self.robot;
+        let progress = py.allow_threads(|| robot.turn_angle(degrees).map_err(to_py_err))?;
+        Ok(progress.status == StepsStatus::Completed)
+    }
+
+    /// Drives with the linear velocity in m/s and angular velocity in rad/s, positive to the left.
+    /// The motion is limited over time only while `update` is called.
+    fn set_velocity(&mut self, linear: f64, angular: f64) -> PyResult<()> {
+        self.robot.set_velocity(linear, angular).map_err(to_py_err)
+    }
+
+    /// Drives with the body velocity, `linear_y` is to the left and moves only mecanum chassis.
+    fn set_body_velocity(&mut self, linear_x: f64, linear_y: f64, angular: f64) -> PyResult<()> {
+        self.robot
+            .set_body_velocity(linear_x, linear_y, angular)
+            .map_err(to_py_err)
+    }
+
+    /// Drive type of the chassis, "differential", "skid-steer" or "mecanum".
+    #[getter]
+    fn drive_type(&self) -> &'static str {
+        self.robot.get_drive_type().name()
+    }
+
+    /// Drives forward with the current speed until the next command, like `move_bot`.
+    fn move_forward(&mut self) {
+        self.robot.stop_moving();
+        self.robot.move_forward();
+    }
+
+    fn move_backward(&mut self) {
+        self.robot.stop_moving();
+        self.robot.move_backward();
+    }
+
+    fn rotate_left(&mut self) {
+        self.robot.stop_moving();
+        self.robot.rotate_left();
+    }
+
+    fn rotate_right(&mut self) {
+        self.robot.stop_moving();
+        self.robot.rotate_right();
+    }
+
+    /// Goes through the waypoints in the mission text format, one per line, and returns
+    /// (state, waypoints_done, events) when the mission is finished.
+    fn run_mission(
+        &mut self,
+        py: Python,
+        waypoints: &str,
+    ) -> PyResult<(String, usize, Vec<String>)> {
+        let steps = parse_mission(waypoints).map_err(to_py_err)?;
+        let robot = &mut self.robot;
+        py.allow_threads(|| {
+            robot.start_mission(steps).map_err(to_py_err)?;
+            let mut events = Vec::new();
+            loop {
+                robot.update();
+                events.extend(
+                    robot
+                        .take_mission_events()
+                        .iter()
+                        .map(|event| event.to_string()),
+                );
+                if let Some(status) = robot.get_mission_status() {
+                    if status.state != MissionState::Running && status.state != MissionState::Paused
+                    {
+                        return Ok((String::from(status.state.name()), status.waypoint, events));
+                    }
+                }
+                thread::sleep(ROBOT_UPDATE_INTERVAL);
+            }
+        })
+    }
+
+    /// Records the motion commands sent from now on.
+    fn start_route_recording(&mut self) {
+        self.robot.start_route_recording();
+    }
+
+    /// Stops the recording and saves the route to the CSV file.
+    fn stop_route_recording(&mut self, path: &str) -> PyResult<()> {
+        self.robot
+            .stop_route_recording()
+            .and_then(|route| route.save(path))
+            .map_err(to_py_err)
+    }
+
+    /// Drives the route from the file, backward from its end with reverse, and returns
+    /// when it is done. The speed scale makes the robot drive the same path faster or slower.
+    #[args(reverse = "false", speed_scale = "1.0")]
+    fn replay_route(
+        &mut self,
+        py: Python,
+        path: &str,
+        reverse: bool,
+        speed_scale: f64,
+    ) -> PyResult<()> {
+        let route = Route::load(path).map_err(to_py_err)?;
+        let robot = &mut self.robot;
+        py.allow_threads(|| {
+            robot
+                .start_replay(route, reverse, speed_scale)
+                .map_err(to_py_err)?;
+            while robot.is_replaying() {
+                thread::sleep(ROBOT_UPDATE_INTERVAL);
+                robot.update();
+            }
+            Ok(())
+        })
+    }
+
+    /// Runs the Rhai script and returns its printed lines, raises RuntimeError when the
+    /// script fails or runs longer than the time limit in seconds.
+    #[args(time_limit = "60.0")]
+    fn run_script(&mut self, py: Python, source: &str, time_limit: f64) -> PyResult<Vec<String>> {
+        let robot = &mut self.robot;
+        py.allow_threads(|| {
+            let config = ScriptConfig {
+                time_limit: Duration::from_secs_f64(time_limit.max(0.0)),
+            };
+            robot.start_script(source, config).map_err(to_py_err)?;
+            let mut output = Vec::new();
+            loop {
+                thread::sleep(ROBOT_UPDATE_INTERVAL);
+                robot.update();
+                for event in robot.take_script_events() {
+                    match event {
+                        ScriptEvent::Output(line) => output.push(line),
+                        ScriptEvent::Finished => return Ok(output),
+                        event => return Err(PyRuntimeError::new_err(event.to_string())),
+                    }
+                }
+            }
+        })
+    }
+
+    fn stop_moving(&mut self) {
+        self.robot.stop_moving();
+    }
+
+    /// Stops the robot and ignores any motion until `reset_emergency_stop` is called.
+    fn emergency_stop(&mut self) {
+        self.robot.emergency_stop();
+    }
+
+    fn reset_emergency_stop(&mut self) {
+        self.robot.reset_emergency_stop();
+    }
+
+    #[getter]
+    fn is_emergency_stopped(&self) -> bool {
+        self.robot.is_emergency_stopped()
+    }
+
+    /// Estimated (x, y, heading) in meters and radians since the last `reset_pose`.
+    #[getter]
+    fn pose(&self) -> (f64, f64, f64) {
+        let pose = self.robot.pose();
+        (pose.x, pose.y, pose.heading)
+    }
+
+    /// Covariance of (x, y, heading) as a 3 x 3 list, None until the robot sends telemetry.
+    #[getter]
+    fn pose_covariance(&self) -> Option<Vec<Vec<f64>>> {
+        self.robot
+            .pose_estimate()
+            .map(|estimate| estimate.covariance.iter().map(|row| row.to_vec()).collect())
+    }
+
+    fn reset_pose(&mut self) {
+        self.robot.reset_pose();
+    }
+
+    /// Limits the motion to the area and speed from the geofence JSON text, None
+    /// removes the limits.
+    fn set_geofence(&mut self, config: Option<&str>) -> PyResult<()> {
+        let geofence = config.map(parse_geofence).transpose().map_err(to_py_err)?;
+        self.robot.set_geofence(geofence);
+        Ok(())
+    }
+
+    #[getter]
+    fn is_inside_geofence(&self) -> bool {
+        self.robot.is_inside_geofence()
+    }
+
+    /// Writes the pose for every received frame to the CSV file.
+    fn start_pose_log(&mut self, path: &str) -> PyResult<()> {
+        self.robot.start_pose_log(path).map_err(to_py_err)
+    }
+
+    fn stop_pose_log(&mut self) {
+        self.robot.stop_pose_log();
+    }
+
+    #[getter]
+    fn is_connected(&self) -> bool {
+        self.robot.is_connected()
+    }
+
+    #[getter]
+    fn is_moving(&self) -> bool {
+        self.robot.is_moving()
+    }
+
+    /// Stops the robot and closes the connection, the robot client exits after that.
+    fn close(&mut self, py: Python) -> PyResult<()> {
+        let robot = &mut self.robot;
+        py.allow_threads(|| {
+            robot.stop_moving();
+            robot.stop().map_err(to_py_err)
+        })
+    }
+}
+
+#[pymodule]
+fn netbot(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<Robot>()?;
+    Ok(())
+}
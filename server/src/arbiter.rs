@@ -0,0 +1,274 @@
+// Decides which input source drives the robot when several of them
+// (keyboard, gamepad, scripts, remote API) send motion commands.
+// The active source with the highest priority wins, a source is active
+// while its last command is not a stop and it is not idle for longer
+// than its timeout.
+use super::kinematics;
+use kinematics::{WheelCommand, WheelState};
+use std::time::{Duration, Instant};
+
+pub type SourceId = usize;
+
+// Sides command for any chassis, or every wheel speed for multi-wheel chassis
+#[derive(Debug, Clone, PartialEq)]
+pub enum MotionCommand {
+    Sides(WheelCommand),
+    Wheels(Vec<WheelState>),
+}
+
+impl MotionCommand {
+    pub fn is_stop(&self) -> bool {
+        match self {
+            MotionCommand::Sides(cmd) => cmd.is_stop(),
+            MotionCommand::Wheels(wheels) => wheels.iter().all(|wheel| wheel.speed == 0),
+        }
+    }
+}
+
+impl Default for MotionCommand {
+    fn default() -> MotionCommand {
+        MotionCommand::Sides(WheelCommand::default())
+    }
+}
+
+struct InputSource {
+    name: String,
+    priority: u8,
+    idle_timeout: Option<Duration>,
+    command: MotionCommand,
+    last_update: Option<Instant>,
+}
+
+impl InputSource {
+    fn is_active(&self, now: Instant) -> bool {
+        match self.last_update {
+            Some(last_update) => {
+                !self.command.is_stop()
+                    && self
+                        .idle_timeout
+                        .map_or(true, |timeout| now.duration_since(last_update) <= timeout)
+            }
+            None => false,
+        }
+    }
+}
+
+pub struct MotionArbiter {
+    sources: Vec<InputSource>,
+    last_sent: MotionCommand,
+}
+
+impl MotionArbiter {
+    pub fn new() -> MotionArbiter {
+        MotionArbiter {
+            sources: Vec::new(),
+            last_sent: MotionCommand::default(),
+        }
+    }
+
+    // idle_timeout of None means the source stays active until it sends stop or is released
+    pub fn register(
+        &mut self,
+        name: &str,
+        priority: u8,
+        idle_timeout: Option<Duration>,
+    ) -> SourceId {
+        self.sources.push(InputSource {
+            name: String::from(name),
+            priority: priority,
+            idle_timeout: idle_timeout,
+            command: MotionCommand::default(),
+            last_update: None,
+        });
+        self.sources.len() - 1
+    }
+
+    // Sets the source command, submitting the same command again refreshes the idle timer
+    pub fn submit(&mut self, source: SourceId, cmd: WheelCommand, now: Instant) {
+        self.submit_command(source, MotionCommand::Sides(cmd), now);
+    }
+
+    pub fn submit_wheels(&mut self, source: SourceId, wheels: Vec<WheelState>, now: Instant) {
+        self.submit_command(source, MotionCommand::Wheels(wheels), now);
+    }
+
+    pub fn submit_command(&mut self, source: SourceId, cmd: MotionCommand, now: Instant) {
+        let source = &mut self.sources[source];
+        source.command = cmd;
+        source.last_update = Some(now);
+    }
+
+    // Restarts the idle timer of an active source without changing its command
+    pub fn refresh(&mut self, source: SourceId, now: Instant) {
+        let source = &mut self.sources[source];
+        if source.is_active(now) {
+            source.last_update = Some(now);
+        }
+    }
+
+    pub fn release(&mut self, source: SourceId) {
+        let source = &mut self.sources[source];
+        source.command = MotionCommand::default();
+        source.last_update = None;
+    }
+
+    pub fn release_all(&mut self) {
+        for source in 0..self.sources.len() {
+            self.release(source);
+        }
+    }
+
+    // Releases all sources and forgets the last sent command
+    pub fn reset(&mut self) {
+        self.release_all();
+        self.last_sent = MotionCommand::default();
+    }
+
+    pub fn active_source(&self, now: Instant) -> Option<SourceId> {
+        let mut active: Option<SourceId> = None;
+        for (id, source) in self.sources.iter().enumerate() {
+            if source.is_active(now)
+                && active.map_or(true, |best| source.priority > self.sources[best].priority)
+            {
+                active = Some(id);
+            }
+        }
+        active
+    }
+
+    pub fn source_name(&self, source: SourceId) -> &str {
+        &self.sources[source].name
+    }
+
+    // Command of the winning source, or stop when no source is active
+    pub fn command(&self, now: Instant) -> MotionCommand {
+        self.active_source(now)
+            .map_or(MotionCommand::default(), |id| {
+                self.sources[id].command.clone()
+            })
+    }
+
+    // Returns the command to send when it differs from the last sent one
+    pub fn update(&mut self, now: Instant) -> Option<MotionCommand> {
+        let cmd = self.command(now);
+        if cmd != self.last_sent {
+            self.last_sent = cmd.clone();
+            Some(cmd)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kinematics::{DIR_BACKWARD, DIR_FORWARD};
+
+    fn forward(speed: u8) -> WheelCommand {
+        WheelCommand {
+            left_speed: speed,
+            left_dir: DIR_FORWARD,
+            right_speed: speed,
+            right_dir: DIR_FORWARD,
+        }
+    }
+
+    fn sides(speed: u8) -> MotionCommand {
+        MotionCommand::Sides(forward(speed))
+    }
+
+    #[test]
+    fn higher_priority_preempts() {
+        let now = Instant::now();
+        let mut arbiter = MotionArbiter::new();
+        let script = arbiter.register("script", 5, None);
+        let keyboard = arbiter.register("keyboard", 30, None);
+        arbiter.submit(script, forward(20), now);
+        assert_eq!(arbiter.active_source(now), Some(script));
+        assert_eq!(arbiter.update(now), Some(sides(20)));
+
+        arbiter.submit(keyboard, forward(50), now);
+        assert_eq!(arbiter.active_source(now), Some(keyboard));
+        assert_eq!(arbiter.source_name(keyboard), "keyboard");
+        assert_eq!(arbiter.update(now), Some(sides(50)));
+        // the lower priority source can not take over
+        arbiter.submit(script, forward(30), now);
+        assert_eq!(arbiter.update(now), None);
+    }
+
+    #[test]
+    fn release_and_stop_give_back_control() {
+        let now = Instant::now();
+        let mut arbiter = MotionArbiter::new();
+        let script = arbiter.register("script", 5, None);
+        let keyboard = arbiter.register("keyboard", 30, None);
+        arbiter.submit(script, forward(20), now);
+        arbiter.submit(keyboard, forward(50), now);
+        assert_eq!(arbiter.update(now), Some(sides(50)));
+
+        arbiter.release(keyboard);
+        assert_eq!(arbiter.active_source(now), Some(script));
+        assert_eq!(arbiter.update(now), Some(sides(20)));
+
+        // a stop command makes the source inactive too
+        arbiter.submit(script, WheelCommand::default(), now);
+        assert_eq!(arbiter.active_source(now), None);
+        assert_eq!(arbiter.update(now), Some(MotionCommand::default()));
+        assert_eq!(arbiter.update(now), None);
+    }
+
+    #[test]
+    fn idle_sources_time_out() {
+        let start = Instant::now();
+        let timeout = Duration::from_millis(500);
+        let mut arbiter = MotionArbiter::new();
+        let remote = arbiter.register("remote", 10, None);
+        let gamepad = arbiter.register("gamepad", 20, Some(timeout));
+        arbiter.submit(remote, forward(20), start);
+        arbiter.submit(gamepad, forward(40), start);
+        assert_eq!(arbiter.update(start + timeout), Some(sides(40)));
+
+        // the stale gamepad gives the control back, the source without timeout keeps it
+        let later = start + timeout * 2;
+        assert_eq!(arbiter.active_source(later), Some(remote));
+        assert_eq!(arbiter.update(later), Some(sides(20)));
+
+        // refreshing does not bring back a source which already went idle
+        arbiter.refresh(gamepad, later);
+        assert_eq!(arbiter.active_source(later), Some(remote));
+        arbiter.submit(gamepad, forward(40), later);
+        arbiter.refresh(gamepad, later + timeout);
+        assert_eq!(arbiter.active_source(later + timeout * 2), Some(gamepad));
+    }
+
+    #[test]
+    fn same_command_is_sent_once() {
+        let now = Instant::now();
+        let mut arbiter = MotionArbiter::new();
+        let gamepad = arbiter.register("gamepad", 20, None);
+        assert_eq!(arbiter.update(now), None);
+        arbiter.submit(gamepad, forward(40), now);
+        assert_eq!(arbiter.update(now), Some(sides(40)));
+        arbiter.submit(gamepad, forward(40), now);
+        assert_eq!(arbiter.update(now), None);
+
+        let wheels = vec![
+            WheelState {
+                speed: 40,
+                dir: DIR_BACKWARD,
+            };
+            4
+        ];
+        arbiter.submit_wheels(gamepad, wheels.clone(), now);
+        assert_eq!(arbiter.update(now), Some(MotionCommand::Wheels(wheels)));
+
+        // the reset forgets the last sent command, so the next one is sent again
+        arbiter.reset();
+        assert_eq!(arbiter.active_source(now), None);
+        arbiter.submit(gamepad, forward(40), now);
+        assert_eq!(arbiter.update(now), Some(sides(40)));
+        arbiter.release_all();
+        assert_eq!(arbiter.update(now), Some(MotionCommand::default()));
+    }
+}
@@ -0,0 +1,665 @@
+extern crate netbot;
+extern crate serde_json;
+
+use netbot::calibration::{
+    corrected_scale, load_calibration, load_drive_type, robot_name, save_calibration,
+    DEFAULT_CALIBRATION_FILE,
+};
+use netbot::geofence::{load_geofence, DEFAULT_GEOFENCE_FILE};
+use netbot::kinematics::{WheelCommand, DIR_BACKWARD, DIR_FORWARD};
+use netbot::mission::{load_mission, MissionState};
+use netbot::robot::{Robot, RobotErrors, StepsProgress, StepsStatus};
+use netbot::route::Route;
+use netbot::script::{ScriptConfig, ScriptEvent};
+use netbot::server::ServerErrors;
+use serde_json::json;
+use std::env;
+use std::error::Error;
+use std::io::{self, Write};
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_TIMEOUT: i32 = 3;
+// the command finished but the robot client did not get the stop message
+const EXIT_STOP_FAILURE: i32 = 4;
+const ROBOT_UPDATE_INTERVAL: Duration = Duration::from_millis(20);
+
+const USAGE: &str =
+    "usage: netbot-ctl [--addr ADDR] [--port PORT] [--timeout SECS] [--calibration FILE]
+                  [--geofence FILE] COMMAND
+
+Waits for the robot client to connect, runs one command, prints the result as JSON
+and sends the stop message, so the robot client has to be restarted for every call.
+Motion is limited by the geofence file when it exists, netbot-geofence.json by default.
+
+commands:
+  cameras                                  list cameras with supported resolutions
+  resolutions CAMERA                       list supported resolutions of a camera
+  set-prop CAMERA [--width W] [--height H] [--fps F] [--encode]
+                                           change camera properties
+  snapshot CAMERA FILE                     save the latest camera frame, format from extension
+  move forward|backward|left|right DURATION
+                                           drive for a duration like 2s or 500ms
+  steps LEFT RIGHT RATE                    move the wheels by exact half-steps, negative for
+                                           backward, RATE is in half-steps per second
+  distance METERS                          drive straight, negative for backward
+  turn DEGREES                             turn in place, positive to the left
+  calibrate distance|turn VALUE            drive by the distance or angle, then read the
+                                           measured value from stdin and store the correction
+                                           in the calibration file, per robot address
+  mission FILE                             go through the waypoints from the file, the
+                                           progress is printed to stderr
+  replay FILE [--reverse] [--scale S]      drive the recorded route, backward from its end
+                                           with --reverse, S scales the speed
+  script FILE [--time-limit SECS]          run the Rhai script, its output is printed to
+                                           stderr, 60 seconds limit by default";
+
+enum Direction {
+    Forward,
+    Backward,
+    Left,
+    Right,
+}
+
+enum Calibration {
+    Distance,
+    Turn,
+}
+
+enum Command {
+    Cameras,
+    Resolutions(u8),
+    SetProp {
+        camera_id: u8,
+        width: u16,
+        height: u16,
+        fps: u8,
+        encode: bool,
+    },
+    Snapshot {
+        camera_id: u8,
+        path: String,
+    },
+    Move {
+        direction: Direction,
+        duration: Duration,
+    },
+    Steps {
+        left: i32,
+        right: i32,
+        rate: u16,
+    },
+    Distance(f64),
+    Turn(f64),
+    Calibrate {
+        calibration: Calibration,
+        value: f64,
+    },
+    Mission(String),
+    Replay {
+        path: String,
+        reverse: bool,
+        speed_scale: f64,
+    },
+    Script {
+        path: String,
+        time_limit: Duration,
+    },
+}
+
+struct Options {
+    addr: Ipv4Addr,
+    port: u16,
+    timeout: Duration,
+    calibration_file: String,
+    geofence_file: String,
+    command: Command,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(EXIT_USAGE);
+        }
+    };
+    let mut robot = match connect(&options) {
+        Ok(robot) => robot,
+        Err(err) => {
+            eprintln!("{}", json!({ "error": err.to_string() }));
+            process::exit(exit_code(err.as_ref()));
+        }
+    };
+    let result = execute(&mut robot, &options);
+    let stopped = robot.stop();
+    // the result of the command comes first, a failed stop only changes the exit code
+    // when the command succeeded
+    let code = match result {
+        Ok(output) => {
+            println!("{}", output);
+            None
+        }
+        Err(err) => {
+            eprintln!("{}", json!({ "error": err.to_string() }));
+            Some(exit_code(err.as_ref()))
+        }
+    };
+    if let Err(err) = stopped {
+        eprintln!("{}", json!({ "stop_error": err.to_string() }));
+        process::exit(code.unwrap_or(EXIT_STOP_FAILURE));
+    }
+    if let Some(code) = code {
+        process::exit(code);
+    }
+}
+
+fn exit_code(err: &(dyn Error + 'static)) -> i32 {
+    match err.downcast_ref::<RobotErrors>() {
+        Some(RobotErrors::Timeout) => return EXIT_TIMEOUT,
+        _ => (),
+    }
+    match err.downcast_ref::<ServerErrors>() {
+        Some(ServerErrors::ConnectionTimeout) | Some(ServerErrors::ReceiveTimeout) => {
+            return EXIT_TIMEOUT
+        }
+        _ => (),
+    }
+    EXIT_FAILURE
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut addr = Ipv4Addr::new(192, 168, 88, 184);
+    let mut port = 2345;
+    let mut timeout = Duration::from_secs(10);
+    let mut calibration_file = String::from(DEFAULT_CALIBRATION_FILE);
+    let mut geofence_file = String::from(DEFAULT_GEOFENCE_FILE);
+    let mut i = 0;
+    while i < args.len() && args[i].starts_with("--") {
+        let value = args
+            .get(i + 1)
+            .ok_or_else(|| format!("missing value for {}", args[i]))?;
+        match args[i].as_str() {
+            "--addr" => addr = parse_value(value, "address")?,
+            "--port" => port = parse_value(value, "port")?,
+            "--timeout" => timeout = parse_duration(value)?,
+            "--calibration" => calibration_file = value.clone(),
+            "--geofence" => geofence_file = value.clone(),
+            option => return Err(format!("unknown option {}", option)),
+        }
+        i += 2;
+    }
+
+    let command_args = &args[i..];
+    let command = match command_args.first().map(String::as_str) {
+        Some("cameras") => Command::Cameras,
+        Some("resolutions") => Command::Resolutions(parse_camera_id(command_args)?),
+        Some("set-prop") => {
+            let camera_id = parse_camera_id(command_args)?;
+            let mut width = 0;
+            let mut height = 0;
+            let mut fps = 0;
+            let mut encode = false;
+            let mut j = 2;
+            while j < command_args.len() {
+                if command_args[j] == "--encode" {
+                    encode = true;
+                    j += 1;
+                    continue;
+                }
+                let value = command_args
+                    .get(j + 1)
+                    .ok_or_else(|| format!("missing value for {}", command_args[j]))?;
+                match command_args[j].as_str() {
+                    "--width" => width = parse_value(value, "width")?,
+                    "--height" => height = parse_value(value, "height")?,
+                    "--fps" => fps = parse_value(value, "fps")?,
+                    option => return Err(format!("unknown option {}", option)),
+                }
+                j += 2;
+            }
+            Command::SetProp {
+                camera_id,
+                width,
+                height,
+                fps,
+                encode,
+            }
+        }
+        Some("snapshot") => Command::Snapshot {
+            camera_id: parse_camera_id(command_args)?,
+            path: command_args
+                .get(2)
+                .ok_or_else(|| String::from("missing snapshot file"))?
+                .clone(),
+        },
+        Some("move") => {
+            let direction = match command_args.get(1).map(String::as_str) {
+                Some("forward") => Direction::Forward,
+                Some("backward") => Direction::Backward,
+                Some("left") => Direction::Left,
+                Some("right") => Direction::Right,
+                _ => return Err(String::from("missing or wrong move direction")),
+            };
+            let duration = parse_duration(
+                command_args
+                    .get(2)
+                    .ok_or_else(|| String::from("missing move duration"))?,
+            )?;
+            Command::Move {
+                direction,
+                duration,
+            }
+        }
+        Some("steps") => {
+            let mut values = command_args.iter().skip(1);
+            let mut next_value = |name: &str| {
+                values
+                    .next()
+                    .ok_or_else(|| format!("missing {} value", name))
+                    .map(String::clone)
+            };
+            let left = parse_value(&next_value("left steps")?, "left steps")?;
+            let right = parse_value(&next_value("right steps")?, "right steps")?;
+            let rate = parse_value(&next_value("rate")?, "rate")?;
+            Command::Steps { left, right, rate }
+        }
+        Some("distance") => Command::Distance(parse_command_value(command_args, 1, "distance")?),
+        Some("turn") => Command::Turn(parse_command_value(command_args, 1, "angle")?),
+        Some("calibrate") => {
+            let calibration = match command_args.get(1).map(String::as_str) {
+                Some("distance") => Calibration::Distance,
+                Some("turn") => Calibration::Turn,
+                _ => return Err(String::from("missing or wrong calibration kind")),
+            };
+            Command::Calibrate {
+                calibration,
+                value: parse_command_value(command_args, 2, "calibration")?,
+            }
+        }
+        Some("mission") => Command::Mission(
+            command_args
+                .get(1)
+                .ok_or_else(|| String::from("missing mission file"))?
+                .clone(),
+        ),
+        Some("replay") => {
+            let path = command_args
+                .get(1)
+                .ok_or_else(|| String::from("missing route file"))?
+                .clone();
+            let mut reverse = false;
+            let mut speed_scale = 1.0;
+            let mut j = 2;
+            while j < command_args.len() {
+                match command_args[j].as_str() {
+                    "--reverse" => reverse = true,
+                    "--scale" => {
+                        speed_scale = parse_command_value(command_args, j + 1, "scale")?;
+                        j += 1;
+                    }
+                    option => return Err(format!("unknown option {}", option)),
+                }
+                j += 1;
+            }
+            Command::Replay {
+                path,
+                reverse,
+                speed_scale,
+            }
+        }
+        Some("script") => {
+            let path = command_args
+                .get(1)
+                .ok_or_else(|| String::from("missing script file"))?
+                .clone();
+            let mut time_limit = ScriptConfig::default().time_limit;
+            match command_args.get(2).map(String::as_str) {
+                Some("--time-limit") => {
+                    time_limit = parse_duration(
+                        command_args
+                            .get(3)
+                            .ok_or_else(|| String::from("missing value for --time-limit"))?,
+                    )?
+                }
+                Some(option) => return Err(format!("unknown option {}", option)),
+                None => (),
+            }
+            Command::Script { path, time_limit }
+        }
+        Some(command) => return Err(format!("unknown command {}", command)),
+        None => return Err(String::from("missing command")),
+    };
+    Ok(Options {
+        addr,
+        port,
+        timeout,
+        calibration_file,
+        geofence_file,
+        command,
+    })
+}
+
+fn parse_value<T: std::str::FromStr>(value: &str, name: &str) -> Result<T, String> {
+    value
+        .parse::<T>()
+        .map_err(|_| format!("wrong {} value {}", name, value))
+}
+
+fn parse_command_value(command_args: &[String], index: usize, name: &str) -> Result<f64, String> {
+    let value = command_args
+        .get(index)
+        .ok_or_else(|| format!("missing {} value", name))?;
+    let value = parse_value::<f64>(value, name)?;
+    if !value.is_finite() {
+        return Err(format!("wrong {} value {}", name, value));
+    }
+    Ok(value)
+}
+
+fn parse_camera_id(command_args: &[String]) -> Result<u8, String> {
+    let value = command_args
+        .get(1)
+        .ok_or_else(|| String::from("missing camera id"))?;
+    parse_value(value, "camera id")
+}
+
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let seconds = if value.ends_with("ms") {
+        parse_value::<f64>(&value[..value.len() - 2], "duration")? / 1000.0
+    } else if value.ends_with('s') {
+        parse_value::<f64>(&value[..value.len() - 1], "duration")?
+    } else {
+        parse_value::<f64>(value, "duration")?
+    };
+    if seconds < 0.0 || !seconds.is_finite() {
+        return Err(format!("wrong duration value {}", value));
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+fn wheel_command(left_speed: u8, left_dir: u8, right_speed: u8, right_dir: u8) -> WheelCommand {
+    WheelCommand {
+        left_speed: left_speed,
+        left_dir: left_dir,
+        right_speed: right_speed,
+        right_dir: right_dir,
+    }
+}
+
+fn steps_json(progress: &StepsProgress) -> serde_json::Value {
+    json!({
+        "left_steps": progress.left_steps,
+        "right_steps": progress.right_steps,
+        "completed": progress.status == StepsStatus::Completed,
+    })
+}
+
+fn run_mission(robot: &mut Robot, path: &str) -> Result<serde_json::Value, Box<dyn Error>> {
+    robot.start_mission(load_mission(path)?)?;
+    let mut events = Vec::new();
+    loop {
+        robot.update();
+        for event in robot.take_mission_events() {
+            eprintln!("{}", event);
+            events.push(event.to_string());
+        }
+        let status = robot
+            .get_mission_status()
+            .ok_or("the mission was not started")?;
+        if status.state != MissionState::Running && status.state != MissionState::Paused {
+            return Ok(json!({
+                "state": status.state.name(),
+                "waypoint": status.waypoint,
+                "waypoint_count": status.waypoint_count,
+                "completed": status.state == MissionState::Completed,
+                "events": events,
+            }));
+        }
+        thread::sleep(ROBOT_UPDATE_INTERVAL);
+    }
+}
+
+fn replay_route(
+    robot: &mut Robot,
+    path: &str,
+    reverse: bool,
+    speed_scale: f64,
+) -> Result<serde_json::Value, Box<dyn Error>> {
+    let route = Route::load(path)?;
+    let entries = route.entries().len();
+    let duration = route.duration().as_secs_f64();
+    robot.start_replay(route, reverse, speed_scale)?;
+    while robot.is_replaying() {
+        thread::sleep(ROBOT_UPDATE_INTERVAL);
+        robot.update();
+    }
+    Ok(json!({
+        "file": path,
+        "reverse": reverse,
+        "entries": entries,
+        "duration": duration,
+    }))
+}
+
+fn run_script(
+    robot: &mut Robot,
+    path: &str,
+    time_limit: Duration,
+) -> Result<serde_json::Value, Box<dyn Error>> {
+    let source = std::fs::read_to_string(path)?;
+    // the client only opens its cameras after the camera list request
+    robot.ask_camera_list()?;
+    robot.start_script(
+        &source,
+        ScriptConfig {
+            time_limit: time_limit,
+        },
+    )?;
+    let mut output = Vec::new();
+    loop {
+        thread::sleep(ROBOT_UPDATE_INTERVAL);
+        robot.update();
+        for event in robot.take_script_events() {
+            match event {
+                ScriptEvent::Output(line) => {
+                    eprintln!("{}", line);
+                    output.push(line);
+                }
+                ScriptEvent::Finished => {
+                    return Ok(json!({ "file": path, "output": output }));
+                }
+                event => return Err(event.to_string().into()),
+            }
+        }
+    }
+}
+
+fn read_measurement(name: &str) -> Result<f64, Box<dyn Error>> {
+    eprint!("measured {}: ", name);
+    io::stderr().flush()?;
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    Ok(line.trim().parse::<f64>()?)
+}
+
+fn calibrate(
+    robot: &mut Robot,
+    options: &Options,
+    calibration: &Calibration,
+    value: f64,
+) -> Result<serde_json::Value, Box<dyn Error>> {
+    let mut drive_config = robot.get_drive_config();
+    let (name, progress) = match calibration {
+        Calibration::Distance => ("distance", robot.drive_distance(value)?),
+        Calibration::Turn => ("angle", robot.turn_angle(value)?),
+    };
+    if progress.status != StepsStatus::Completed {
+        return Err("the move was interrupted".into());
+    }
+    let measured = read_measurement(name)?;
+    match calibration {
+        Calibration::Distance => {
+            drive_config.distance_scale =
+                corrected_scale(drive_config.distance_scale, value, measured)?
+        }
+        Calibration::Turn => {
+            drive_config.rotation_scale =
+                corrected_scale(drive_config.rotation_scale, value, measured)?
+        }
+    }
+    save_calibration(&options.calibration_file, &robot_name(robot), &drive_config)?;
+    robot.set_drive_config(drive_config);
+    Ok(json!({
+        "robot": robot_name(robot),
+        "distance_scale": drive_config.distance_scale,
+        "rotation_scale": drive_config.rotation_scale,
+    }))
+}
+
+fn connect(options: &Options) -> Result<Robot, Box<dyn Error>> {
+    let mut robot = Robot::new()?;
+    robot.init_timeout(options.addr, options.port, Some(options.timeout))?;
+    let mut drive_config = robot.get_drive_config();
+    load_calibration(
+        &options.calibration_file,
+        &robot_name(&robot),
+        &mut drive_config,
+    )?;
+    robot.set_drive_config(drive_config);
+    if let Some(drive_type) = load_drive_type(&options.calibration_file, &robot_name(&robot))? {
+        robot.set_drive_type(drive_type);
+    }
+    robot.set_geofence(load_geofence(&options.geofence_file)?);
+    Ok(robot)
+}
+
+fn execute(robot: &mut Robot, options: &Options) -> Result<serde_json::Value, Box<dyn Error>> {
+    let timeout = options.timeout;
+    match &options.command {
+        Command::Move {
+            direction,
+            duration,
+        } => {
+            let speed = robot.get_speed();
+            let (name, cmd) = match direction {
+                Direction::Forward => (
+                    "forward",
+                    wheel_command(speed, DIR_FORWARD, speed, DIR_FORWARD),
+                ),
+                Direction::Backward => (
+                    "backward",
+                    wheel_command(speed, DIR_BACKWARD, speed, DIR_BACKWARD),
+                ),
+                Direction::Left => ("left", wheel_command(0, DIR_BACKWARD, speed, DIR_FORWARD)),
+                Direction::Right => ("right", wheel_command(speed, DIR_FORWARD, 0, DIR_BACKWARD)),
+            };
+            // the robot stops by itself, so a lost connection does not leave it running
+            let move_id = robot.move_for(cmd, *duration)?;
+            let completed = robot.wait_move_done(move_id, *duration + timeout)?;
+            Ok(json!({
+                "direction": name,
+                "duration_ms": duration.as_millis() as u64,
+                "completed": completed,
+            }))
+        }
+        Command::Steps { left, right, rate } => {
+            Ok(steps_json(&robot.move_steps(*left, *right, *rate)?))
+        }
+        Command::Distance(meters) => Ok(steps_json(&robot.drive_distance(*meters)?)),
+        Command::Turn(degrees) => Ok(steps_json(&robot.turn_angle(*degrees)?)),
+        Command::Calibrate { calibration, value } => calibrate(robot, options, calibration, *value),
+        Command::Mission(path) => run_mission(robot, path),
+        Command::Replay {
+            path,
+            reverse,
+            speed_scale,
+        } => replay_route(robot, path, *reverse, *speed_scale),
+        Command::Script { path, time_limit } => run_script(robot, path, *time_limit),
+        command => {
+            // the client only opens its cameras after the camera list request
+            robot.ask_camera_list()?;
+            let camera_list = robot.wait_camera_list(timeout)?;
+            let check_camera = |camera_id: &u8| -> Result<u8, Box<dyn Error>> {
+                if camera_list.contains(camera_id) {
+                    Ok(*camera_id)
+                } else {
+                    Err(format!("unknown camera {}", camera_id).into())
+                }
+            };
+            match command {
+                Command::Cameras => {
+                    let mut cameras = Vec::new();
+                    for camera_id in &camera_list {
+                        robot.ask_camera_prop(*camera_id)?;
+                        let resolutions = robot.wait_camera_resolutions(*camera_id, timeout)?;
+                        cameras.push(json!({
+                            "id": camera_id,
+                            "resolutions": resolutions_json(&resolutions),
+                        }));
+                    }
+                    Ok(json!({ "cameras": cameras }))
+                }
+                Command::Resolutions(camera_id) => {
+                    let camera_id = check_camera(camera_id)?;
+                    robot.ask_camera_prop(camera_id)?;
+                    let resolutions = robot.wait_camera_resolutions(camera_id, timeout)?;
+                    Ok(json!({
+                        "camera": camera_id,
+                        "resolutions": resolutions_json(&resolutions),
+                    }))
+                }
+                Command::SetProp {
+                    camera_id,
+                    width,
+                    height,
+                    fps,
+                    encode,
+                } => {
+                    let camera_id = check_camera(camera_id)?;
+                    robot.ask_set_camera_prop(camera_id, *width, *height, *fps, *encode)?;
+                    Ok(json!({
+                        "camera": camera_id,
+                        "width": width,
+                        "height": height,
+                        "fps": fps,
+                        "encode": encode,
+                    }))
+                }
+                Command::Snapshot { camera_id, path } => {
+                    let camera_id = check_camera(camera_id)?;
+                    let info = robot.wait_frame_info(camera_id, timeout)?;
+                    let ext = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+                        Some(ext) => format!(".{}", ext),
+                        None => String::from(".png"),
+                    };
+                    let data = robot
+                        .get_encoded_image(camera_id, &ext, &[])?
+                        .ok_or_else(|| format!("no frame from camera {}", camera_id))?;
+                    std::fs::write(path, &data)?;
+                    Ok(json!({
+                        "camera": camera_id,
+                        "file": path,
+                        "width": info.width,
+                        "height": info.height,
+                        "size": data.len(),
+                    }))
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+fn resolutions_json(resolutions: &Vec<(i32, i32)>) -> serde_json::Value {
+    resolutions
+        .iter()
+        .map(|res| json!({ "width": res.0, "height": res.1 }))
+        .collect()
+}
@@ -0,0 +1,50 @@
+extern crate netbot;
+
+use netbot::geofence::{load_geofence, DEFAULT_GEOFENCE_FILE};
+use netbot::http_api::HttpApi;
+use netbot::robot::{Robot, DEFAULT_ACCELERATION};
+use std::env;
+use std::error::Error;
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const CAMERA_INFO_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut addr = Ipv4Addr::new(192, 168, 88, 184);
+    let mut port = 2345;
+    let mut http_addr = String::from("127.0.0.1:8080");
+    let args: Vec<String> = env::args().collect();
+    if args.len() >= 2 {
+        addr = args[1].parse::<Ipv4Addr>().unwrap();
+    }
+    if args.len() >= 3 {
+        port = args[2].parse::<u16>().unwrap();
+    }
+    if args.len() >= 4 {
+        http_addr = args[3].clone();
+    }
+
+    let mut robot = Robot::new()?;
+    robot.init(addr, port)?;
+    eprintln!("Getting camera list...");
+    robot.ask_camera_list()?;
+    let camera_list = robot.wait_camera_list(CAMERA_INFO_TIMEOUT)?;
+    eprintln!("Getting cameras resolutions...");
+    for camera_id in &camera_list {
+        robot.ask_camera_prop(*camera_id)?;
+        robot.wait_camera_resolutions(*camera_id, CAMERA_INFO_TIMEOUT)?;
+    }
+
+    // the API update thread sends the intermediate speeds
+    robot.set_acceleration(Some(DEFAULT_ACCELERATION));
+    robot.set_geofence(load_geofence(DEFAULT_GEOFENCE_FILE)?);
+    let robot = Arc::new(Mutex::new(robot));
+    let api = HttpApi::new(Arc::clone(&robot));
+    let result = api.serve(&http_addr);
+    let mut robot = robot.lock().unwrap();
+    robot.stop_moving();
+    robot.stop()?;
+    result
+}
@@ -0,0 +1,418 @@
+extern crate crossterm;
+extern crate netbot;
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::{cursor, execute, queue, terminal};
+use netbot::geofence::{load_geofence, DEFAULT_GEOFENCE_FILE};
+use netbot::odometry::DEFAULT_POSE_LOG_FILE;
+use netbot::robot::{Robot, DEFAULT_ACCELERATION};
+use netbot::route::{Route, DEFAULT_ROUTE_FILE};
+use std::env;
+use std::error::Error;
+use std::io::{stdout, Stdout, Write};
+use std::net::Ipv4Addr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Preview size in terminal cells, every cell shows two pixels stacked vertically
+const PREVIEW_COLS: u16 = 64;
+const PREVIEW_ROWS: u16 = 24;
+const FRAME_INTERVAL: Duration = Duration::from_millis(60);
+const ROBOT_UPDATE_INTERVAL: Duration = Duration::from_millis(20);
+// Terminals do not report key releases, so driving stops when key repeat stops. Longer
+// than the usual key repeat delays of 500-660 ms, so the first repeat always comes in time.
+const DRIVE_HOLD_TIMEOUT: Duration = Duration::from_millis(1000);
+
+struct TuiState {
+    camera_index: usize,
+    drive_key: Option<KeyCode>,
+    last_drive_event: Instant,
+    // last result or error shown on the status line
+    message: Option<String>,
+    done: bool,
+}
+
+struct TerminalGuard {}
+
+impl TerminalGuard {
+    fn new(out: &mut Stdout) -> Result<TerminalGuard, Box<dyn Error>> {
+        terminal::enable_raw_mode()?;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(TerminalGuard {})
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut out = stdout();
+        execute!(
+            out,
+            ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        )
+        .expect("Failed to restore terminal");
+        terminal::disable_raw_mode().expect("Failed to disable terminal raw mode");
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut addr = Ipv4Addr::new(192, 168, 88, 184);
+    let mut port = 2345;
+    let args: Vec<String> = env::args().collect();
+    if args.len() >= 2 {
+        addr = args[1].parse::<Ipv4Addr>().unwrap();
+    }
+    if args.len() >= 3 {
+        port = args[2].parse::<u16>().unwrap();
+    }
+
+    let mut robot = Robot::new()?;
+    robot.set_out_resolution(PREVIEW_COLS as i32, (PREVIEW_ROWS * 2) as i32);
+    robot.init(addr, port)?;
+    robot.set_acceleration(Some(DEFAULT_ACCELERATION));
+    // the watchdog waits for the first key repeat as long as the drive hold does
+    robot.set_motion_watchdog(Some(DRIVE_HOLD_TIMEOUT));
+    robot.set_geofence(load_geofence(DEFAULT_GEOFENCE_FILE)?);
+    let camera_list = wait_cameras(&mut robot)?;
+
+    let result = run(&mut robot, &camera_list);
+    // the terminal is restored, the messages go to stderr again
+    robot.collect_notices(false);
+    robot.stop_moving();
+    robot.stop()?;
+    result
+}
+
+fn wait_cameras(robot: &mut Robot) -> Result<Vec<u8>, Box<dyn Error>> {
+    println!("Getting camera list...");
+    robot.ask_camera_list()?;
+    let camera_list = loop {
+        match robot.get_camera_list() {
+            Some(list) => break list,
+            None => thread::sleep(Duration::from_millis(100)),
+        }
+    };
+
+    println!("Getting cameras resolutions...");
+    for camera_id in &camera_list {
+        robot.ask_camera_prop(*camera_id)?;
+    }
+    while camera_list
+        .iter()
+        .any(|camera_id| robot.get_camera_resolutions(*camera_id).is_none())
+    {
+        thread::sleep(Duration::from_millis(100));
+    }
+    Ok(camera_list)
+}
+
+fn run(robot: &mut Robot, camera_list: &Vec<u8>) -> Result<(), Box<dyn Error>> {
+    let mut out = stdout();
+    let _guard = TerminalGuard::new(&mut out)?;
+    // stderr would write over the alternate screen, so the library messages go to the
+    // status line
+    robot.collect_notices(true);
+    let mut state = TuiState {
+        camera_index: 0,
+        drive_key: None,
+        last_drive_event: Instant::now(),
+        message: None,
+        done: false,
+    };
+
+    while !state.done {
+        draw(&mut out, robot, camera_list, &state)?;
+
+        let frame_start = Instant::now();
+        while frame_start.elapsed() < FRAME_INTERVAL {
+            let timeout = FRAME_INTERVAL
+                .checked_sub(frame_start.elapsed())
+                .unwrap_or(Duration::from_millis(0))
+                .min(ROBOT_UPDATE_INTERVAL);
+            robot.update();
+            if let Some(notice) = robot.take_notices().pop() {
+                state.message = Some(notice);
+            }
+            if event::poll(timeout)? {
+                match event::read()? {
+                    Event::Key(key) => process_key(robot, camera_list, &mut state, key)?,
+                    Event::Resize(_, _) => {
+                        execute!(out, terminal::Clear(terminal::ClearType::All))?
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        if state.drive_key.is_some() && state.last_drive_event.elapsed() > DRIVE_HOLD_TIMEOUT {
+            robot.stop_moving();
+            state.drive_key = None;
+        }
+    }
+    Ok(())
+}
+
+fn process_key(
+    robot: &mut Robot,
+    camera_list: &Vec<u8>,
+    state: &mut TuiState,
+    key: KeyEvent,
+) -> Result<(), Box<dyn Error>> {
+    match key.code {
+        KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right => {
+            // manual driving takes over from the replay
+            robot.stop_replay();
+            if state.drive_key != Some(key.code) {
+                robot.stop_moving();
+                match key.code {
+                    KeyCode::Up => robot.move_forward(),
+                    KeyCode::Down => robot.move_backward(),
+                    KeyCode::Left => robot.rotate_left(),
+                    _ => robot.rotate_right(),
+                }
+                state.drive_key = Some(key.code);
+            }
+            state.last_drive_event = Instant::now();
+            // key repeat keeps the motion watchdog from stopping the robot
+            robot.refresh_motion();
+        }
+        KeyCode::Char(' ') => {
+            robot.stop_replay();
+            robot.stop_moving();
+            state.drive_key = None;
+        }
+        KeyCode::Char('x') => {
+            robot.emergency_stop();
+            state.drive_key = None;
+        }
+        KeyCode::Char('X') => robot.reset_emergency_stop(),
+        KeyCode::Char('o') => robot.reset_pose(),
+        KeyCode::Char('l') => {
+            if robot.is_pose_logging() {
+                robot.stop_pose_log();
+            } else if let Err(err) = robot.start_pose_log(DEFAULT_POSE_LOG_FILE) {
+                state.message = Some(format!("Failed to start pose log: {}", err));
+            }
+        }
+        KeyCode::Char('t') => {
+            if robot.is_route_recording() {
+                let result = robot
+                    .stop_route_recording()
+                    .and_then(|route| route.save(DEFAULT_ROUTE_FILE));
+                state.message = Some(match result {
+                    Ok(()) => format!("route saved to {}", DEFAULT_ROUTE_FILE),
+                    Err(err) => format!("Failed to save route: {}", err),
+                });
+            } else {
+                robot.start_route_recording();
+            }
+        }
+        KeyCode::Char('p') | KeyCode::Char('b') => {
+            let reverse = key.code == KeyCode::Char('b');
+            let result = Route::load(DEFAULT_ROUTE_FILE)
+                .and_then(|route| robot.start_replay(route, reverse, 1.0));
+            if let Err(err) = result {
+                state.message = Some(format!("Failed to replay route: {}", err));
+            }
+            state.drive_key = None;
+        }
+        KeyCode::Char('+') | KeyCode::Char('=') => robot.increase_speed(),
+        KeyCode::Char('-') => robot.decrease_speed(),
+        // the camera keys do nothing when the robot has no cameras
+        KeyCode::Tab if !camera_list.is_empty() => {
+            state.camera_index = (state.camera_index + 1) % camera_list.len();
+        }
+        KeyCode::Char('e') if !camera_list.is_empty() => {
+            let camera_id = camera_list[state.camera_index];
+            let encoded = robot
+                .get_frame_info(camera_id)
+                .map_or(false, |info| info.encoded);
+            robot.ask_set_camera_prop(camera_id, 0, 0, 0, !encoded)?;
+        }
+        KeyCode::Char('r') if !camera_list.is_empty() => {
+            let camera_id = camera_list[state.camera_index];
+            let resolutions = robot.get_camera_resolutions(camera_id).unwrap_or_default();
+            if !resolutions.is_empty() {
+                let info = robot.get_frame_info(camera_id);
+                let current = info.and_then(|info| {
+                    resolutions
+                        .iter()
+                        .position(|res| res.0 == info.width as i32 && res.1 == info.height as i32)
+                });
+                let next = current.map_or(0, |i| (i + 1) % resolutions.len());
+                let encoded = info.map_or(false, |info| info.encoded);
+                robot.ask_set_camera_prop(
+                    camera_id,
+                    resolutions[next].0 as u16,
+                    resolutions[next].1 as u16,
+                    0,
+                    encoded,
+                )?;
+            }
+        }
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => state.done = true,
+        KeyCode::Char('q') | KeyCode::Esc => state.done = true,
+        _ => (),
+    }
+    Ok(())
+}
+
+fn draw(
+    out: &mut Stdout,
+    robot: &Robot,
+    camera_list: &Vec<u8>,
+    state: &TuiState,
+) -> Result<(), Box<dyn Error>> {
+    let (term_cols, term_rows) = terminal::size()?;
+    let mut row: u16 = 0;
+
+    let status = if robot.is_connected() {
+        match robot.get_peer_addr() {
+            Some(peer) => format!("connected to {}", peer),
+            None => String::from("connected"),
+        }
+    } else {
+        String::from("disconnected")
+    };
+    let moving = match state.drive_key {
+        _ if robot.is_emergency_stopped() => "E-STOP ENGAGED (X to reset)",
+        _ if robot.is_replaying() => "replaying route",
+        Some(KeyCode::Up) => "forward",
+        Some(KeyCode::Down) => "backward",
+        Some(KeyCode::Left) => "rotating left",
+        Some(KeyCode::Right) => "rotating right",
+        _ => "stopped",
+    };
+    draw_line(
+        out,
+        row,
+        term_cols,
+        &format!(
+            "netbot | {} | {} | speed {}",
+            status,
+            moving,
+            robot.get_speed()
+        ),
+    )?;
+    row += 1;
+    let pose = robot.pose();
+    draw_line(
+        out,
+        row,
+        term_cols,
+        &format!(
+            "pose x {:.2} m  y {:.2} m  heading {:.0} deg{}",
+            pose.x,
+            pose.y,
+            pose.heading.to_degrees(),
+            match (robot.is_pose_logging(), robot.is_route_recording()) {
+                (true, true) => "  | logging | recording route",
+                (true, false) => "  | logging",
+                (false, true) => "  | recording route",
+                (false, false) => "",
+            }
+        ),
+    )?;
+    row += 1;
+    draw_line(
+        out,
+        row,
+        term_cols,
+        state
+            .message
+            .as_ref()
+            .map_or("", |message| message.as_str()),
+    )?;
+    row += 2;
+
+    if camera_list.is_empty() {
+        draw_line(out, row, term_cols, "no cameras")?;
+        row += 1;
+    }
+    for (i, camera_id) in camera_list.iter().enumerate() {
+        let marker = if i == state.camera_index { ">" } else { " " };
+        let info = match robot.get_frame_info(*camera_id) {
+            Some(info) => format!(
+                "{} x {} {}",
+                info.width,
+                info.height,
+                if info.encoded { "encoded" } else { "raw" }
+            ),
+            None => String::from("no frames yet"),
+        };
+        draw_line(
+            out,
+            row,
+            term_cols,
+            &format!("{} camera {}: {}", marker, camera_id, info),
+        )?;
+        row += 1;
+    }
+    row += 1;
+    draw_line(
+        out,
+        row,
+        term_cols,
+        "arrows: drive  space: stop  x: e-stop  +/-: speed  o: reset pose  l: pose log  t: record route  p/b: replay/return  tab: camera  r: resolution  e: encoding  q: quit",
+    )?;
+    row += 2;
+
+    if let Some(camera_id) = camera_list.get(state.camera_index) {
+        if let Some(data) = robot.get_image(*camera_id) {
+            draw_preview(out, row, term_cols, term_rows, &data)?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn draw_line(out: &mut Stdout, row: u16, term_cols: u16, text: &str) -> Result<(), Box<dyn Error>> {
+    let line: String = text.chars().take(term_cols as usize).collect();
+    queue!(
+        out,
+        cursor::MoveTo(0, row),
+        terminal::Clear(terminal::ClearType::CurrentLine),
+        Print(line)
+    )?;
+    Ok(())
+}
+
+fn draw_preview(
+    out: &mut Stdout,
+    top_row: u16,
+    term_cols: u16,
+    term_rows: u16,
+    data: &Vec<u8>,
+) -> Result<(), Box<dyn Error>> {
+    let width = PREVIEW_COLS as usize;
+    let height = (PREVIEW_ROWS * 2) as usize;
+    if data.len() != width * height * 3 {
+        // frame was scaled before the preview resolution was applied
+        return Ok(());
+    }
+    let pixel = |x: usize, y: usize| {
+        let offset = (y * width + x) * 3;
+        Color::Rgb {
+            r: data[offset],
+            g: data[offset + 1],
+            b: data[offset + 2],
+        }
+    };
+    let cols = std::cmp::min(PREVIEW_COLS, term_cols) as usize;
+    let rows = std::cmp::min(PREVIEW_ROWS, term_rows.saturating_sub(top_row)) as usize;
+    for cell_y in 0..rows {
+        queue!(out, cursor::MoveTo(0, top_row + cell_y as u16))?;
+        for x in 0..cols {
+            queue!(
+                out,
+                SetForegroundColor(pixel(x, cell_y * 2)),
+                SetBackgroundColor(pixel(x, cell_y * 2 + 1)),
+                Print('\u{2580}')
+            )?;
+        }
+        queue!(out, ResetColor)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,145 @@
+// Correction factors for distance and angle moves and the wheels trim are measured
+// per robot and kept in a JSON file keyed by the robot name, usually its address:
+// { "192.168.88.184": { "distance_scale": 1.02, "rotation_scale": 0.97,
+//                       "left_trim": 0.96, "right_trim": 1.0 } }
+// The optional "drive" value overrides the drive type reported by the robot.
+use super::kinematics;
+use super::profile;
+use super::robot;
+use kinematics::DriveConfig;
+use profile::DriveType;
+use robot::Robot;
+use serde_json::{json, Map, Value};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+
+pub const DEFAULT_CALIBRATION_FILE: &str = "netbot-calibration.json";
+
+#[derive(Debug)]
+pub enum CalibrationErrors {
+    WrongFormat,
+    WrongMeasurement,
+}
+impl fmt::Display for CalibrationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error: {:?}", self)
+    }
+}
+impl Error for CalibrationErrors {}
+
+// Calibration key of the connected robot
+pub fn robot_name(robot: &Robot) -> String {
+    robot
+        .get_peer_addr()
+        .map_or(String::from("unknown"), |peer| peer.ip().to_string())
+}
+
+fn read_file(path: &str) -> Result<Map<String, Value>, Box<dyn Error>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Map::new()),
+        Err(err) => return Err(Box::new(err)),
+    };
+    match serde_json::from_str(&text)? {
+        Value::Object(robots) => Ok(robots),
+        _ => Err(Box::new(CalibrationErrors::WrongFormat)),
+    }
+}
+
+// Sets the robot correction factors in the config, keeps them unchanged if the robot
+// was not calibrated yet. Returns true when the calibration was found.
+pub fn load_calibration(
+    path: &str,
+    robot_name: &str,
+    config: &mut DriveConfig,
+) -> Result<bool, Box<dyn Error>> {
+    let robots = read_file(path)?;
+    let robot = match robots.get(robot_name) {
+        Some(robot) => robot,
+        None => return Ok(false),
+    };
+    // factors which were not calibrated yet keep the config values
+    let factor = |name: &str, value: f64, max: f64| -> Result<f64, Box<dyn Error>> {
+        match robot.get(name) {
+            Some(factor) => factor
+                .as_f64()
+                .filter(|factor| *factor > 0.0 && *factor <= max)
+                .ok_or_else(|| Box::new(CalibrationErrors::WrongFormat) as Box<dyn Error>),
+            None => Ok(value),
+        }
+    };
+    config.distance_scale = factor("distance_scale", config.distance_scale, f64::INFINITY)?;
+    config.rotation_scale = factor("rotation_scale", config.rotation_scale, f64::INFINITY)?;
+    // the trims only slow a wheel down, so the trimmed speeds stay within the move speed
+    config.left_trim = factor("left_trim", config.left_trim, 1.0)?;
+    config.right_trim = factor("right_trim", config.right_trim, 1.0)?;
+    Ok(true)
+}
+
+// Drive type set for the robot in the file
+pub fn load_drive_type(path: &str, robot_name: &str) -> Result<Option<DriveType>, Box<dyn Error>> {
+    let robots = read_file(path)?;
+    match robots.get(robot_name).and_then(|robot| robot.get("drive")) {
+        Some(drive) => drive
+            .as_str()
+            .and_then(DriveType::from_name)
+            .map(Some)
+            .ok_or_else(|| Box::new(CalibrationErrors::WrongFormat) as Box<dyn Error>),
+        None => Ok(None),
+    }
+}
+
+// Stores the config correction factors, other robots and values in the file are kept
+pub fn save_calibration(
+    path: &str,
+    robot_name: &str,
+    config: &DriveConfig,
+) -> Result<(), Box<dyn Error>> {
+    let mut robots = read_file(path)?;
+    let robot = robots
+        .entry(String::from(robot_name))
+        .or_insert_with(|| json!({}));
+    if !robot.is_object() {
+        return Err(Box::new(CalibrationErrors::WrongFormat));
+    }
+    robot["distance_scale"] = json!(config.distance_scale);
+    robot["rotation_scale"] = json!(config.rotation_scale);
+    robot["left_trim"] = json!(config.left_trim);
+    robot["right_trim"] = json!(config.right_trim);
+    fs::write(path, serde_json::to_string_pretty(&Value::Object(robots))?)?;
+    Ok(())
+}
+
+// New correction factor after the robot moved by measured instead of commanded
+pub fn corrected_scale(scale: f64, commanded: f64, measured: f64) -> Result<f64, Box<dyn Error>> {
+    if measured == 0.0 || commanded == 0.0 || measured.signum() != commanded.signum() {
+        return Err(Box::new(CalibrationErrors::WrongMeasurement));
+    }
+    Ok(scale * commanded / measured)
+}
+
+// New wheels trim after driving straight by distance with the lateral drift at the end,
+// both in meters, drift is positive to the right. The robot moved along an arc,
+// so the wheels speeds differ by the arc curvature times the track width.
+pub fn corrected_trim(
+    config: &DriveConfig,
+    distance: f64,
+    drift: f64,
+) -> Result<(f64, f64), Box<dyn Error>> {
+    if distance <= 0.0 || drift.abs() >= distance {
+        return Err(Box::new(CalibrationErrors::WrongMeasurement));
+    }
+    // curvature of the arc through the end point
+    let curvature = 2.0 * drift / (distance * distance + drift * drift);
+    let speed_diff = curvature * config.track_width;
+    let left_trim = config.left_trim * (1.0 - speed_diff / 2.0);
+    let right_trim = config.right_trim * (1.0 + speed_diff / 2.0);
+    // the faster wheel is slowed down, so trimmed speeds never exceed the command
+    let max_trim = left_trim.max(right_trim);
+    if max_trim <= 0.0 || left_trim <= 0.0 || right_trim <= 0.0 {
+        return Err(Box::new(CalibrationErrors::WrongMeasurement));
+    }
+    Ok((left_trim / max_trim, right_trim / max_trim))
+}
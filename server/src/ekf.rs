@@ -0,0 +1,406 @@
+// Extended Kalman filter fusing the wheel encoders and the IMU into the robot pose.
+// The state is x, y, heading, linear and angular velocity. Encoders measure both
+// velocities, the gyro measures the angular one, and the forward acceleration drives
+// the linear velocity between the measurements. Poses are in the odometry frame.
+use super::kinematics;
+use super::odometry;
+use kinematics::DriveConfig;
+use odometry::{normalize_angle, Pose};
+
+const STATE_SIZE: usize = 5;
+const X: usize = 0;
+const Y: usize = 1;
+const HEADING: usize = 2;
+const LINEAR: usize = 3;
+const ANGULAR: usize = 4;
+// longer gaps in the telemetry are not predicted over, the motion is unknown
+const MAX_PREDICT_INTERVAL: f64 = 1.0;
+
+type Vector = [f64; STATE_SIZE];
+type Matrix = [[f64; STATE_SIZE]; STATE_SIZE];
+
+// Standard deviations of the noise
+#[derive(Debug, Clone, Copy)]
+pub struct FilterConfig {
+    // unmodelled linear acceleration, m/s^2
+    pub linear_accel_noise: f64,
+    // unmodelled angular acceleration, rad/s^2
+    pub angular_accel_noise: f64,
+    // linear velocity measured by the encoders, m/s
+    pub encoder_noise: f64,
+    // angular velocity measured by the gyro, rad/s
+    pub gyro_noise: f64,
+    // the accelerometer is not used when it is mounted tilted or too noisy
+    pub use_accelerometer: bool,
+}
+
+impl Default for FilterConfig {
+    fn default() -> FilterConfig {
+        FilterConfig {
+            linear_accel_noise: 0.5,
+            angular_accel_noise: 2.0,
+            encoder_noise: 0.01,
+            gyro_noise: 0.02,
+            use_accelerometer: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoseEstimate {
+    pub pose: Pose,
+    // m/s and rad/s
+    pub linear: f64,
+    pub angular: f64,
+    // x, y and heading covariance
+    pub covariance: [[f64; 3]; 3],
+}
+
+fn identity() -> Matrix {
+    let mut m = [[0.0; STATE_SIZE]; STATE_SIZE];
+    for (i, row) in m.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    m
+}
+
+// The pose starts at the origin, the velocities are not known
+fn initial_covariance() -> Matrix {
+    let mut m = [[0.0; STATE_SIZE]; STATE_SIZE];
+    m[LINEAR][LINEAR] = 1.0;
+    m[ANGULAR][ANGULAR] = 1.0;
+    m
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; STATE_SIZE]; STATE_SIZE];
+    for i in 0..STATE_SIZE {
+        for j in 0..STATE_SIZE {
+            m[i][j] = (0..STATE_SIZE).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+fn transpose(a: &Matrix) -> Matrix {
+    let mut m = [[0.0; STATE_SIZE]; STATE_SIZE];
+    for i in 0..STATE_SIZE {
+        for j in 0..STATE_SIZE {
+            m[i][j] = a[j][i];
+        }
+    }
+    m
+}
+
+pub struct PoseFilter {
+    config: FilterConfig,
+    drive_config: DriveConfig,
+    state: Vector,
+    covariance: Matrix,
+    // robot clock of the state
+    time_ms: Option<u32>,
+    last_ticks: Option<(u32, i32, i32)>,
+    linear_accel: f64,
+}
+
+impl PoseFilter {
+    pub fn new(drive_config: DriveConfig, config: FilterConfig) -> PoseFilter {
+        PoseFilter {
+            config: config,
+            drive_config: drive_config,
+            state: [0.0; STATE_SIZE],
+            covariance: initial_covariance(),
+            time_ms: None,
+            last_ticks: None,
+            linear_accel: 0.0,
+        }
+    }
+
+    pub fn set_drive_config(&mut self, drive_config: DriveConfig) {
+        self.drive_config = drive_config;
+    }
+
+    pub fn set_config(&mut self, config: FilterConfig) {
+        self.config = config;
+    }
+
+    pub fn get_config(&self) -> FilterConfig {
+        self.config
+    }
+
+    // True after the first telemetry message
+    pub fn is_active(&self) -> bool {
+        self.time_ms.is_some()
+    }
+
+    // Moves the origin to the current pose, the pose becomes certain
+    pub fn reset(&mut self) {
+        for i in &[X, Y, HEADING] {
+            self.state[*i] = 0.0;
+            for j in 0..STATE_SIZE {
+                self.covariance[*i][j] = 0.0;
+                self.covariance[j][*i] = 0.0;
+            }
+        }
+    }
+
+    // Forgets the telemetry, used when a new robot connects
+    pub fn clear(&mut self) {
+        self.state = [0.0; STATE_SIZE];
+        self.covariance = initial_covariance();
+        self.time_ms = None;
+        self.last_ticks = None;
+        self.linear_accel = 0.0;
+    }
+
+    // Seconds from the state time, None for readings older than the state
+    fn elapsed(&self, time_ms: u32) -> Option<f64> {
+        match self.time_ms {
+            Some(state_ms) => {
+                // the robot clock wraps around after 49 days
+                let elapsed = time_ms.wrapping_sub(state_ms) as i32;
+                if elapsed >= 0 {
+                    Some(elapsed as f64 / 1000.0)
+                } else {
+                    None
+                }
+            }
+            None => Some(0.0),
+        }
+    }
+
+    // Moves the state to the time with the constant velocity model
+    pub fn predict(&mut self, time_ms: u32) {
+        let dt = match self.elapsed(time_ms) {
+            Some(dt) => dt,
+            None => return,
+        };
+        self.time_ms = Some(time_ms);
+        if dt == 0.0 {
+            return;
+        }
+        if dt > MAX_PREDICT_INTERVAL {
+            // the velocities are unknown after the gap
+            self.covariance[LINEAR][LINEAR] += self.config.linear_accel_noise.powi(2);
+            self.covariance[ANGULAR][ANGULAR] += self.config.angular_accel_noise.powi(2);
+            return;
+        }
+
+        let (sin, cos) = self.state[HEADING].sin_cos();
+        let linear = self.state[LINEAR];
+        let accel = if self.config.use_accelerometer {
+            self.linear_accel
+        } else {
+            0.0
+        };
+        self.state[X] += linear * cos * dt;
+        self.state[Y] += linear * sin * dt;
+        self.state[HEADING] = normalize_angle(self.state[HEADING] + self.state[ANGULAR] * dt);
+        self.state[LINEAR] += accel * dt;
+
+        let mut jacobian = identity();
+        jacobian[X][HEADING] = -linear * sin * dt;
+        jacobian[X][LINEAR] = cos * dt;
+        jacobian[Y][HEADING] = linear * cos * dt;
+        jacobian[Y][LINEAR] = sin * dt;
+        jacobian[HEADING][ANGULAR] = dt;
+        let mut covariance = multiply(
+            &multiply(&jacobian, &self.covariance),
+            &transpose(&jacobian),
+        );
+        covariance[LINEAR][LINEAR] += (self.config.linear_accel_noise * dt).powi(2);
+        covariance[ANGULAR][ANGULAR] += (self.config.angular_accel_noise * dt).powi(2);
+        self.covariance = covariance;
+    }
+
+    // Kalman update with a direct measurement of one state value
+    fn update_value(&mut self, index: usize, value: f64, variance: f64) {
+        let innovation = value - self.state[index];
+        let innovation_variance = self.covariance[index][index] + variance;
+        if innovation_variance <= 0.0 {
+            return;
+        }
+        let gain: Vector = {
+            let mut gain = [0.0; STATE_SIZE];
+            for (i, k) in gain.iter_mut().enumerate() {
+                *k = self.covariance[i][index] / innovation_variance;
+            }
+            gain
+        };
+        for (i, k) in gain.iter().enumerate() {
+            self.state[i] += k * innovation;
+        }
+        self.state[HEADING] = normalize_angle(self.state[HEADING]);
+        let row = self.covariance[index];
+        for (i, k) in gain.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                self.covariance[i][j] -= k * value;
+            }
+        }
+    }
+
+    // Encoder counts in half-steps, the velocities are measured between two readings
+    pub fn update_encoders(&mut self, time_ms: u32, left_ticks: i32, right_ticks: i32) {
+        // readings older than the state came out of order and are dropped
+        if self.elapsed(time_ms).is_none() {
+            return;
+        }
+        let last_ticks = self.last_ticks.replace((time_ms, left_ticks, right_ticks));
+        self.predict(time_ms);
+        let (last_ms, last_left, last_right) = match last_ticks {
+            Some(last_ticks) => last_ticks,
+            None => return,
+        };
+        let dt = time_ms.wrapping_sub(last_ms) as i32 as f64 / 1000.0;
+        if dt <= 0.0 || dt > MAX_PREDICT_INTERVAL {
+            return;
+        }
+        let (distance, rotation) = self.drive_config.steps_displacement(
+            left_ticks.wrapping_sub(last_left),
+            right_ticks.wrapping_sub(last_right),
+        );
+        let linear_variance = self.config.encoder_noise.powi(2);
+        // the wheels speed difference over the track width
+        let angular_variance = 2.0 * linear_variance / self.drive_config.track_width.powi(2);
+        self.update_value(LINEAR, distance / dt, linear_variance);
+        self.update_value(ANGULAR, rotation / dt, angular_variance);
+    }
+
+    // Yaw rate in rad/s and accelerations in m/s^2
+    pub fn update_imu(&mut self, time_ms: u32, gyro_z: f64, accel_x: f64, _accel_y: f64) {
+        if self.elapsed(time_ms).is_none() {
+            return;
+        }
+        self.predict(time_ms);
+        self.linear_accel = accel_x;
+        self.update_value(ANGULAR, gyro_z, self.config.gyro_noise.powi(2));
+    }
+
+    pub fn estimate(&self) -> PoseEstimate {
+        let mut covariance = [[0.0; 3]; 3];
+        for (i, row) in covariance.iter_mut().enumerate() {
+            row.copy_from_slice(&self.covariance[i][..3]);
+        }
+        PoseEstimate {
+            pose: Pose {
+                x: self.state[X],
+                y: self.state[Y],
+                heading: self.state[HEADING],
+            },
+            linear: self.state[LINEAR],
+            angular: self.state[ANGULAR],
+            covariance: covariance,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::assert_near;
+    use super::*;
+    use std::f64::consts::PI;
+
+    const STEP_MS: u32 = 50;
+
+    fn filter() -> PoseFilter {
+        PoseFilter::new(DriveConfig::default(), FilterConfig::default())
+    }
+
+    // Encoder and gyro readings of a motion with constant velocities for the time
+    fn drive(filter: &mut PoseFilter, linear: f64, angular: f64, duration_ms: u32) {
+        let config = DriveConfig::default();
+        for time_ms in (0..=duration_ms).step_by(STEP_MS as usize) {
+            let seconds = time_ms as f64 / 1000.0;
+            let distance_steps = config.distance_steps(linear * seconds);
+            let (turn_left, turn_right) = config.turn_steps((angular * seconds).to_degrees());
+            filter.update_encoders(
+                time_ms,
+                distance_steps + turn_left,
+                distance_steps + turn_right,
+            );
+            filter.update_imu(time_ms, angular, 0.0, 0.0);
+        }
+    }
+
+    // Straight encoder reading with the total distance
+    fn drive_step(filter: &mut PoseFilter, time_ms: u32, meters: f64) {
+        let steps = DriveConfig::default().distance_steps(meters);
+        filter.update_encoders(time_ms, steps, steps);
+    }
+
+    #[test]
+    fn straight_line() {
+        let mut filter = filter();
+        drive(&mut filter, 0.1, 0.0, 2000);
+        let estimate = filter.estimate();
+        // the first interval is driven before the velocity is measured
+        assert_near(estimate.pose.x, 0.2, 0.01);
+        assert_near(estimate.pose.y, 0.0, 0.001);
+        assert_near(estimate.pose.heading, 0.0, 0.01);
+        assert_near(estimate.linear, 0.1, 0.005);
+    }
+
+    #[test]
+    fn constant_turn() {
+        let mut filter = filter();
+        drive(&mut filter, 0.1, 0.5, 2000);
+        let estimate = filter.estimate();
+        assert_near(estimate.pose.heading, 1.0, 0.05);
+        assert_near(estimate.angular, 0.5, 0.02);
+        // an arc with the radius of linear / angular
+        assert_near(estimate.pose.x, 0.2 * 1.0f64.sin(), 0.02);
+        assert_near(estimate.pose.y, 0.2 * (1.0 - 1.0f64.cos()), 0.02);
+    }
+
+    #[test]
+    fn heading_wraps_around() {
+        let mut filter = filter();
+        drive(&mut filter, 0.0, 0.5, 7000);
+        let heading = filter.estimate().pose.heading;
+        assert!(heading > -PI && heading <= PI);
+        assert_near(normalize_angle(heading - 3.5), 0.0, 0.05);
+        assert!(heading < 0.0);
+    }
+
+    #[test]
+    fn out_of_order_readings_are_ignored() {
+        let mut filter = filter();
+        drive(&mut filter, 0.1, 0.0, 1000);
+        let before = filter.estimate();
+        filter.update_imu(900, 3.0, 5.0, 0.0);
+        filter.update_encoders(900, 10_000, -10_000);
+        filter.predict(900);
+        assert_eq!(filter.estimate(), before);
+        // the next reading uses the ticks before the stale one
+        drive_step(&mut filter, 1050, 0.1 * 1.05);
+        assert_near(filter.estimate().linear, 0.1, 0.005);
+    }
+
+    #[test]
+    fn covariance_grows_on_predict_and_shrinks_on_update() {
+        let mut filter = filter();
+        drive(&mut filter, 0.1, 0.0, 500);
+        let before = filter.covariance;
+        filter.predict(1000);
+        let predicted = filter.covariance;
+        assert!(predicted[X][X] > before[X][X]);
+        assert!(predicted[LINEAR][LINEAR] > before[LINEAR][LINEAR]);
+        assert!(predicted[ANGULAR][ANGULAR] > before[ANGULAR][ANGULAR]);
+        filter.update_imu(1000, 0.0, 0.0, 0.0);
+        assert!(filter.covariance[ANGULAR][ANGULAR] < predicted[ANGULAR][ANGULAR]);
+        drive_step(&mut filter, 1050, 0.105);
+        drive_step(&mut filter, 1100, 0.11);
+        assert!(filter.covariance[LINEAR][LINEAR] < predicted[LINEAR][LINEAR]);
+    }
+
+    #[test]
+    fn reset_moves_the_origin() {
+        let mut filter = filter();
+        drive(&mut filter, 0.1, 0.5, 1000);
+        filter.reset();
+        let estimate = filter.estimate();
+        assert_eq!(estimate.pose, Pose::default());
+        assert_eq!(estimate.covariance, [[0.0; 3]; 3]);
+        assert_near(estimate.linear, 0.1, 0.01);
+    }
+}
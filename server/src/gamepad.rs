@@ -0,0 +1,478 @@
+// Gamepad driving through the Linux joystick interface (/dev/input/jsN).
+// Every event from the device is 8 bytes: u32 timestamp in ms, i16 value,
+// u8 event type and u8 axis or button number.
+use super::kinematics;
+use kinematics::{WheelCommand, DIR_BACKWARD, DIR_FORWARD};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+pub const JS_EVENT_BUTTON: u8 = 0x01;
+pub const JS_EVENT_AXIS: u8 = 0x02;
+// set for the synthetic events describing the initial device state
+pub const JS_EVENT_INIT: u8 = 0x80;
+const JS_EVENT_SIZE: usize = 8;
+const AXIS_MAX: f64 = 32767.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoystickEvent {
+    Button {
+        number: u8,
+        pressed: bool,
+        init: bool,
+    },
+    Axis {
+        number: u8,
+        value: i16,
+        init: bool,
+    },
+}
+
+impl JoystickEvent {
+    pub fn from_bytes(buf: &[u8; JS_EVENT_SIZE]) -> Option<JoystickEvent> {
+        let value = i16::from_ne_bytes([buf[4], buf[5]]);
+        let init = buf[6] & JS_EVENT_INIT != 0;
+        let number = buf[7];
+        match buf[6] & !JS_EVENT_INIT {
+            JS_EVENT_BUTTON => Some(JoystickEvent::Button {
+                number: number,
+                pressed: value != 0,
+                init: init,
+            }),
+            JS_EVENT_AXIS => Some(JoystickEvent::Axis {
+                number: number,
+                value: value,
+                init: init,
+            }),
+            _ => None,
+        }
+    }
+
+    // Device representation of the event, used to feed virtual devices
+    pub fn to_bytes(&self, time_ms: u32) -> [u8; JS_EVENT_SIZE] {
+        let (event_type, number, value, init) = match *self {
+            JoystickEvent::Button {
+                number,
+                pressed,
+                init,
+            } => (JS_EVENT_BUTTON, number, pressed as i16, init),
+            JoystickEvent::Axis {
+                number,
+                value,
+                init,
+            } => (JS_EVENT_AXIS, number, value, init),
+        };
+        let mut buf = [0; JS_EVENT_SIZE];
+        buf[0..4].copy_from_slice(&time_ms.to_ne_bytes());
+        buf[4..6].copy_from_slice(&value.to_ne_bytes());
+        buf[6] = if init {
+            event_type | JS_EVENT_INIT
+        } else {
+            event_type
+        };
+        buf[7] = number;
+        buf
+    }
+}
+
+// Reads the next known event, returns None at the end of the stream
+pub fn read_event<R: Read>(reader: &mut R) -> io::Result<Option<JoystickEvent>> {
+    let mut buf = [0; JS_EVENT_SIZE];
+    loop {
+        match reader.read_exact(&mut buf) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        if let Some(event) = JoystickEvent::from_bytes(&buf) {
+            return Ok(Some(event));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamepadAction {
+    Stop,
+    SpeedPreset(u8),
+    Snapshot,
+}
+
+#[derive(Debug, Clone)]
+pub struct GamepadConfig {
+    pub throttle_axis: u8,
+    pub steer_axis: u8,
+    // joystick axes are negative when pushed up or left
+    pub invert_throttle: bool,
+    pub invert_steer: bool,
+    // part of the axis range around the center treated as zero, 0..1
+    pub deadzone: f64,
+    // 0 is linear response, 1 is fully cubic for fine control around the center
+    pub expo: f64,
+    // the robot moves only while this button is held
+    pub deadman_button: Option<u8>,
+    pub stop_button: Option<u8>,
+    pub snapshot_button: Option<u8>,
+    // (button, speed) pairs
+    pub speed_preset_buttons: Vec<(u8, u8)>,
+}
+
+impl Default for GamepadConfig {
+    fn default() -> GamepadConfig {
+        // left stick and buttons of Xbox-like pads in the xpad driver layout
+        GamepadConfig {
+            throttle_axis: 1,
+            steer_axis: 0,
+            invert_throttle: true,
+            invert_steer: true,
+            deadzone: 0.1,
+            expo: 0.3,
+            deadman_button: Some(5),
+            stop_button: Some(1),
+            snapshot_button: Some(3),
+            speed_preset_buttons: vec![(0, 10), (2, 50)],
+        }
+    }
+}
+
+// Keeps the pad state and maps it to wheel commands and actions
+pub struct GamepadMapper {
+    config: GamepadConfig,
+    axes: HashMap<u8, i16>,
+    buttons: HashMap<u8, bool>,
+}
+
+impl GamepadMapper {
+    pub fn new(config: GamepadConfig) -> GamepadMapper {
+        GamepadMapper {
+            config: config,
+            axes: HashMap::new(),
+            buttons: HashMap::new(),
+        }
+    }
+
+    pub fn process_event(&mut self, event: JoystickEvent) -> Option<GamepadAction> {
+        match event {
+            JoystickEvent::Axis { number, value, .. } => {
+                self.axes.insert(number, value);
+                None
+            }
+            JoystickEvent::Button {
+                number,
+                pressed,
+                init,
+            } => {
+                self.buttons.insert(number, pressed);
+                if !pressed || init {
+                    return None;
+                }
+                if self.config.stop_button == Some(number) {
+                    Some(GamepadAction::Stop)
+                } else if self.config.snapshot_button == Some(number) {
+                    Some(GamepadAction::Snapshot)
+                } else {
+                    self.config
+                        .speed_preset_buttons
+                        .iter()
+                        .find(|(button, _)| *button == number)
+                        .map(|(_, speed)| GamepadAction::SpeedPreset(*speed))
+                }
+            }
+        }
+    }
+
+    pub fn is_button_pressed(&self, number: u8) -> bool {
+        *self.buttons.get(&number).unwrap_or(&false)
+    }
+
+    pub fn is_deadman_held(&self) -> bool {
+        match self.config.deadman_button {
+            Some(button) => self.is_button_pressed(button),
+            None => true,
+        }
+    }
+
+    // Axis value in -1..1 after the deadzone and expo curve
+    pub fn axis(&self, number: u8, invert: bool) -> f64 {
+        let raw = *self.axes.get(&number).unwrap_or(&0) as f64 / AXIS_MAX;
+        let value = if invert { -raw } else { raw };
+        let value = value.max(-1.0).min(1.0);
+        let deadzone = self.config.deadzone;
+        if value.abs() <= deadzone {
+            return 0.0;
+        }
+        let value = value.signum() * (value.abs() - deadzone) / (1.0 - deadzone);
+        let expo = self.config.expo;
+        (1.0 - expo) * value + expo * value.powi(3)
+    }
+
+    // Throttle and steering mixed into wheel speeds up to max_speed,
+    // positive steering turns left
+    pub fn command(&self, max_speed: u8) -> WheelCommand {
+        if !self.is_deadman_held() {
+            return WheelCommand::default();
+        }
+        let throttle = self.axis(self.config.throttle_axis, self.config.invert_throttle);
+        let steer = self.axis(self.config.steer_axis, self.config.invert_steer);
+        let mut left = throttle - steer;
+        let mut right = throttle + steer;
+        let fastest = left.abs().max(right.abs());
+        if fastest > 1.0 {
+            left /= fastest;
+            right /= fastest;
+        }
+        let to_speed = |wheel: f64| (wheel.abs() * max_speed as f64).round() as u8;
+        let to_dir = |wheel: f64| {
+            if wheel < 0.0 {
+                DIR_BACKWARD
+            } else {
+                DIR_FORWARD
+            }
+        };
+        WheelCommand {
+            left_speed: to_speed(left),
+            left_dir: to_dir(left),
+            right_speed: to_speed(right),
+            right_dir: to_dir(right),
+        }
+    }
+}
+
+pub struct Gamepad {
+    mapper: GamepadMapper,
+    receiver: Receiver<JoystickEvent>,
+    connected_flag: Arc<AtomicBool>,
+}
+
+impl Gamepad {
+    pub fn open(path: &str, config: GamepadConfig) -> Result<Gamepad, Box<dyn Error>> {
+        let device = File::open(path)?;
+        Ok(Gamepad::from_reader(device, config))
+    }
+
+    // Any byte stream in the joystick event format can be used as a device,
+    // for example a pipe or an in-memory buffer for a virtual pad
+    pub fn from_reader<R: Read + Send + 'static>(reader: R, config: GamepadConfig) -> Gamepad {
+        let (sender, receiver) = channel();
+        let connected_flag = Arc::new(AtomicBool::new(true));
+        let connected_flag_clone = Arc::clone(&connected_flag);
+        let mut reader = reader;
+        thread::spawn(move || {
+            loop {
+                match read_event(&mut reader) {
+                    Ok(Some(event)) => {
+                        if sender.send(event).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(err) => {
+                        eprintln!("Failed to read gamepad event: {}", err);
+                        break;
+                    }
+                }
+            }
+            connected_flag_clone.store(false, Ordering::SeqCst);
+        });
+        Gamepad {
+            mapper: GamepadMapper::new(config),
+            receiver: receiver,
+            connected_flag: connected_flag,
+        }
+    }
+
+    // Applies all pending events and returns the actions triggered by them
+    pub fn poll(&mut self) -> Vec<GamepadAction> {
+        let mut actions = Vec::new();
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) => {
+                    if let Some(action) = self.mapper.process_event(event) {
+                        actions.push(action);
+                    }
+                }
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => break,
+            }
+        }
+        actions
+    }
+
+    pub fn command(&self, max_speed: u8) -> WheelCommand {
+        self.mapper.command(max_speed)
+    }
+
+    // False when the device was unplugged, pending events are still returned by poll
+    pub fn is_connected(&self) -> bool {
+        self.connected_flag.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::time::{Duration, Instant};
+
+    fn axis(number: u8, value: i16) -> JoystickEvent {
+        JoystickEvent::Axis {
+            number: number,
+            value: value,
+            init: false,
+        }
+    }
+
+    fn button(number: u8, pressed: bool) -> JoystickEvent {
+        JoystickEvent::Button {
+            number: number,
+            pressed: pressed,
+            init: false,
+        }
+    }
+
+    // Virtual pad fed with the events, returns the pad after all of them were read
+    fn virtual_pad(bytes: Vec<u8>, config: GamepadConfig) -> (Gamepad, Vec<GamepadAction>) {
+        let mut gamepad = Gamepad::from_reader(Cursor::new(bytes), config);
+        let start = Instant::now();
+        while gamepad.is_connected() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(1));
+        }
+        let actions = gamepad.poll();
+        (gamepad, actions)
+    }
+
+    fn events_bytes(events: &[JoystickEvent]) -> Vec<u8> {
+        events
+            .iter()
+            .enumerate()
+            .flat_map(|(i, event)| event.to_bytes(i as u32 * 10).to_vec())
+            .collect()
+    }
+
+    fn pad_with(events: &[JoystickEvent]) -> (Gamepad, Vec<GamepadAction>) {
+        virtual_pad(events_bytes(events), GamepadConfig::default())
+    }
+
+    #[test]
+    fn event_bytes_round_trip() {
+        let event = axis(3, -1234);
+        assert_eq!(JoystickEvent::from_bytes(&event.to_bytes(42)), Some(event));
+    }
+
+    #[test]
+    fn deadzone_keeps_robot_stopped() {
+        // 5% of the range is inside the default 10% deadzone
+        let (gamepad, _) = pad_with(&[button(5, true), axis(1, -1638), axis(0, 1638)]);
+        assert!(gamepad.command(100).is_stop());
+    }
+
+    #[test]
+    fn full_throttle_drives_forward() {
+        let (gamepad, _) = pad_with(&[button(5, true), axis(1, -32767)]);
+        let cmd = gamepad.command(80);
+        assert_eq!(cmd.left_speed, 80);
+        assert_eq!(cmd.right_speed, 80);
+        assert_eq!(cmd.left_dir, DIR_FORWARD);
+        assert_eq!(cmd.right_dir, DIR_FORWARD);
+    }
+
+    #[test]
+    fn expo_softens_the_center() {
+        let config = GamepadConfig {
+            deadzone: 0.0,
+            expo: 0.5,
+            ..GamepadConfig::default()
+        };
+        let bytes = events_bytes(&[button(5, true), axis(1, -16384)]);
+        let (gamepad, _) = virtual_pad(bytes, config);
+        // 0.5 * 0.5 + 0.5 * 0.5^3 of the maximum speed
+        let cmd = gamepad.command(100);
+        assert_eq!(cmd.left_speed, 31);
+        assert_eq!(cmd.right_speed, 31);
+    }
+
+    #[test]
+    fn deadzone_is_removed_from_the_range() {
+        let config = GamepadConfig {
+            deadzone: 0.2,
+            expo: 0.0,
+            ..GamepadConfig::default()
+        };
+        // 60% of the range is half way between the deadzone and the end
+        let bytes = events_bytes(&[button(5, true), axis(1, 19660)]);
+        let (gamepad, _) = virtual_pad(bytes, config);
+        let cmd = gamepad.command(100);
+        assert_eq!(cmd.left_speed, 50);
+        assert_eq!(cmd.left_dir, DIR_BACKWARD);
+        assert_eq!(cmd.right_dir, DIR_BACKWARD);
+    }
+
+    #[test]
+    fn steering_turns_left() {
+        let (gamepad, _) = pad_with(&[button(5, true), axis(0, -32767)]);
+        let cmd = gamepad.command(100);
+        assert_eq!(cmd.left_dir, DIR_BACKWARD);
+        assert_eq!(cmd.right_dir, DIR_FORWARD);
+        assert_eq!(cmd.left_speed, cmd.right_speed);
+    }
+
+    #[test]
+    fn deadman_release_stops() {
+        let (gamepad, _) = pad_with(&[axis(1, -32767)]);
+        assert_eq!(gamepad.command(100), WheelCommand::default());
+        let (gamepad, _) = pad_with(&[button(5, true), axis(1, -32767), button(5, false)]);
+        assert_eq!(gamepad.command(100), WheelCommand::default());
+    }
+
+    #[test]
+    fn buttons_map_to_actions() {
+        let (_, actions) = pad_with(&[
+            button(1, true),
+            button(1, false),
+            button(0, true),
+            button(2, true),
+            button(3, true),
+            button(7, true),
+        ]);
+        assert_eq!(
+            actions,
+            vec![
+                GamepadAction::Stop,
+                GamepadAction::SpeedPreset(10),
+                GamepadAction::SpeedPreset(50),
+                GamepadAction::Snapshot,
+            ]
+        );
+    }
+
+    #[test]
+    fn init_events_trigger_no_actions() {
+        let (gamepad, actions) = pad_with(&[
+            JoystickEvent::Button {
+                number: 1,
+                pressed: true,
+                init: true,
+            },
+            JoystickEvent::Button {
+                number: 3,
+                pressed: true,
+                init: true,
+            },
+        ]);
+        assert!(actions.is_empty());
+        // the initial state is still known
+        assert!(gamepad.mapper.is_button_pressed(1));
+    }
+
+    #[test]
+    fn unknown_event_types_are_skipped() {
+        let mut bytes = vec![0, 0, 0, 0, 1, 0, 0x04, 1];
+        bytes.extend(events_bytes(&[button(1, true)]));
+        let (_, actions) = virtual_pad(bytes, GamepadConfig::default());
+        assert_eq!(actions, vec![GamepadAction::Stop]);
+    }
+}
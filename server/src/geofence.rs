@@ -0,0 +1,218 @@
+// Operating area limits for demos in crowded rooms. The area is a circle around the
+// pose origin at the time the fence is set, or a polygon in that pose frame, and it
+// stays in place when the pose is reset later. Motion which would take the robot out of
+// the area soon is stopped, motion back inside is allowed when the robot drifted out.
+// The config is a JSON file:
+// { "radius": 1.5, "max_speed": 40 }
+// { "polygon": [[-0.5, -1.0], [2.0, -1.0], [2.0, 1.0], [-0.5, 1.0]], "lookahead": 0.5 }
+// All values are optional, max_speed limits the wheel speeds in the move speed units.
+use super::kinematics;
+use super::odometry;
+use kinematics::{WheelCommand, WheelState};
+use odometry::{BodyVelocity, Pose};
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+
+pub const DEFAULT_GEOFENCE_FILE: &str = "netbot-geofence.json";
+// seconds of the motion checked ahead
+const DEFAULT_LOOKAHEAD: f64 = 0.5;
+
+#[derive(Debug)]
+pub enum GeofenceErrors {
+    WrongFormat,
+}
+impl fmt::Display for GeofenceErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error: {:?}", self)
+    }
+}
+impl Error for GeofenceErrors {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FenceArea {
+    // meters around the origin
+    Circle { radius: f64 },
+    // corners in meters, in order around the area
+    Polygon(Vec<(f64, f64)>),
+}
+
+// Distance from the point to the segment
+fn segment_distance(x: f64, y: f64, start: (f64, f64), end: (f64, f64)) -> f64 {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared > 0.0 {
+        (((x - start.0) * dx + (y - start.1) * dy) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (x - start.0 - t * dx).hypot(y - start.1 - t * dy)
+}
+
+impl FenceArea {
+    // Distance to the edge, positive inside the area and negative outside
+    pub fn clearance(&self, x: f64, y: f64) -> f64 {
+        match self {
+            FenceArea::Circle { radius } => radius - x.hypot(y),
+            FenceArea::Polygon(corners) => {
+                let mut inside = false;
+                let mut distance = f64::INFINITY;
+                for (i, end) in corners.iter().enumerate() {
+                    let start = corners[(i + corners.len() - 1) % corners.len()];
+                    distance = distance.min(segment_distance(x, y, start, *end));
+                    // crossings of the ray to the right of the point
+                    if (start.1 > y) != (end.1 > y)
+                        && x < start.0 + (y - start.1) * (end.0 - start.0) / (end.1 - start.1)
+                    {
+                        inside = !inside;
+                    }
+                }
+                if inside {
+                    distance
+                } else {
+                    -distance
+                }
+            }
+        }
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        self.clearance(x, y) >= 0.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeofenceConfig {
+    pub area: Option<FenceArea>,
+    pub max_speed: Option<u8>,
+    // seconds
+    pub lookahead: f64,
+}
+
+impl Default for GeofenceConfig {
+    fn default() -> GeofenceConfig {
+        GeofenceConfig {
+            area: None,
+            max_speed: None,
+            lookahead: DEFAULT_LOOKAHEAD,
+        }
+    }
+}
+
+impl GeofenceConfig {
+    // Keeps the motion inside the area: the motion is allowed when the robot stays
+    // inside for the lookahead time or does not get farther from the area, so the bot
+    // outside can turn and come back
+    pub fn allows(&self, pose: Pose, velocity: BodyVelocity) -> bool {
+        self.allows_for(pose, velocity, self.lookahead)
+    }
+
+    // The same check for a motion which lasts the given seconds, like a timed move
+    pub fn allows_for(&self, pose: Pose, velocity: BodyVelocity, seconds: f64) -> bool {
+        let area = match self.area.as_ref() {
+            Some(area) => area,
+            None => return true,
+        };
+        let next = pose.advanced(velocity, seconds);
+        let clearance = area.clearance(next.x, next.y);
+        clearance >= 0.0 || clearance >= area.clearance(pose.x, pose.y)
+    }
+
+    // The wheels keep their speed ratio, so the path stays the same
+    pub fn limit_command(&self, cmd: WheelCommand) -> WheelCommand {
+        let fastest = cmd.left_speed.max(cmd.right_speed);
+        match self.max_speed {
+            Some(max_speed) if fastest > max_speed => WheelCommand {
+                left_speed: (cmd.left_speed as u32 * max_speed as u32 / fastest as u32) as u8,
+                left_dir: cmd.left_dir,
+                right_speed: (cmd.right_speed as u32 * max_speed as u32 / fastest as u32) as u8,
+                right_dir: cmd.right_dir,
+            },
+            _ => cmd,
+        }
+    }
+
+    pub fn limit_wheels(&self, wheels: &[WheelState]) -> Vec<WheelState> {
+        let fastest = wheels.iter().map(|wheel| wheel.speed).max().unwrap_or(0);
+        match self.max_speed {
+            Some(max_speed) if fastest > max_speed => wheels
+                .iter()
+                .map(|wheel| WheelState {
+                    speed: (wheel.speed as u32 * max_speed as u32 / fastest as u32) as u8,
+                    dir: wheel.dir,
+                })
+                .collect(),
+            _ => wheels.to_vec(),
+        }
+    }
+}
+
+fn parse_area(config: &Value) -> Result<Option<FenceArea>, Box<dyn Error>> {
+    let wrong_format = || Box::new(GeofenceErrors::WrongFormat) as Box<dyn Error>;
+    match (config.get("radius"), config.get("polygon")) {
+        (Some(radius), None) => {
+            let radius = radius
+                .as_f64()
+                .filter(|radius| *radius > 0.0)
+                .ok_or_else(wrong_format)?;
+            Ok(Some(FenceArea::Circle { radius: radius }))
+        }
+        (None, Some(polygon)) => {
+            let mut corners = Vec::new();
+            for corner in polygon.as_array().ok_or_else(wrong_format)? {
+                let x = corner.get(0).and_then(|x| x.as_f64());
+                let y = corner.get(1).and_then(|y| y.as_f64());
+                match (x, y) {
+                    (Some(x), Some(y)) => corners.push((x, y)),
+                    _ => return Err(wrong_format()),
+                }
+            }
+            if corners.len() < 3 {
+                return Err(wrong_format());
+            }
+            Ok(Some(FenceArea::Polygon(corners)))
+        }
+        (None, None) => Ok(None),
+        _ => Err(wrong_format()),
+    }
+}
+
+pub fn parse_geofence(text: &str) -> Result<GeofenceConfig, Box<dyn Error>> {
+    let wrong_format = || Box::new(GeofenceErrors::WrongFormat) as Box<dyn Error>;
+    let config: Value = serde_json::from_str(text)?;
+    if !config.is_object() {
+        return Err(wrong_format());
+    }
+    let max_speed = match config.get("max_speed") {
+        Some(max_speed) => Some(
+            max_speed
+                .as_u64()
+                .filter(|speed| *speed > 0 && *speed <= u8::MAX as u64)
+                .ok_or_else(wrong_format)? as u8,
+        ),
+        None => None,
+    };
+    let lookahead = match config.get("lookahead") {
+        Some(lookahead) => lookahead
+            .as_f64()
+            .filter(|lookahead| *lookahead >= 0.0)
+            .ok_or_else(wrong_format)?,
+        None => DEFAULT_LOOKAHEAD,
+    };
+    Ok(GeofenceConfig {
+        area: parse_area(&config)?,
+        max_speed: max_speed,
+        lookahead: lookahead,
+    })
+}
+
+// None when there is no config file
+pub fn load_geofence(path: &str) -> Result<Option<GeofenceConfig>, Box<dyn Error>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(Some(parse_geofence(&text)?)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(Box::new(err)),
+    }
+}
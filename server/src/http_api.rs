@@ -0,0 +1,652 @@
+// JSON API for remote control. Moves sent with POST /move keep going only while they
+// are refreshed: the client sends the move again or POST /move/refresh at least every
+// refresh_ms from the state, otherwise the robot stops. POST /stop gives up the control.
+extern crate opencv;
+extern crate tiny_http;
+use super::arbiter::SourceId;
+use super::geofence::FenceArea;
+use super::kinematics::{WheelCommand, DIR_BACKWARD, DIR_FORWARD};
+use super::mission;
+use super::robot::{Robot, DEFAULT_MOTION_WATCHDOG, MAX_MOVE_SPEED};
+use super::route::{Route, DEFAULT_ROUTE_FILE};
+use super::script::ScriptConfig;
+use opencv::imgcodecs;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::io::{Cursor, Write};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response};
+
+const STATE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+const ROBOT_UPDATE_INTERVAL: Duration = Duration::from_millis(20);
+const DEFAULT_JPEG_QUALITY: i32 = 80;
+pub const REMOTE_PRIORITY: u8 = 10;
+// the remote moves stop when they are not refreshed in this time
+pub const REMOTE_IDLE_TIMEOUT: Duration = DEFAULT_MOTION_WATCHDOG;
+
+type JsonResponse = Response<Cursor<Vec<u8>>>;
+type Subscribers = Arc<Mutex<Vec<Sender<String>>>>;
+
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: u16, message: &str) -> ApiError {
+        ApiError {
+            status: status,
+            message: String::from(message),
+        }
+    }
+
+    fn internal(err: Box<dyn Error>) -> ApiError {
+        ApiError {
+            status: 500,
+            message: err.to_string(),
+        }
+    }
+}
+
+pub struct HttpApi {
+    robot: Arc<Mutex<Robot>>,
+    source: SourceId,
+    subscribers: Subscribers,
+}
+
+impl HttpApi {
+    pub fn new(robot: Arc<Mutex<Robot>>) -> HttpApi {
+        let source = robot.lock().unwrap().register_input_source(
+            "remote",
+            REMOTE_PRIORITY,
+            Some(REMOTE_IDLE_TIMEOUT),
+        );
+        HttpApi {
+            robot: robot,
+            source: source,
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn serve(&self, addr: &str) -> Result<(), Box<dyn Error>> {
+        let server = tiny_http::Server::http(addr).map_err(|err| err.to_string())?;
+        eprintln!("HTTP API listening on {}", addr);
+
+        let robot = Arc::clone(&self.robot);
+        let subscribers = Arc::clone(&self.subscribers);
+        thread::spawn(move || state_watch_thread(robot, subscribers));
+        let robot = Arc::clone(&self.robot);
+        thread::spawn(move || robot_update_thread(robot));
+
+        for request in server.incoming_requests() {
+            self.process_request(request);
+        }
+        Ok(())
+    }
+
+    fn process_request(&self, mut request: Request) {
+        let method = request.method().clone();
+        let url = String::from(request.url());
+        let mut url_parts = url.splitn(2, '?');
+        let path: Vec<&str> = url_parts
+            .next()
+            .unwrap_or("")
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect();
+        let query = url_parts.next().unwrap_or("");
+
+        if method == Method::Get && path == ["events"] {
+            self.open_event_stream(request);
+            return;
+        }
+
+        let result = match (&method, path.as_slice()) {
+            (Method::Get, ["status"]) => Ok(json_response(200, &self.status())),
+            (Method::Get, ["cameras"]) => Ok(json_response(200, &self.cameras())),
+            (Method::Get, ["cameras", camera_id]) => self
+                .camera(camera_id)
+                .map(|camera| json_response(200, &camera)),
+            (Method::Put, ["cameras", camera_id, "props"]) => read_json_body(&mut request)
+                .and_then(|body| self.set_camera_props(camera_id, &body))
+                .map(|camera| json_response(200, &camera)),
+            (Method::Get, ["cameras", camera_id, "frame.jpg"]) => {
+                self.camera_frame(camera_id, query)
+            }
+            (Method::Post, ["move"]) => read_json_body(&mut request)
+                .and_then(|body| self.move_bot(&body))
+                .map(|state| json_response(200, &state)),
+            (Method::Post, ["move", "refresh"]) => self.refresh_move(),
+            (Method::Put, ["speed"]) => read_json_body(&mut request)
+                .and_then(|body| self.set_speed(&body))
+                .map(|state| json_response(200, &state)),
+            (Method::Post, ["stop"]) => {
+                // only gives up the remote control, other active sources keep driving
+                let result = self.robot.lock().unwrap().release_motion(self.source);
+                result
+                    .map(|_| json_response(200, &self.status()))
+                    .map_err(ApiError::internal)
+            }
+            (Method::Post, ["estop"]) => {
+                self.robot.lock().unwrap().emergency_stop();
+                Ok(json_response(200, &self.status()))
+            }
+            (Method::Post, ["pose", "reset"]) => {
+                self.robot.lock().unwrap().reset_pose();
+                Ok(json_response(200, &self.status()))
+            }
+            (Method::Post, ["mission"]) => read_json_body(&mut request)
+                .and_then(|body| self.start_mission(&body))
+                .map(|state| json_response(200, &state)),
+            (Method::Post, ["mission", "pause"]) => {
+                self.robot.lock().unwrap().pause_mission();
+                Ok(json_response(200, &self.status()))
+            }
+            (Method::Post, ["mission", "resume"]) => {
+                self.robot.lock().unwrap().resume_mission();
+                Ok(json_response(200, &self.status()))
+            }
+            (Method::Post, ["mission", "abort"]) => {
+                self.robot.lock().unwrap().abort_mission();
+                Ok(json_response(200, &self.status()))
+            }
+            (Method::Post, ["route", "record"]) => {
+                self.robot.lock().unwrap().start_route_recording();
+                Ok(json_response(200, &self.status()))
+            }
+            (Method::Post, ["route", "record", "stop"]) => self
+                .stop_route_recording()
+                .map(|state| json_response(200, &state)),
+            (Method::Post, ["route", "replay"]) => read_json_body(&mut request)
+                .and_then(|body| self.start_replay(&body))
+                .map(|state| json_response(200, &state)),
+            (Method::Post, ["route", "replay", "stop"]) => {
+                self.robot.lock().unwrap().stop_replay();
+                Ok(json_response(200, &self.status()))
+            }
+            (Method::Put, ["route", "replay", "speed"]) => read_json_body(&mut request)
+                .and_then(|body| self.set_replay_speed(&body))
+                .map(|state| json_response(200, &state)),
+            (Method::Post, ["script"]) => read_json_body(&mut request)
+                .and_then(|body| self.start_script(&body))
+                .map(|state| json_response(200, &state)),
+            (Method::Post, ["script", "stop"]) => {
+                self.robot.lock().unwrap().stop_script();
+                Ok(json_response(200, &self.status()))
+            }
+            (Method::Get, ["script", "output"]) => {
+                let events: Vec<String> = self
+                    .robot
+                    .lock()
+                    .unwrap()
+                    .take_script_events()
+                    .iter()
+                    .map(|event| event.to_string())
+                    .collect();
+                Ok(json_response(200, &json!({ "output": events })))
+            }
+            (Method::Post, ["estop", "reset"]) => {
+                self.robot.lock().unwrap().reset_emergency_stop();
+                Ok(json_response(200, &self.status()))
+            }
+            _ => Err(ApiError::new(404, "unknown endpoint")),
+        };
+        let response = match result {
+            Ok(response) => response,
+            Err(err) => json_response(err.status, &json!({ "error": err.message })),
+        };
+        if let Err(err) = request.respond(response) {
+            eprintln!("Failed to send HTTP response: {}", err);
+        }
+    }
+
+    fn status(&self) -> Value {
+        state_json(&self.robot.lock().unwrap())
+    }
+
+    fn cameras(&self) -> Value {
+        let robot = self.robot.lock().unwrap();
+        let cameras: Vec<Value> = robot
+            .get_camera_list()
+            .unwrap_or_default()
+            .iter()
+            .map(|camera_id| camera_json(&robot, *camera_id))
+            .collect();
+        json!({ "cameras": cameras })
+    }
+
+    fn camera(&self, camera_id: &str) -> Result<Value, ApiError> {
+        let robot = self.robot.lock().unwrap();
+        let camera_id = parse_camera_id(&robot, camera_id)?;
+        Ok(camera_json(&robot, camera_id))
+    }
+
+    fn set_camera_props(&self, camera_id: &str, body: &Value) -> Result<Value, ApiError> {
+        let mut robot = self.robot.lock().unwrap();
+        let camera_id = parse_camera_id(&robot, camera_id)?;
+        let width = body_u64(body, "width", u16::MAX as u64)? as u16;
+        let height = body_u64(body, "height", u16::MAX as u64)? as u16;
+        let fps = body_u64(body, "fps", u8::MAX as u64)? as u8;
+        let encode = match body.get("encode") {
+            Some(value) => value
+                .as_bool()
+                .ok_or_else(|| ApiError::new(400, "encode should be a boolean"))?,
+            None => robot
+                .get_frame_info(camera_id)
+                .map_or(false, |info| info.encoded),
+        };
+        robot
+            .ask_set_camera_prop(camera_id, width, height, fps, encode)
+            .map_err(ApiError::internal)?;
+        Ok(camera_json(&robot, camera_id))
+    }
+
+    fn camera_frame(&self, camera_id: &str, query: &str) -> Result<JsonResponse, ApiError> {
+        let robot = self.robot.lock().unwrap();
+        let camera_id = parse_camera_id(&robot, camera_id)?;
+        let mut quality = DEFAULT_JPEG_QUALITY;
+        for param in query.split('&') {
+            let mut key_value = param.splitn(2, '=');
+            if key_value.next() == Some("quality") {
+                quality = key_value
+                    .next()
+                    .and_then(|v| v.parse::<i32>().ok())
+                    .filter(|v| *v >= 0 && *v <= 100)
+                    .ok_or_else(|| ApiError::new(400, "quality should be in 0..100"))?;
+            }
+        }
+        let data = robot
+            .get_encoded_image(
+                camera_id,
+                ".jpg",
+                &[imgcodecs::IMWRITE_JPEG_QUALITY, quality],
+            )
+            .map_err(ApiError::internal)?
+            .ok_or_else(|| ApiError::new(503, "no frame received from the camera yet"))?;
+        Ok(Response::from_data(data).with_header(content_type("image/jpeg")))
+    }
+
+    // Keep-alive for the last move, so the client does not need to send it again
+    fn refresh_move(&self) -> Result<JsonResponse, ApiError> {
+        let mut robot = self.robot.lock().unwrap();
+        if robot.is_emergency_stopped() {
+            return Err(ApiError::new(409, "emergency stop is engaged"));
+        }
+        robot.refresh_motion_source(self.source);
+        Ok(json_response(200, &state_json(&robot)))
+    }
+
+    fn move_bot(&self, body: &Value) -> Result<Value, ApiError> {
+        let mut robot = self.robot.lock().unwrap();
+        if robot.is_emergency_stopped() {
+            return Err(ApiError::new(409, "emergency stop is engaged"));
+        }
+        let speed = robot.get_speed();
+        let cmd = if body.get("linear").is_some() || body.get("angular").is_some() {
+            let linear = body_f64(body, "linear")?;
+            let angular = body_f64(body, "angular")?;
+            robot
+                .get_drive_config()
+                .wheel_command(linear, angular, MAX_MOVE_SPEED)
+        } else if let Some(direction) = body.get("direction") {
+            let direction = direction
+                .as_str()
+                .ok_or_else(|| ApiError::new(400, "direction should be a string"))?;
+            let (left_speed, left_dir, right_speed, right_dir) = match direction {
+                "forward" => (speed, DIR_FORWARD, speed, DIR_FORWARD),
+                "backward" => (speed, DIR_BACKWARD, speed, DIR_BACKWARD),
+                "left" => (0, DIR_BACKWARD, speed, DIR_FORWARD),
+                "right" => (speed, DIR_FORWARD, 0, DIR_BACKWARD),
+                _ => return Err(ApiError::new(400, "unknown direction")),
+            };
+            WheelCommand {
+                left_speed: left_speed,
+                left_dir: left_dir,
+                right_speed: right_speed,
+                right_dir: right_dir,
+            }
+        } else {
+            WheelCommand {
+                left_speed: body_u64(body, "left_speed", MAX_MOVE_SPEED as u64)? as u8,
+                left_dir: body_u64(body, "left_dir", 1)? as u8,
+                right_speed: body_u64(body, "right_speed", MAX_MOVE_SPEED as u64)? as u8,
+                right_dir: body_u64(body, "right_dir", 1)? as u8,
+            }
+        };
+        robot
+            .submit_motion(self.source, cmd)
+            .map_err(ApiError::internal)?;
+        Ok(state_json(&robot))
+    }
+
+    fn set_speed(&self, body: &Value) -> Result<Value, ApiError> {
+        let mut robot = self.robot.lock().unwrap();
+        let speed = body_u64(body, "speed", MAX_MOVE_SPEED as u64)? as u8;
+        robot.set_speed(speed);
+        Ok(state_json(&robot))
+    }
+
+    // The waypoints are in the mission text format, one per line
+    fn start_mission(&self, body: &Value) -> Result<Value, ApiError> {
+        let text = body
+            .get("waypoints")
+            .and_then(|waypoints| waypoints.as_str())
+            .ok_or_else(|| ApiError::new(400, "waypoints should be a string"))?;
+        let steps =
+            mission::parse_mission(text).map_err(|err| ApiError::new(400, &err.to_string()))?;
+        let mut robot = self.robot.lock().unwrap();
+        if robot.is_emergency_stopped() {
+            return Err(ApiError::new(409, "emergency stop is engaged"));
+        }
+        robot.start_mission(steps).map_err(ApiError::internal)?;
+        Ok(state_json(&robot))
+    }
+
+    // The route is saved to the default file on the server side
+    fn stop_route_recording(&self) -> Result<Value, ApiError> {
+        let mut robot = self.robot.lock().unwrap();
+        if !robot.is_route_recording() {
+            return Err(ApiError::new(409, "the route is not being recorded"));
+        }
+        let route = robot
+            .stop_route_recording()
+            .map_err(|err| ApiError::new(409, &err.to_string()))?;
+        route.save(DEFAULT_ROUTE_FILE).map_err(ApiError::internal)?;
+        Ok(state_json(&robot))
+    }
+
+    fn start_replay(&self, body: &Value) -> Result<Value, ApiError> {
+        let reverse = match body.get("reverse") {
+            Some(reverse) => reverse
+                .as_bool()
+                .ok_or_else(|| ApiError::new(400, "reverse should be a boolean"))?,
+            None => false,
+        };
+        let speed_scale = match body.get("speed_scale") {
+            Some(_) => body_f64(body, "speed_scale")?,
+            None => 1.0,
+        };
+        let route = Route::load(DEFAULT_ROUTE_FILE)
+            .map_err(|err| ApiError::new(404, &format!("no recorded route: {}", err)))?;
+        let mut robot = self.robot.lock().unwrap();
+        if robot.is_emergency_stopped() {
+            return Err(ApiError::new(409, "emergency stop is engaged"));
+        }
+        robot
+            .start_replay(route, reverse, speed_scale)
+            .map_err(ApiError::internal)?;
+        Ok(state_json(&robot))
+    }
+
+    fn set_replay_speed(&self, body: &Value) -> Result<Value, ApiError> {
+        let mut robot = self.robot.lock().unwrap();
+        robot.set_replay_speed_scale(body_f64(body, "speed_scale")?);
+        Ok(state_json(&robot))
+    }
+
+    // The output is taken with GET /script/output
+    fn start_script(&self, body: &Value) -> Result<Value, ApiError> {
+        let source = body
+            .get("source")
+            .and_then(|source| source.as_str())
+            .ok_or_else(|| ApiError::new(400, "source should be a string"))?;
+        let mut config = ScriptConfig::default();
+        if body.get("time_limit").is_some() {
+            let seconds = body_f64(body, "time_limit")?;
+            if seconds <= 0.0 || !seconds.is_finite() {
+                return Err(ApiError::new(400, "time_limit should be positive"));
+            }
+            config.time_limit = Duration::from_secs_f64(seconds);
+        }
+        let mut robot = self.robot.lock().unwrap();
+        if robot.is_emergency_stopped() {
+            return Err(ApiError::new(409, "emergency stop is engaged"));
+        }
+        robot
+            .start_script(source, config)
+            .map_err(|err| ApiError::new(400, &err.to_string()))?;
+        Ok(state_json(&robot))
+    }
+
+    fn open_event_stream(&self, request: Request) {
+        let (sender, receiver) = channel::<String>();
+        let initial_event = state_event(&self.status());
+        self.subscribers.lock().unwrap().push(sender);
+        thread::spawn(move || {
+            // the response is written by hand because the chunked encoder buffers events
+            let mut writer = request.into_writer();
+            let header = "HTTP/1.1 200 OK\r\n\
+                          Content-Type: text/event-stream\r\n\
+                          Cache-Control: no-cache\r\n\
+                          Connection: close\r\n\r\n";
+            let mut data = String::from(header) + initial_event.as_str();
+            loop {
+                if writer
+                    .write_all(data.as_bytes())
+                    .and_then(|_| writer.flush())
+                    .is_err()
+                {
+                    break;
+                }
+                data = match receiver.recv_timeout(KEEP_ALIVE_INTERVAL) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => String::from(": keep-alive\n\n"),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+            }
+        });
+    }
+}
+
+fn state_watch_thread(robot: Arc<Mutex<Robot>>, subscribers: Subscribers) {
+    let mut last_state = Value::Null;
+    loop {
+        let state = state_json(&robot.lock().unwrap());
+        if state != last_state {
+            let event = state_event(&state);
+            subscribers
+                .lock()
+                .unwrap()
+                .retain(|sender| sender.send(event.clone()).is_ok());
+            last_state = state;
+        }
+        thread::sleep(STATE_POLL_INTERVAL);
+    }
+}
+
+fn robot_update_thread(robot: Arc<Mutex<Robot>>) {
+    loop {
+        robot.lock().unwrap().update();
+        thread::sleep(ROBOT_UPDATE_INTERVAL);
+    }
+}
+
+fn state_event(state: &Value) -> String {
+    format!("event: state\ndata: {}\n\n", state)
+}
+
+// Moves stop when they are not refreshed by the remote source timeout or the watchdog
+fn refresh_interval(robot: &Robot) -> Duration {
+    robot
+        .get_motion_watchdog()
+        .map_or(REMOTE_IDLE_TIMEOUT, |watchdog| {
+            watchdog.min(REMOTE_IDLE_TIMEOUT)
+        })
+}
+
+fn state_json(robot: &Robot) -> Value {
+    let cameras: Vec<Value> = robot
+        .get_camera_list()
+        .unwrap_or_default()
+        .iter()
+        .map(|camera_id| frame_json(robot, *camera_id))
+        .collect();
+    json!({
+        "connected": robot.is_connected(),
+        "peer": robot.get_peer_addr().map(|addr| addr.to_string()),
+        "moving": robot.is_moving(),
+        "drive": robot.get_drive_type().name(),
+        "holonomic": robot.is_holonomic(),
+        "estop": robot.is_emergency_stopped(),
+        "speed": robot.get_speed(),
+        "control": robot.get_control_source(),
+        "refresh_ms": refresh_interval(robot).as_millis() as u64,
+        "pose": pose_json(robot),
+        "mission": mission_json(robot),
+        "route": route_json(robot),
+        "geofence": geofence_json(robot),
+        "script": { "running": robot.is_script_running() },
+        "cameras": cameras,
+    })
+}
+
+// Rounded so that the state events are not sent for every tiny pose change
+fn pose_json(robot: &Robot) -> Value {
+    let pose = robot.pose();
+    let round = |value: f64, scale: f64| (value * scale).round() / scale;
+    let mut pose_json = json!({
+        "x": round(pose.x, 1000.0),
+        "y": round(pose.y, 1000.0),
+        "heading": round(pose.heading, 1000.0),
+    });
+    // standard deviations of the filtered pose
+    if let Some(estimate) = robot.pose_estimate() {
+        let std_dev = |i: usize| round(estimate.covariance[i][i].max(0.0).sqrt(), 1000.0);
+        pose_json["std_dev"] = json!({
+            "x": std_dev(0),
+            "y": std_dev(1),
+            "heading": std_dev(2),
+        });
+    }
+    pose_json
+}
+
+fn geofence_json(robot: &Robot) -> Value {
+    let geofence = match robot.get_geofence() {
+        Some(geofence) => geofence,
+        None => return Value::Null,
+    };
+    // the area is in the frame of the origin pose, which moves with the pose resets
+    let origin = robot.get_geofence_origin();
+    let mut geofence_json = json!({
+        "max_speed": geofence.max_speed,
+        "inside": robot.is_inside_geofence(),
+        "origin": {
+            "x": origin.x,
+            "y": origin.y,
+            "heading": origin.heading,
+        },
+    });
+    match geofence.area {
+        Some(FenceArea::Circle { radius }) => geofence_json["radius"] = json!(radius),
+        Some(FenceArea::Polygon(corners)) => geofence_json["polygon"] = json!(corners),
+        None => (),
+    }
+    geofence_json
+}
+
+fn mission_json(robot: &Robot) -> Value {
+    match robot.get_mission_status() {
+        Some(status) => json!({
+            "state": status.state.name(),
+            "waypoint": status.waypoint,
+            "waypoint_count": status.waypoint_count,
+        }),
+        None => Value::Null,
+    }
+}
+
+fn route_json(robot: &Robot) -> Value {
+    let round = |value: f64| (value * 10.0).round() / 10.0;
+    let mut route_json = json!({
+        "recording": robot.is_route_recording(),
+        "replaying": robot.is_replaying(),
+    });
+    if let Some((played, duration)) = robot.get_replay_progress() {
+        route_json["played"] = json!(round(played));
+        route_json["duration"] = json!(round(duration));
+        route_json["speed_scale"] = json!(robot.get_replay_speed_scale());
+    }
+    route_json
+}
+
+fn frame_json(robot: &Robot, camera_id: u8) -> Value {
+    match robot.get_frame_info(camera_id) {
+        Some(info) => json!({
+            "id": camera_id,
+            "width": info.width,
+            "height": info.height,
+            "encoded": info.encoded,
+        }),
+        None => json!({ "id": camera_id }),
+    }
+}
+
+fn camera_json(robot: &Robot, camera_id: u8) -> Value {
+    let resolutions: Vec<Value> = robot
+        .get_camera_resolutions(camera_id)
+        .unwrap_or_default()
+        .iter()
+        .map(|res| json!({ "width": res.0, "height": res.1 }))
+        .collect();
+    let mut camera = frame_json(robot, camera_id);
+    camera["resolutions"] = Value::from(resolutions);
+    camera
+}
+
+fn parse_camera_id(robot: &Robot, camera_id: &str) -> Result<u8, ApiError> {
+    camera_id
+        .parse::<u8>()
+        .ok()
+        .filter(|id| robot.get_camera_list().unwrap_or_default().contains(id))
+        .ok_or_else(|| ApiError::new(404, "unknown camera"))
+}
+
+fn read_json_body(request: &mut Request) -> Result<Value, ApiError> {
+    let mut body = String::new();
+    request
+        .as_reader()
+        .read_to_string(&mut body)
+        .map_err(|_| ApiError::new(400, "failed to read request body"))?;
+    if body.trim().is_empty() {
+        return Ok(json!({}));
+    }
+    serde_json::from_str(&body).map_err(|_| ApiError::new(400, "request body is not valid JSON"))
+}
+
+fn body_u64(body: &Value, name: &str, max: u64) -> Result<u64, ApiError> {
+    match body.get(name) {
+        Some(value) => value
+            .as_u64()
+            .filter(|v| *v <= max)
+            .ok_or_else(|| ApiError {
+                status: 400,
+                message: format!("{} should be an integer in 0..{}", name, max),
+            }),
+        None => Ok(0),
+    }
+}
+
+fn body_f64(body: &Value, name: &str) -> Result<f64, ApiError> {
+    match body.get(name) {
+        Some(value) => value.as_f64().ok_or_else(|| ApiError {
+            status: 400,
+            message: format!("{} should be a number", name),
+        }),
+        None => Ok(0.0),
+    }
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes(&b"Content-Type"[..], value.as_bytes()).unwrap()
+}
+
+fn json_response(status: u16, value: &Value) -> JsonResponse {
+    Response::from_string(value.to_string())
+        .with_status_code(status)
+        .with_header(content_type("application/json"))
+}
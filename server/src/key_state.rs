@@ -0,0 +1,90 @@
+use super::kinematics;
+use kinematics::{WheelCommand, DIR_BACKWARD, DIR_FORWARD};
+use std::collections::HashSet;
+
+// Inner wheel speed relative to the outer one when driving and turning together
+const ARC_INNER_RATIO: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DriveKey {
+    Forward,
+    Backward,
+    Left,
+    Right,
+}
+
+// Tracks the set of held drive keys and turns it into one wheel command,
+// so forward and turn keys can be combined into an arc.
+pub struct KeyStateTracker {
+    held_keys: HashSet<DriveKey>,
+}
+
+impl KeyStateTracker {
+    pub fn new() -> KeyStateTracker {
+        KeyStateTracker {
+            held_keys: HashSet::new(),
+        }
+    }
+
+    // Returns true when the held set has changed
+    pub fn press(&mut self, key: DriveKey) -> bool {
+        self.held_keys.insert(key)
+    }
+
+    // Returns true when the held set has changed
+    pub fn release(&mut self, key: DriveKey) -> bool {
+        self.held_keys.remove(&key)
+    }
+
+    // Returns true when the held set has changed
+    pub fn release_all(&mut self) -> bool {
+        let changed = !self.held_keys.is_empty();
+        self.held_keys.clear();
+        changed
+    }
+
+    pub fn is_any_held(&self) -> bool {
+        !self.held_keys.is_empty()
+    }
+
+    pub fn is_held(&self, key: DriveKey) -> bool {
+        self.held_keys.contains(&key)
+    }
+
+    // -1, 0 or 1 for backward, none and forward
+    pub fn throttle(&self) -> i8 {
+        self.is_held(DriveKey::Forward) as i8 - self.is_held(DriveKey::Backward) as i8
+    }
+
+    // -1, 0 or 1 for right, none and left
+    pub fn turn(&self) -> i8 {
+        self.is_held(DriveKey::Left) as i8 - self.is_held(DriveKey::Right) as i8
+    }
+
+    pub fn command(&self, speed: u8) -> WheelCommand {
+        let inner_speed = (speed as f64 * ARC_INNER_RATIO).round() as u8;
+        let dir = |throttle: i8| {
+            if throttle < 0 {
+                DIR_BACKWARD
+            } else {
+                DIR_FORWARD
+            }
+        };
+        let throttle = self.throttle();
+        let (left_speed, right_speed) = match (throttle, self.turn()) {
+            (0, 0) => (0, 0),
+            // turning in place pivots on the inner wheel
+            (0, turn) if turn > 0 => (0, speed),
+            (0, _) => (speed, 0),
+            (_, 0) => (speed, speed),
+            (_, turn) if turn > 0 => (inner_speed, speed),
+            (_, _) => (speed, inner_speed),
+        };
+        WheelCommand {
+            left_speed: left_speed,
+            left_dir: dir(throttle),
+            right_speed: right_speed,
+            right_dir: dir(throttle),
+        }
+    }
+}
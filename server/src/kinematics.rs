@@ -0,0 +1,221 @@
+// Differential drive: the left and right wheels are on one axis and the robot turns
+// by driving them at different speeds. Linear velocity is in m/s along the robot heading,
+// angular velocity is in rad/s counterclockwise, so a positive value turns left.
+
+use std::f64::consts::PI;
+
+pub const DIR_BACKWARD: u8 = 0;
+pub const DIR_FORWARD: u8 = 1;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DriveConfig {
+    // meters
+    pub wheel_radius: f64,
+    // distance between the wheels contact points, meters
+    pub track_width: f64,
+    // distance between the front and rear axles of 4-wheel chassis, meters
+    pub wheel_base: f64,
+    // wheel angular speed sent as the maximum move speed, rad/s
+    pub max_wheel_speed: f64,
+    // motor half-steps for one wheel revolution
+    pub steps_per_revolution: u32,
+    // half-steps per second used by distance and angle moves
+    pub step_rate: u16,
+    // calibration factors, commanded over measured distance and angle
+    pub distance_scale: f64,
+    pub rotation_scale: f64,
+    // wheel speed multipliers correcting the drift when driving straight
+    pub left_trim: f64,
+    pub right_trim: f64,
+}
+
+impl Default for DriveConfig {
+    fn default() -> DriveConfig {
+        // 28BYJ-48 steppers with 65mm wheels
+        DriveConfig {
+            wheel_radius: 0.0325,
+            track_width: 0.13,
+            wheel_base: 0.13,
+            max_wheel_speed: 1.5,
+            steps_per_revolution: 4096,
+            step_rate: 500,
+            distance_scale: 1.0,
+            rotation_scale: 1.0,
+            left_trim: 1.0,
+            right_trim: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WheelCommand {
+    pub left_speed: u8,
+    pub left_dir: u8,
+    pub right_speed: u8,
+    pub right_dir: u8,
+}
+
+// Speed and direction of one wheel in multi-wheel commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WheelState {
+    pub speed: u8,
+    pub dir: u8,
+}
+
+impl WheelCommand {
+    pub fn is_stop(&self) -> bool {
+        self.left_speed == 0 && self.right_speed == 0
+    }
+
+    // Left and right side as wheels of a multi-wheel command
+    pub fn wheels(&self) -> [WheelState; 2] {
+        [
+            WheelState {
+                speed: self.left_speed,
+                dir: self.left_dir,
+            },
+            WheelState {
+                speed: self.right_speed,
+                dir: self.right_dir,
+            },
+        ]
+    }
+}
+
+impl DriveConfig {
+    // Wheel angular speeds in rad/s for the body velocity, without any limits applied
+    pub fn wheel_speeds(&self, linear: f64, angular: f64) -> (f64, f64) {
+        let half_track = self.track_width / 2.0;
+        let left = (linear - angular * half_track) / self.wheel_radius;
+        let right = (linear + angular * half_track) / self.wheel_radius;
+        (left, right)
+    }
+
+    // Body velocity for the wheel angular speeds in rad/s
+    pub fn body_velocity(&self, left: f64, right: f64) -> (f64, f64) {
+        let linear = (left + right) * self.wheel_radius / 2.0;
+        let angular = (right - left) * self.wheel_radius / self.track_width;
+        (linear, angular)
+    }
+
+    // Converts the body velocity to wheel speeds in 0..max_speed units. When a wheel
+    // would exceed max_wheel_speed both wheels are scaled down by the same factor,
+    // so the robot keeps the requested turn radius and only moves slower.
+    pub fn wheel_command(&self, linear: f64, angular: f64, max_speed: u8) -> WheelCommand {
+        let (left, right) = self.wheel_speeds(linear, angular);
+        let wheels = self.wheel_states(&[left, right], max_speed);
+        WheelCommand {
+            left_speed: wheels[0].speed,
+            left_dir: wheels[0].dir,
+            right_speed: wheels[1].speed,
+            right_dir: wheels[1].dir,
+        }
+    }
+
+    // Converts wheel angular speeds in rad/s to 0..max_speed units, all wheels are
+    // scaled down together when any of them would exceed max_wheel_speed
+    pub fn wheel_states(&self, wheel_speeds: &[f64], max_speed: u8) -> Vec<WheelState> {
+        let fastest = wheel_speeds
+            .iter()
+            .fold(0.0, |fastest: f64, wheel| fastest.max(wheel.abs()));
+        let scale = if fastest > self.max_wheel_speed {
+            self.max_wheel_speed / fastest
+        } else {
+            1.0
+        };
+        wheel_speeds
+            .iter()
+            .map(|wheel| {
+                let wheel = wheel * scale;
+                let speed = (wheel.abs() / self.max_wheel_speed * max_speed as f64).round();
+                WheelState {
+                    speed: speed.min(max_speed as f64) as u8,
+                    dir: if wheel < 0.0 {
+                        DIR_BACKWARD
+                    } else {
+                        DIR_FORWARD
+                    },
+                }
+            })
+            .collect()
+    }
+
+    // Wheel angular speed in rad/s for a speed in 0..max_speed units, negative when backward
+    pub fn wheel_speed(&self, speed: u8, dir: u8, max_speed: u8) -> f64 {
+        let wheel = speed as f64 / max_speed as f64 * self.max_wheel_speed;
+        if dir == DIR_BACKWARD {
+            -wheel
+        } else {
+            wheel
+        }
+    }
+
+    // Body velocity the command moves the robot with, linear in m/s and angular in rad/s
+    pub fn command_velocity(&self, cmd: WheelCommand, max_speed: u8) -> (f64, f64) {
+        self.body_velocity(
+            self.wheel_speed(cmd.left_speed, cmd.left_dir, max_speed),
+            self.wheel_speed(cmd.right_speed, cmd.right_dir, max_speed),
+        )
+    }
+
+    // Half-steps for a wheel to roll the distance in meters, negative when backward
+    pub fn distance_steps(&self, meters: f64) -> i32 {
+        let revolutions = meters * self.distance_scale / (2.0 * PI * self.wheel_radius);
+        (revolutions * self.steps_per_revolution as f64).round() as i32
+    }
+
+    // Left and right wheel half-steps to turn in place, positive degrees turn left
+    pub fn turn_steps(&self, degrees: f64) -> (i32, i32) {
+        let arc = degrees.to_radians() * self.rotation_scale * self.track_width / 2.0;
+        let revolutions = arc / (2.0 * PI * self.wheel_radius);
+        let steps = (revolutions * self.steps_per_revolution as f64).round() as i32;
+        (-steps, steps)
+    }
+
+    // Half-steps per second turning the wheel with the angular speed in rad/s
+    pub fn wheel_step_rate(&self, wheel_speed: f64) -> f64 {
+        wheel_speed.abs() / (2.0 * PI) * self.steps_per_revolution as f64
+    }
+
+    // Distance in meters and rotation in radians after the wheels made the half-steps,
+    // the inverse of distance_steps and turn_steps
+    pub fn steps_displacement(&self, left_steps: i32, right_steps: i32) -> (f64, f64) {
+        let wheel_distance = |steps: i32| {
+            steps as f64 / self.steps_per_revolution as f64 * 2.0 * PI * self.wheel_radius
+        };
+        let (left, right) = (wheel_distance(left_steps), wheel_distance(right_steps));
+        let distance = (left + right) / 2.0 / self.distance_scale;
+        let rotation = (right - left) / self.track_width / self.rotation_scale;
+        (distance, rotation)
+    }
+
+    // Applies the wheels trim to the command speeds
+    pub fn trim(&self, cmd: WheelCommand) -> WheelCommand {
+        let trim = |speed: u8, trim: f64| (speed as f64 * trim).round().min(u8::MAX as f64) as u8;
+        WheelCommand {
+            left_speed: trim(cmd.left_speed, self.left_trim),
+            left_dir: cmd.left_dir,
+            right_speed: trim(cmd.right_speed, self.right_trim),
+            right_dir: cmd.right_dir,
+        }
+    }
+
+    // Applies the wheels trim to multi-wheel states, even wheels are on the left side
+    pub fn trim_wheels(&self, wheels: &[WheelState]) -> Vec<WheelState> {
+        wheels
+            .iter()
+            .enumerate()
+            .map(|(i, wheel)| {
+                let trim = if i % 2 == 0 {
+                    self.left_trim
+                } else {
+                    self.right_trim
+                };
+                WheelState {
+                    speed: (wheel.speed as f64 * trim).round().min(u8::MAX as f64) as u8,
+                    dir: wheel.dir,
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,30 @@
+#![allow(dead_code)]
+
+#[macro_use]
+extern crate slice_as_array;
+
+pub mod arbiter;
+pub mod calibration;
+pub mod camera_msg;
+pub mod camera_prop_msg;
+pub mod ekf;
+pub mod gamepad;
+pub mod geofence;
+pub mod http_api;
+pub mod image_msg;
+pub mod key_state;
+pub mod kinematics;
+pub mod message;
+pub mod mission;
+pub mod mjpeg;
+pub mod move_msg;
+pub mod odometry;
+pub mod profile;
+pub mod ramp;
+pub mod robot;
+pub mod route;
+pub mod script;
+pub mod server;
+pub mod telemetry_msg;
+#[cfg(test)]
+mod test_util;
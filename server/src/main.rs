@@ -1,7 +1,5 @@
 #![allow(dead_code)]
 
-#[macro_use]
-extern crate slice_as_array;
 extern crate cairo;
 extern crate gdk;
 extern crate gdk_pixbuf;
@@ -14,26 +12,44 @@ use gtk::Application;
 use std::env;
 mod windowui;
 use windowui::WindowUi;
-mod camera_msg;
-mod camera_prop_msg;
-mod image_msg;
-mod message;
-mod move_msg;
-mod robot;
-mod server;
-
-use robot::Robot;
-use std::cell::{RefCell, RefMut};
+
+use netbot::arbiter::SourceId;
+use netbot::calibration::{
+    corrected_trim, load_calibration, load_drive_type, robot_name, save_calibration,
+    DEFAULT_CALIBRATION_FILE,
+};
+use netbot::gamepad::{Gamepad, GamepadAction, GamepadConfig};
+use netbot::geofence::{load_geofence, DEFAULT_GEOFENCE_FILE};
+use netbot::key_state::{DriveKey, KeyStateTracker};
+use netbot::kinematics::{WheelCommand, DIR_FORWARD};
+use netbot::mission::parse_mission;
+use netbot::mjpeg::MjpegConfig;
+use netbot::robot::{Robot, DEFAULT_ACCELERATION};
+use netbot::route::{Route, DEFAULT_ROUTE_FILE};
+use netbot::script::ScriptConfig;
+use std::cell::{Cell, RefCell};
 use std::error::Error;
 use std::net::Ipv4Addr;
 use std::rc::Rc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const GAMEPAD_DEVICE: &str = "/dev/input/js0";
+const GAMEPAD_POLL_INTERVAL_MS: u32 = 20;
+const GAMEPAD_IDLE_TIMEOUT_MS: u64 = 500;
+// longer than the usual key repeat delays, so a held key keeps driving
+const KEYBOARD_IDLE_TIMEOUT_MS: u64 = 1000;
+const ROBOT_UPDATE_INTERVAL_MS: u32 = 20;
+const TRIM_CALIBRATION_DRIVE_MS: u64 = 3000;
+// input sources priorities, the operator at the console overrides automation
+const KEYBOARD_PRIORITY: u8 = 30;
+const GAMEPAD_PRIORITY: u8 = 20;
 
 fn main() -> Result<(), Box<dyn Error>> {
     // Initialize UI
     let mut addr = Ipv4Addr::new(192, 168, 88, 184);
     let mut port = 2345;
+    let mut mjpeg_addr: Option<String> = None;
     let args: Vec<String> = env::args().collect();
     if args.len() >= 2 {
         addr = args[1].parse::<Ipv4Addr>().unwrap();
@@ -41,9 +57,45 @@ fn main() -> Result<(), Box<dyn Error>> {
     if args.len() >= 3 {
         port = args[2].parse::<u16>().unwrap();
     }
+    if args.len() >= 4 {
+        mjpeg_addr = Some(args[3].clone());
+    }
 
     let robot = Rc::new(RefCell::new(Robot::new()?));
     robot.borrow_mut().init(addr, port)?;
+    // the intermediate speeds are sent from the robot update timer
+    robot
+        .borrow_mut()
+        .set_acceleration(Some(DEFAULT_ACCELERATION));
+    let robot_name = robot_name(&robot.borrow());
+    {
+        let mut robot = robot.borrow_mut();
+        let mut drive_config = robot.get_drive_config();
+        match load_calibration(DEFAULT_CALIBRATION_FILE, &robot_name, &mut drive_config) {
+            Ok(true) => println!("Calibration loaded for {}", robot_name),
+            Ok(false) => println!("No calibration for {}", robot_name),
+            Err(err) => println!("Failed to load calibration: {}", err),
+        }
+        robot.set_drive_config(drive_config);
+        match load_drive_type(DEFAULT_CALIBRATION_FILE, &robot_name) {
+            Ok(Some(drive_type)) => robot.set_drive_type(drive_type),
+            Ok(None) => (),
+            Err(err) => println!("Failed to load drive type: {}", err),
+        }
+        match load_geofence(DEFAULT_GEOFENCE_FILE) {
+            Ok(Some(geofence)) => {
+                println!("Geofence loaded from {}", DEFAULT_GEOFENCE_FILE);
+                robot.set_geofence(Some(geofence));
+            }
+            Ok(None) => (),
+            Err(err) => println!("Failed to load geofence: {}", err),
+        }
+    }
+    if let Some(mjpeg_addr) = mjpeg_addr {
+        robot
+            .borrow_mut()
+            .start_mjpeg_streaming(&mjpeg_addr, MjpegConfig::default())?;
+    }
     {
         println!("Getting camera list...");
         robot.borrow_mut().ask_camera_list()?;
@@ -98,21 +150,32 @@ fn main() -> Result<(), Box<dyn Error>> {
         );
         let ui_container = Rc::new(RefCell::new(Some(window_ui)));
 
-        connect_robot_moving(&robot_ui, &ui_container, gdk::keys::constants::Up, |r| {
-            r.move_forward()
-        });
+        ui_container
+            .borrow_mut()
+            .as_mut()
+            .unwrap()
+            .set_speed(robot_ui.borrow().get_speed());
+        connect_speed_control(&robot_ui, &ui_container);
 
-        connect_robot_moving(&robot_ui, &ui_container, gdk::keys::constants::Down, |r| {
-            r.move_backward()
-        });
+        let keyboard_source = connect_robot_driving(&robot_ui, &ui_container);
 
-        connect_robot_moving(&robot_ui, &ui_container, gdk::keys::constants::Left, |r| {
-            r.rotate_left()
-        });
+        connect_emergency_stop(&robot_ui, &ui_container);
 
-        connect_robot_moving(&robot_ui, &ui_container, gdk::keys::constants::Right, |r| {
-            r.rotate_right()
-        });
+        connect_trim_calibration(&robot_ui, &ui_container, &robot_name);
+
+        connect_strafing(&robot_ui, &ui_container, keyboard_source);
+
+        connect_gamepad(&robot_ui, &ui_container, camera_list.as_ref().unwrap());
+
+        connect_minimap(&robot_ui, &ui_container);
+
+        connect_mission(&robot_ui, &ui_container);
+
+        connect_route(&robot_ui, &ui_container);
+
+        connect_script(&robot_ui, &ui_container);
+
+        connect_robot_update(&robot_ui, &ui_container);
 
         {
             let robot_ref = Rc::clone(&robot_ui);
@@ -224,53 +287,624 @@ fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn connect_robot_moving<MoveFunc: 'static>(
+fn drive_key(key_val: &gdk::keys::Key) -> Option<DriveKey> {
+    use gdk::keys::constants as keys;
+    if *key_val == keys::Up {
+        Some(DriveKey::Forward)
+    } else if *key_val == keys::Down {
+        Some(DriveKey::Backward)
+    } else if *key_val == keys::Left {
+        Some(DriveKey::Left)
+    } else if *key_val == keys::Right {
+        Some(DriveKey::Right)
+    } else {
+        None
+    }
+}
+
+fn print_move_error(result: Result<(), Box<dyn Error>>) {
+    if let Err(err) = result {
+        println!("Failed to send move command: {}", err);
+    }
+}
+
+fn update_driving(
     robot: &Rc<RefCell<Robot>>,
     ui_container: &Rc<RefCell<Option<WindowUi>>>,
-    target_key: gdk::keys::Key,
-    func: MoveFunc,
-) where
-    MoveFunc: Fn(&mut Robot),
-{
+    key_state: &KeyStateTracker,
+    source: SourceId,
+) {
+    let mut robot = robot.borrow_mut();
+    let mut ui = ui_container.borrow_mut();
+    if key_state.is_any_held() {
+        let cmd = key_state.command(robot.get_speed());
+        print_move_error(robot.submit_motion(source, cmd));
+        ui.as_mut().unwrap().disable_comboboxes();
+    } else {
+        print_move_error(robot.release_motion(source));
+        ui.as_mut().unwrap().enable_comboboxes();
+    }
+}
+
+// Returns the keyboard input source, strafing keys submit through it too
+fn connect_robot_driving(
+    robot: &Rc<RefCell<Robot>>,
+    ui_container: &Rc<RefCell<Option<WindowUi>>>,
+) -> SourceId {
+    use crate::gtk::WidgetExt;
+    let key_state = Rc::new(RefCell::new(KeyStateTracker::new()));
+    // a lost key release does not keep the keyboard in control
+    let source = robot.borrow_mut().register_input_source(
+        "keyboard",
+        KEYBOARD_PRIORITY,
+        Some(Duration::from_millis(KEYBOARD_IDLE_TIMEOUT_MS)),
+    );
+
+    let ui = ui_container.borrow_mut();
+    {
+        let robot_ref = Rc::clone(robot);
+        let ui_ref = Rc::clone(ui_container);
+        let key_state_ref = Rc::clone(&key_state);
+        ui.as_ref()
+            .unwrap()
+            .window
+            .connect_key_press_event(move |_, key| {
+                if let Some(drive_key) = drive_key(&key.get_keyval()) {
+                    // key repeat is passed too, it keeps the source fresh and picks up speed changes
+                    let mut key_state = key_state_ref.borrow_mut();
+                    key_state.press(drive_key);
+                    update_driving(&robot_ref, &ui_ref, &key_state, source);
+                }
+                gtk::Inhibit(false)
+            });
+    }
+    {
+        let robot_ref = Rc::clone(robot);
+        let ui_ref = Rc::clone(ui_container);
+        let key_state_ref = Rc::clone(&key_state);
+        ui.as_ref()
+            .unwrap()
+            .window
+            .connect_key_release_event(move |_, key| {
+                if let Some(drive_key) = drive_key(&key.get_keyval()) {
+                    let mut key_state = key_state_ref.borrow_mut();
+                    if key_state.release(drive_key) {
+                        update_driving(&robot_ref, &ui_ref, &key_state, source);
+                    }
+                }
+                gtk::Inhibit(false)
+            });
+    }
+    {
+        // release events are lost when the window is not focused
+        let robot_ref = Rc::clone(robot);
+        let ui_ref = Rc::clone(ui_container);
+        let key_state_ref = Rc::clone(&key_state);
+        ui.as_ref()
+            .unwrap()
+            .window
+            .connect_focus_out_event(move |_, _| {
+                let mut key_state = key_state_ref.borrow_mut();
+                if key_state.release_all() {
+                    update_driving(&robot_ref, &ui_ref, &key_state, source);
+                }
+                gtk::Inhibit(false)
+            });
+    }
+    source
+}
+
+fn strafe_direction(key_val: &gdk::keys::Key) -> Option<bool> {
+    use gdk::keys::constants as keys;
+    if *key_val == keys::a || *key_val == keys::A {
+        Some(true)
+    } else if *key_val == keys::d || *key_val == keys::D {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+// Strafing goes through the arbiter as the keyboard source, like the arrow keys
+fn connect_strafing(
+    robot: &Rc<RefCell<Robot>>,
+    ui_container: &Rc<RefCell<Option<WindowUi>>>,
+    source: SourceId,
+) {
+    use crate::gtk::WidgetExt;
+    let holonomic = robot.borrow().is_holonomic();
+    let mut ui = ui_container.borrow_mut();
+    let ui = ui.as_mut().unwrap();
+    ui.set_holonomic(holonomic);
+    if !holonomic {
+        return;
+    }
+    {
+        // key repeat sends the command again and keeps the motion watchdog fresh
+        let robot_ref = Rc::clone(robot);
+        ui.window.connect_key_press_event(move |_, key| {
+            if let Some(left) = strafe_direction(&key.get_keyval()) {
+                print_move_error(robot_ref.borrow_mut().submit_strafe(source, left));
+            }
+            gtk::Inhibit(false)
+        });
+    }
+    {
+        let robot_ref = Rc::clone(robot);
+        ui.window.connect_key_release_event(move |_, key| {
+            if strafe_direction(&key.get_keyval()).is_some() {
+                print_move_error(robot_ref.borrow_mut().release_motion(source));
+            }
+            gtk::Inhibit(false)
+        });
+    }
+    for (button, left) in &[
+        (&ui.strafe_left_button, true),
+        (&ui.strafe_right_button, false),
+    ] {
+        let left = *left;
+        let robot_ref = Rc::clone(robot);
+        button.connect_button_press_event(move |_, _| {
+            print_move_error(robot_ref.borrow_mut().submit_strafe(source, left));
+            gtk::Inhibit(false)
+        });
+        let robot_ref = Rc::clone(robot);
+        button.connect_button_release_event(move |_, _| {
+            print_move_error(robot_ref.borrow_mut().release_motion(source));
+            gtk::Inhibit(false)
+        });
+    }
+}
+
+fn engage_emergency_stop(robot: &Rc<RefCell<Robot>>, ui_container: &Rc<RefCell<Option<WindowUi>>>) {
+    robot.borrow_mut().emergency_stop();
+    let mut ui = ui_container.borrow_mut();
+    if let Some(ui) = ui.as_mut() {
+        ui.set_emergency_stop(true);
+        ui.set_control_source(None);
+        ui.enable_comboboxes();
+    }
+}
+
+fn connect_emergency_stop(
+    robot: &Rc<RefCell<Robot>>,
+    ui_container: &Rc<RefCell<Option<WindowUi>>>,
+) {
+    use crate::gtk::ButtonExt;
+    use crate::gtk::WidgetExt;
+    use gdk::keys::constants as keys;
+    let ui = ui_container.borrow_mut();
+    {
+        let robot_ref = Rc::clone(robot);
+        let ui_ref = Rc::clone(ui_container);
+        ui.as_ref()
+            .unwrap()
+            .window
+            .connect_key_press_event(move |_, key| {
+                if key.get_keyval() == keys::Escape {
+                    engage_emergency_stop(&robot_ref, &ui_ref);
+                }
+                gtk::Inhibit(false)
+            });
+    }
+    {
+        let robot_ref = Rc::clone(robot);
+        let ui_ref = Rc::clone(ui_container);
+        ui.as_ref()
+            .unwrap()
+            .estop_button
+            .connect_clicked(move |_| engage_emergency_stop(&robot_ref, &ui_ref));
+    }
+    {
+        let robot_ref = Rc::clone(robot);
+        let ui_ref = Rc::clone(ui_container);
+        ui.as_ref()
+            .unwrap()
+            .estop_reset_button
+            .connect_clicked(move |_| {
+                robot_ref.borrow_mut().reset_emergency_stop();
+                if let Some(ui) = ui_ref.borrow_mut().as_mut() {
+                    ui.set_emergency_stop(false);
+                }
+            });
+    }
+}
+
+fn connect_trim_calibration(
+    robot: &Rc<RefCell<Robot>>,
+    ui_container: &Rc<RefCell<Option<WindowUi>>>,
+    robot_name: &str,
+) {
+    use crate::gtk::ButtonExt;
+    use crate::gtk::SpinButtonExt;
+    let ui = ui_container.borrow_mut();
+    let ui = ui.as_ref().unwrap();
+    let drive_config = robot.borrow().get_drive_config();
+    ui.trim_dialog
+        .set_trim(drive_config.left_trim, drive_config.right_trim);
+    {
+        let ui_ref = Rc::clone(ui_container);
+        ui.trim_button.connect_clicked(move |_| {
+            if let Some(ui) = ui_ref.borrow().as_ref() {
+                ui.trim_dialog.show();
+            }
+        });
+    }
+    {
+        let robot_ref = Rc::clone(robot);
+        ui.trim_dialog.drive_button.connect_clicked(move |_| {
+            let mut robot = robot_ref.borrow_mut();
+            let speed = robot.get_speed();
+            let cmd = WheelCommand {
+                left_speed: speed,
+                left_dir: DIR_FORWARD,
+                right_speed: speed,
+                right_dir: DIR_FORWARD,
+            };
+            // the robot stops by itself, the operator measures the result
+            if let Err(err) = robot.move_for(cmd, Duration::from_millis(TRIM_CALIBRATION_DRIVE_MS))
+            {
+                println!("Failed to start calibration drive: {}", err);
+            }
+        });
+    }
+    {
+        let robot_ref = Rc::clone(robot);
+        let ui_ref = Rc::clone(ui_container);
+        let robot_name = String::from(robot_name);
+        ui.trim_dialog.apply_button.connect_clicked(move |_| {
+            let ui = ui_ref.borrow();
+            let dialog = &ui.as_ref().unwrap().trim_dialog;
+            // the dialog values are in centimeters
+            let distance = dialog.distance_spin.get_value() / 100.0;
+            let drift = dialog.drift_spin.get_value() / 100.0;
+            let mut robot = robot_ref.borrow_mut();
+            let mut drive_config = robot.get_drive_config();
+            match corrected_trim(&drive_config, distance, drift) {
+                Ok((left_trim, right_trim)) => {
+                    drive_config.left_trim = left_trim;
+                    drive_config.right_trim = right_trim;
+                    robot.set_drive_config(drive_config);
+                    dialog.set_trim(left_trim, right_trim);
+                    dialog.drift_spin.set_value(0.0);
+                    if let Err(err) =
+                        save_calibration(DEFAULT_CALIBRATION_FILE, &robot_name, &drive_config)
+                    {
+                        println!("Failed to save calibration: {}", err);
+                    }
+                }
+                Err(err) => println!("Wrong trim measurement: {}", err),
+            }
+        });
+    }
+}
+
+fn connect_speed_control(robot: &Rc<RefCell<Robot>>, ui_container: &Rc<RefCell<Option<WindowUi>>>) {
     use crate::gtk::WidgetExt;
-    let robot_move_ref = Rc::clone(robot);
-    let ui_move_ref = Rc::clone(ui_container);
+    use gdk::keys::constants as keys;
+    let robot_ref = Rc::clone(robot);
+    let ui_ref = Rc::clone(ui_container);
     let ui = ui_container.borrow_mut();
-    let stop_target_key = target_key.clone();
     ui.as_ref()
         .unwrap()
         .window
         .connect_key_press_event(move |_, key| {
             let key_val = key.get_keyval();
-            if key_val == target_key {
-                ui_move_ref
-                    .borrow_mut()
-                    .as_mut()
-                    .unwrap()
-                    .disable_comboboxes();
-                let r_ref = robot_move_ref.borrow_mut();
-                RefMut::map(r_ref, |r| {
-                    func(r);
-                    r
-                });
+            let mut robot = robot_ref.borrow_mut();
+            if key_val == keys::plus || key_val == keys::equal || key_val == keys::KP_Add {
+                robot.increase_speed();
+            } else if key_val == keys::minus || key_val == keys::KP_Subtract {
+                robot.decrease_speed();
+            } else {
+                return gtk::Inhibit(false);
             }
+            ui_ref
+                .borrow_mut()
+                .as_mut()
+                .unwrap()
+                .set_speed(robot.get_speed());
             gtk::Inhibit(false)
         });
-    let robot_stop_ref = Rc::clone(&robot);
-    let ui_stop_ref = Rc::clone(&ui_container);
+}
+
+fn save_snapshot(robot: &Robot, camera_id: u8) -> Result<String, Box<dyn Error>> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let path = format!("snapshot_{}_{}.png", camera_id, timestamp);
+    let data = robot
+        .get_encoded_image(camera_id, ".png", &[])?
+        .ok_or("No frame received from the camera yet")?;
+    std::fs::write(&path, &data)?;
+    Ok(path)
+}
+
+fn connect_gamepad(
+    robot: &Rc<RefCell<Robot>>,
+    ui_container: &Rc<RefCell<Option<WindowUi>>>,
+    camera_list: &Vec<u8>,
+) {
+    let mut gamepad = match Gamepad::open(GAMEPAD_DEVICE, GamepadConfig::default()) {
+        Ok(gamepad) => gamepad,
+        Err(err) => {
+            println!("Gamepad {} is not used: {}", GAMEPAD_DEVICE, err);
+            return;
+        }
+    };
+    println!("Gamepad {} connected", GAMEPAD_DEVICE);
+    let source = robot.borrow_mut().register_input_source(
+        "gamepad",
+        GAMEPAD_PRIORITY,
+        Some(Duration::from_millis(GAMEPAD_IDLE_TIMEOUT_MS)),
+    );
+    let robot_ref = Rc::clone(robot);
+    let ui_ref = Rc::clone(ui_container);
+    let snapshot_camera = camera_list.first().cloned();
+    let stop_latch: Cell<Option<WheelCommand>> = Cell::new(None);
+    glib::source::timeout_add_local(GAMEPAD_POLL_INTERVAL_MS, move || {
+        let mut ui = ui_ref.borrow_mut();
+        let ui = match ui.as_mut() {
+            Some(ui) => ui,
+            None => return glib::Continue(false),
+        };
+        let mut robot = robot_ref.borrow_mut();
+        for action in gamepad.poll() {
+            match action {
+                GamepadAction::Stop => {
+                    // stays stopped until the sticks are moved again
+                    print_move_error(robot.release_motion(source));
+                    stop_latch.set(Some(gamepad.command(robot.get_speed())));
+                }
+                GamepadAction::SpeedPreset(speed) => {
+                    robot.set_speed(speed);
+                    ui.set_speed(robot.get_speed());
+                }
+                GamepadAction::Snapshot => {
+                    if let Some(camera_id) = snapshot_camera {
+                        match save_snapshot(&robot, camera_id) {
+                            Ok(path) => println!("Snapshot saved to {}", path),
+                            Err(err) => println!("Failed to save snapshot: {}", err),
+                        }
+                    }
+                }
+            }
+        }
+        // submitted on every poll to keep the source fresh, the arbiter sends only changes
+        let cmd = gamepad.command(robot.get_speed());
+        if stop_latch.get().map_or(false, |latched| latched != cmd) {
+            stop_latch.set(None);
+        }
+        if stop_latch.get().is_none() {
+            print_move_error(robot.submit_motion(source, cmd));
+        }
+        if !gamepad.is_connected() {
+            println!("Gamepad {} disconnected", GAMEPAD_DEVICE);
+            print_move_error(robot.release_motion(source));
+            return glib::Continue(false);
+        }
+        glib::Continue(true)
+    });
+}
+
+fn connect_minimap(robot: &Rc<RefCell<Robot>>, ui_container: &Rc<RefCell<Option<WindowUi>>>) {
+    use crate::gtk::ButtonExt;
+    if let Some(ui) = ui_container.borrow_mut().as_mut() {
+        let robot = robot.borrow();
+        let fence = robot.get_geofence().and_then(|geofence| geofence.area);
+        ui.minimap.set_geofence(fence, robot.get_geofence_origin());
+    }
+    let robot_ref = Rc::clone(robot);
+    let ui_ref = Rc::clone(ui_container);
+    let ui = ui_container.borrow();
     ui.as_ref()
         .unwrap()
-        .window
-        .connect_key_release_event(move |_, key| {
-            let key_val = key.get_keyval();
-            if key_val == stop_target_key {
-                robot_stop_ref.borrow_mut().stop_moving();
-                ui_stop_ref
-                    .borrow_mut()
-                    .as_mut()
-                    .unwrap()
-                    .enable_comboboxes();
+        .minimap
+        .reset_button
+        .connect_clicked(move |_| {
+            let mut robot = robot_ref.borrow_mut();
+            robot.reset_pose();
+            if let Some(ui) = ui_ref.borrow_mut().as_mut() {
+                ui.minimap.reset();
+                // the fence stays in place, so it moves in the new pose frame
+                let fence = robot.get_geofence().and_then(|geofence| geofence.area);
+                ui.minimap.set_geofence(fence, robot.get_geofence_origin());
             }
-            gtk::Inhibit(false)
         });
 }
+
+fn connect_mission(robot: &Rc<RefCell<Robot>>, ui_container: &Rc<RefCell<Option<WindowUi>>>) {
+    use crate::gtk::ButtonExt;
+    let ui = ui_container.borrow();
+    let ui = ui.as_ref().unwrap();
+    {
+        let ui_ref = Rc::clone(ui_container);
+        ui.mission_button.connect_clicked(move |_| {
+            if let Some(ui) = ui_ref.borrow().as_ref() {
+                ui.mission_dialog.show();
+            }
+        });
+    }
+    {
+        let ui_ref = Rc::clone(ui_container);
+        ui.mission_dialog.load_button.connect_clicked(move |_| {
+            let ui = ui_ref.borrow();
+            let dialog = &ui.as_ref().unwrap().mission_dialog;
+            if let Some(path) = dialog.choose_file() {
+                match std::fs::read_to_string(&path) {
+                    Ok(text) => dialog.set_text(&text),
+                    Err(err) => println!("Failed to load mission {}: {}", path.display(), err),
+                }
+            }
+        });
+    }
+    {
+        let robot_ref = Rc::clone(robot);
+        let ui_ref = Rc::clone(ui_container);
+        ui.mission_dialog.start_button.connect_clicked(move |_| {
+            let text = ui_ref.borrow().as_ref().unwrap().mission_dialog.get_text();
+            let result =
+                parse_mission(&text).and_then(|steps| robot_ref.borrow_mut().start_mission(steps));
+            if let Err(err) = result {
+                println!("Failed to start mission: {}", err);
+            }
+        });
+    }
+    {
+        let robot_ref = Rc::clone(robot);
+        ui.mission_dialog
+            .pause_button
+            .connect_clicked(move |_| robot_ref.borrow_mut().pause_mission());
+    }
+    {
+        let robot_ref = Rc::clone(robot);
+        ui.mission_dialog
+            .resume_button
+            .connect_clicked(move |_| robot_ref.borrow_mut().resume_mission());
+    }
+    {
+        let robot_ref = Rc::clone(robot);
+        ui.mission_dialog
+            .abort_button
+            .connect_clicked(move |_| robot_ref.borrow_mut().abort_mission());
+    }
+}
+
+fn start_replay(robot: &mut Robot, reverse: bool, speed_scale: f64) {
+    let result = Route::load(DEFAULT_ROUTE_FILE)
+        .and_then(|route| robot.start_replay(route, reverse, speed_scale));
+    if let Err(err) = result {
+        println!("Failed to replay route {}: {}", DEFAULT_ROUTE_FILE, err);
+    }
+}
+
+fn connect_route(robot: &Rc<RefCell<Robot>>, ui_container: &Rc<RefCell<Option<WindowUi>>>) {
+    use crate::gtk::ButtonExt;
+    use crate::gtk::SpinButtonExt;
+    use crate::gtk::SpinButtonSignals;
+    use crate::gtk::ToggleButtonExt;
+    let ui = ui_container.borrow();
+    let ui = ui.as_ref().unwrap();
+    {
+        let robot_ref = Rc::clone(robot);
+        ui.record_button.connect_toggled(move |button| {
+            let mut robot = robot_ref.borrow_mut();
+            if button.get_active() {
+                robot.start_route_recording();
+                return;
+            }
+            let result = robot
+                .stop_route_recording()
+                .and_then(|route| route.save(DEFAULT_ROUTE_FILE));
+            match result {
+                Ok(()) => println!("Route saved to {}", DEFAULT_ROUTE_FILE),
+                Err(err) => println!("Failed to save route: {}", err),
+            }
+        });
+    }
+    {
+        let robot_ref = Rc::clone(robot);
+        let scale_spin = ui.replay_scale_spin.clone();
+        ui.replay_button.connect_clicked(move |_| {
+            start_replay(&mut robot_ref.borrow_mut(), false, scale_spin.get_value());
+        });
+    }
+    {
+        let robot_ref = Rc::clone(robot);
+        let scale_spin = ui.replay_scale_spin.clone();
+        ui.return_button.connect_clicked(move |_| {
+            start_replay(&mut robot_ref.borrow_mut(), true, scale_spin.get_value());
+        });
+    }
+    {
+        let robot_ref = Rc::clone(robot);
+        ui.replay_scale_spin.connect_value_changed(move |spin| {
+            robot_ref
+                .borrow_mut()
+                .set_replay_speed_scale(spin.get_value());
+        });
+    }
+}
+
+fn connect_script(robot: &Rc<RefCell<Robot>>, ui_container: &Rc<RefCell<Option<WindowUi>>>) {
+    use crate::gtk::ButtonExt;
+    use crate::gtk::SpinButtonExt;
+    let ui = ui_container.borrow();
+    let ui = ui.as_ref().unwrap();
+    {
+        let ui_ref = Rc::clone(ui_container);
+        ui.script_button.connect_clicked(move |_| {
+            if let Some(ui) = ui_ref.borrow().as_ref() {
+                ui.script_dialog.show();
+            }
+        });
+    }
+    {
+        let ui_ref = Rc::clone(ui_container);
+        ui.script_dialog.load_button.connect_clicked(move |_| {
+            let ui = ui_ref.borrow();
+            let dialog = &ui.as_ref().unwrap().script_dialog;
+            if let Some(path) = dialog.choose_file() {
+                match std::fs::read_to_string(&path) {
+                    Ok(text) => dialog.set_text(&text),
+                    Err(err) => println!("Failed to load script {}: {}", path.display(), err),
+                }
+            }
+        });
+    }
+    {
+        let robot_ref = Rc::clone(robot);
+        let ui_ref = Rc::clone(ui_container);
+        ui.script_dialog.run_button.connect_clicked(move |_| {
+            let ui = ui_ref.borrow();
+            let dialog = &ui.as_ref().unwrap().script_dialog;
+            let config = ScriptConfig {
+                time_limit: Duration::from_secs_f64(dialog.time_limit_spin.get_value()),
+            };
+            dialog.clear_output();
+            if let Err(err) = robot_ref
+                .borrow_mut()
+                .start_script(&dialog.get_text(), config)
+            {
+                dialog.append_output(&format!("Failed to start script: {}", err));
+            }
+        });
+    }
+    {
+        let robot_ref = Rc::clone(robot);
+        ui.script_dialog
+            .stop_button
+            .connect_clicked(move |_| robot_ref.borrow_mut().stop_script());
+    }
+}
+
+fn connect_robot_update(robot: &Rc<RefCell<Robot>>, ui_container: &Rc<RefCell<Option<WindowUi>>>) {
+    let robot_ref = Rc::clone(robot);
+    let ui_ref = Rc::clone(ui_container);
+    glib::source::timeout_add_local(ROBOT_UPDATE_INTERVAL_MS, move || {
+        let mut ui = ui_ref.borrow_mut();
+        let ui = match ui.as_mut() {
+            Some(ui) => ui,
+            None => return glib::Continue(false),
+        };
+        let mut robot = robot_ref.borrow_mut();
+        robot.update();
+        ui.set_control_source(robot.get_control_source().as_deref());
+        for event in robot.take_mission_events() {
+            println!("{}", event);
+        }
+        ui.mission_dialog.set_status(robot.get_mission_status());
+        ui.set_route_status(robot.is_route_recording(), robot.get_replay_progress());
+        for event in robot.take_script_events() {
+            ui.script_dialog.append_output(&event.to_string());
+        }
+        ui.script_dialog.set_running(robot.is_script_running());
+        ui.minimap.set_pose(robot.pose());
+        ui.minimap
+            .set_position_covariance(robot.pose_estimate().map(|estimate| {
+                let covariance = estimate.covariance;
+                [
+                    [covariance[0][0], covariance[0][1]],
+                    [covariance[1][0], covariance[1][1]],
+                ]
+            }));
+        glib::Continue(true)
+    });
+}
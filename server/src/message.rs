@@ -12,6 +12,13 @@ pub enum MessageId {
     RecvCameraProp = 8,
     Stop = 9,
     SetCameraProp = 10,
+    TimedMove = 11,
+    MoveDone = 12,
+    MoveSteps = 13,
+    StepsProgress = 14,
+    MultiMove = 15,
+    WheelEncoders = 16,
+    Imu = 17,
     Unknown,
 }
 impl From<u8> for MessageId {
@@ -27,6 +34,13 @@ impl From<u8> for MessageId {
             8 => return MessageId::RecvCameraProp,
             9 => return MessageId::Stop,
             10 => return MessageId::SetCameraProp,
+            11 => return MessageId::TimedMove,
+            12 => return MessageId::MoveDone,
+            13 => return MessageId::MoveSteps,
+            14 => return MessageId::StepsProgress,
+            15 => return MessageId::MultiMove,
+            16 => return MessageId::WheelEncoders,
+            17 => return MessageId::Imu,
             _ => return MessageId::Unknown,
         };
     }
@@ -0,0 +1,617 @@
+// Autonomous missions: the robot goes through the waypoints one by one, steering by
+// its estimated pose. A mission is written as text with one waypoint per line:
+//   drive 0.5          straight by meters, negative moves backward
+//   turn 90            in place by degrees, positive turns left
+//   goto 1.0 0.5 90    to the point in meters in the pose frame, the final heading
+//                      in degrees is optional
+// Any waypoint can end with "timeout SECONDS", lines starting with # are comments.
+use super::odometry;
+use odometry::{normalize_angle, Pose};
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+use std::fs;
+use std::time::{Duration, Instant};
+
+// the robot turns in place first when the target is farther aside
+const TURN_IN_PLACE_ANGLE: f64 = 20.0 * PI / 180.0;
+// proportional gains of the steering, 1/s
+const LINEAR_GAIN: f64 = 1.0;
+const ANGULAR_GAIN: f64 = 2.0;
+
+#[derive(Debug)]
+pub enum MissionErrors {
+    // line number starting from 1
+    WrongWaypoint(usize),
+    EmptyMission,
+}
+impl fmt::Display for MissionErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error: {:?}", self)
+    }
+}
+impl Error for MissionErrors {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waypoint {
+    Drive {
+        meters: f64,
+    },
+    Turn {
+        degrees: f64,
+    },
+    GoTo {
+        x: f64,
+        y: f64,
+        heading: Option<f64>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MissionStep {
+    pub waypoint: Waypoint,
+    pub timeout: Option<Duration>,
+}
+
+impl fmt::Display for MissionStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.waypoint {
+            Waypoint::Drive { meters } => write!(f, "drive {}", meters)?,
+            Waypoint::Turn { degrees } => write!(f, "turn {}", degrees)?,
+            Waypoint::GoTo { x, y, heading } => {
+                write!(f, "goto {} {}", x, y)?;
+                if let Some(heading) = heading {
+                    write!(f, " {}", heading)?;
+                }
+            }
+        }
+        if let Some(timeout) = self.timeout {
+            write!(f, " timeout {}", timeout.as_secs_f64())?;
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_mission(text: &str) -> Result<Vec<MissionStep>, Box<dyn Error>> {
+    let mut steps = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let wrong_waypoint = || Box::new(MissionErrors::WrongWaypoint(i + 1)) as Box<dyn Error>;
+        let mut words: Vec<&str> = line.split_whitespace().collect();
+        let mut timeout = None;
+        if words.len() >= 2 && words[words.len() - 2] == "timeout" {
+            let seconds = words[words.len() - 1]
+                .parse::<f64>()
+                .ok()
+                .filter(|seconds| *seconds > 0.0)
+                .ok_or_else(wrong_waypoint)?;
+            timeout = Some(Duration::from_secs_f64(seconds));
+            words.truncate(words.len() - 2);
+        }
+        if words.is_empty() {
+            return Err(wrong_waypoint());
+        }
+        let values = words[1..]
+            .iter()
+            .map(|word| word.parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| wrong_waypoint())?;
+        let waypoint = match (words[0], values.as_slice()) {
+            ("drive", [meters]) => Waypoint::Drive { meters: *meters },
+            ("turn", [degrees]) => Waypoint::Turn { degrees: *degrees },
+            ("goto", [x, y]) => Waypoint::GoTo {
+                x: *x,
+                y: *y,
+                heading: None,
+            },
+            ("goto", [x, y, heading]) => Waypoint::GoTo {
+                x: *x,
+                y: *y,
+                heading: Some(*heading),
+            },
+            _ => return Err(wrong_waypoint()),
+        };
+        steps.push(MissionStep {
+            waypoint: waypoint,
+            timeout: timeout,
+        });
+    }
+    if steps.is_empty() {
+        return Err(Box::new(MissionErrors::EmptyMission));
+    }
+    Ok(steps)
+}
+
+pub fn load_mission(path: &str) -> Result<Vec<MissionStep>, Box<dyn Error>> {
+    parse_mission(&fs::read_to_string(path)?)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MissionConfig {
+    // m/s and rad/s
+    pub linear_speed: f64,
+    pub angular_speed: f64,
+    // slower speeds are rounded to a stop by the wheel commands
+    pub min_linear_speed: f64,
+    pub min_angular_speed: f64,
+    // meters and radians
+    pub position_tolerance: f64,
+    pub heading_tolerance: f64,
+}
+
+impl Default for MissionConfig {
+    fn default() -> MissionConfig {
+        MissionConfig {
+            linear_speed: 0.03,
+            angular_speed: 0.4,
+            min_linear_speed: 0.005,
+            min_angular_speed: 0.1,
+            position_tolerance: 0.02,
+            heading_tolerance: 3.0f64.to_radians(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissionState {
+    Running,
+    Paused,
+    Completed,
+    Aborted,
+    // a waypoint was not reached in time
+    Failed,
+}
+
+impl MissionState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MissionState::Running => "running",
+            MissionState::Paused => "paused",
+            MissionState::Completed => "completed",
+            MissionState::Aborted => "aborted",
+            MissionState::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissionStatus {
+    pub state: MissionState,
+    // index of the current waypoint, equal to the count when completed
+    pub waypoint: usize,
+    pub waypoint_count: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissionEvent {
+    WaypointStarted(usize),
+    WaypointReached(usize),
+    WaypointTimedOut(usize),
+    Paused,
+    Resumed,
+    Completed,
+    Aborted,
+}
+
+impl fmt::Display for MissionEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MissionEvent::WaypointStarted(i) => write!(f, "waypoint {} started", i + 1),
+            MissionEvent::WaypointReached(i) => write!(f, "waypoint {} reached", i + 1),
+            MissionEvent::WaypointTimedOut(i) => write!(f, "waypoint {} timed out", i + 1),
+            MissionEvent::Paused => write!(f, "mission paused"),
+            MissionEvent::Resumed => write!(f, "mission resumed"),
+            MissionEvent::Completed => write!(f, "mission completed"),
+            MissionEvent::Aborted => write!(f, "mission aborted"),
+        }
+    }
+}
+
+// Goal of the current waypoint in the pose frame
+#[derive(Debug, Clone, Copy)]
+struct Target {
+    x: f64,
+    y: f64,
+    heading: Option<f64>,
+    // the position is reached driving backward
+    reverse: bool,
+    position_reached: bool,
+    // radians still to turn, the turns can be longer than half a circle
+    turn_remaining: Option<f64>,
+    last_heading: f64,
+}
+
+pub struct MissionExecutor {
+    steps: Vec<MissionStep>,
+    config: MissionConfig,
+    state: MissionState,
+    current: usize,
+    target: Option<Target>,
+    // time spent on the current waypoint before the pause
+    waypoint_elapsed: Duration,
+    waypoint_start: Instant,
+    events: Vec<MissionEvent>,
+}
+
+impl MissionExecutor {
+    pub fn new(steps: Vec<MissionStep>, config: MissionConfig, now: Instant) -> MissionExecutor {
+        MissionExecutor {
+            steps: steps,
+            config: config,
+            state: MissionState::Running,
+            current: 0,
+            target: None,
+            waypoint_elapsed: Duration::from_secs(0),
+            waypoint_start: now,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn status(&self) -> MissionStatus {
+        MissionStatus {
+            state: self.state,
+            waypoint: self.current,
+            waypoint_count: self.steps.len(),
+        }
+    }
+
+    pub fn steps(&self) -> &[MissionStep] {
+        &self.steps
+    }
+
+    pub fn is_finished(&self) -> bool {
+        match self.state {
+            MissionState::Running | MissionState::Paused => false,
+            _ => true,
+        }
+    }
+
+    pub fn take_events(&mut self) -> Vec<MissionEvent> {
+        std::mem::replace(&mut self.events, Vec::new())
+    }
+
+    pub fn pause(&mut self, now: Instant) {
+        if self.state == MissionState::Running {
+            self.waypoint_elapsed += now.duration_since(self.waypoint_start);
+            self.state = MissionState::Paused;
+            self.events.push(MissionEvent::Paused);
+        }
+    }
+
+    // The current waypoint goes on toward the same target
+    pub fn resume(&mut self, now: Instant) {
+        if self.state == MissionState::Paused {
+            self.waypoint_start = now;
+            self.state = MissionState::Running;
+            self.events.push(MissionEvent::Resumed);
+        }
+    }
+
+    pub fn abort(&mut self) {
+        if !self.is_finished() {
+            self.state = MissionState::Aborted;
+            self.events.push(MissionEvent::Aborted);
+        }
+    }
+
+    fn start_waypoint(&mut self, pose: Pose, now: Instant) -> Target {
+        let target = match self.steps[self.current].waypoint {
+            Waypoint::Drive { meters } => {
+                let end = pose.moved_by(meters, 0.0, 0.0);
+                Target {
+                    x: end.x,
+                    y: end.y,
+                    heading: None,
+                    reverse: meters < 0.0,
+                    position_reached: false,
+                    turn_remaining: None,
+                    last_heading: pose.heading,
+                }
+            }
+            Waypoint::Turn { degrees } => Target {
+                x: pose.x,
+                y: pose.y,
+                heading: None,
+                reverse: false,
+                position_reached: true,
+                turn_remaining: Some(degrees.to_radians()),
+                last_heading: pose.heading,
+            },
+            Waypoint::GoTo { x, y, heading } => Target {
+                x: x,
+                y: y,
+                heading: heading.map(|heading| normalize_angle(heading.to_radians())),
+                reverse: false,
+                position_reached: false,
+                turn_remaining: None,
+                last_heading: pose.heading,
+            },
+        };
+        self.waypoint_elapsed = Duration::from_secs(0);
+        self.waypoint_start = now;
+        self.events
+            .push(MissionEvent::WaypointStarted(self.current));
+        target
+    }
+
+    // Linear and angular velocity toward the target, None when it is reached
+    fn steer(&self, target: &mut Target, pose: Pose) -> Option<(f64, f64)> {
+        let config = &self.config;
+        if !target.position_reached {
+            let (dx, dy) = (target.x - pose.x, target.y - pose.y);
+            let distance = dx.hypot(dy);
+            let mut bearing = dy.atan2(dx);
+            if target.reverse {
+                bearing += PI;
+            }
+            let error = normalize_angle(bearing - pose.heading);
+            // a small overshoot puts the target behind, it is not worth turning around
+            let passed = error.abs() > PI / 2.0 && distance < 2.0 * config.position_tolerance;
+            if distance <= config.position_tolerance || passed {
+                target.position_reached = true;
+            } else if error.abs() > TURN_IN_PLACE_ANGLE {
+                return Some((0.0, config.angular_speed.copysign(error)));
+            } else {
+                let linear = (distance * LINEAR_GAIN)
+                    .max(config.min_linear_speed)
+                    .min(config.linear_speed);
+                let angular = (error * ANGULAR_GAIN)
+                    .max(-config.angular_speed)
+                    .min(config.angular_speed);
+                return Some((if target.reverse { -linear } else { linear }, angular));
+            }
+        }
+        if let Some(remaining) = target.turn_remaining.as_mut() {
+            // the heading changes by less than half a circle between the updates
+            *remaining -= normalize_angle(pose.heading - target.last_heading);
+            target.last_heading = pose.heading;
+            if remaining.abs() > config.heading_tolerance {
+                let angular = (remaining.abs() * ANGULAR_GAIN)
+                    .max(config.min_angular_speed)
+                    .min(config.angular_speed);
+                return Some((0.0, angular.copysign(*remaining)));
+            }
+        }
+        if let Some(heading) = target.heading {
+            let error = normalize_angle(heading - pose.heading);
+            if error.abs() > config.heading_tolerance {
+                let angular = (error.abs() * ANGULAR_GAIN)
+                    .max(config.min_angular_speed)
+                    .min(config.angular_speed);
+                return Some((0.0, angular.copysign(error)));
+            }
+        }
+        None
+    }
+
+    // Velocity to drive with, None stops the robot
+    pub fn update(&mut self, pose: Pose, now: Instant) -> Option<(f64, f64)> {
+        while self.state == MissionState::Running {
+            let mut target = match self.target {
+                Some(target) => target,
+                None => self.start_waypoint(pose, now),
+            };
+            let velocity = self.steer(&mut target, pose);
+            self.target = Some(target);
+            if velocity.is_some() {
+                let elapsed = self.waypoint_elapsed + now.duration_since(self.waypoint_start);
+                if let Some(timeout) = self.steps[self.current].timeout {
+                    if elapsed > timeout {
+                        self.events
+                            .push(MissionEvent::WaypointTimedOut(self.current));
+                        self.state = MissionState::Failed;
+                        return None;
+                    }
+                }
+                return velocity;
+            }
+            self.events
+                .push(MissionEvent::WaypointReached(self.current));
+            self.target = None;
+            self.current += 1;
+            if self.current == self.steps.len() {
+                self.state = MissionState::Completed;
+                self.events.push(MissionEvent::Completed);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::{assert_near, parse_error};
+    use super::*;
+    use odometry::BodyVelocity;
+
+    const STEP: Duration = Duration::from_millis(50);
+    const MAX_STEPS: usize = 20_000;
+
+    #[test]
+    fn parse_waypoints() {
+        let steps = parse_mission(
+            "# square corner\n\ndrive 0.5\n  turn -90 timeout 5\ngoto 1 0.5\ngoto 1 0.5 180\n",
+        )
+        .unwrap();
+        assert_eq!(
+            steps,
+            vec![
+                MissionStep {
+                    waypoint: Waypoint::Drive { meters: 0.5 },
+                    timeout: None,
+                },
+                MissionStep {
+                    waypoint: Waypoint::Turn { degrees: -90.0 },
+                    timeout: Some(Duration::from_secs(5)),
+                },
+                MissionStep {
+                    waypoint: Waypoint::GoTo {
+                        x: 1.0,
+                        y: 0.5,
+                        heading: None,
+                    },
+                    timeout: None,
+                },
+                MissionStep {
+                    waypoint: Waypoint::GoTo {
+                        x: 1.0,
+                        y: 0.5,
+                        heading: Some(180.0),
+                    },
+                    timeout: None,
+                },
+            ]
+        );
+        // the text form parses back to the same step
+        let text: Vec<String> = steps.iter().map(|step| step.to_string()).collect();
+        assert_eq!(parse_mission(&text.join("\n")).unwrap(), steps);
+    }
+
+    #[test]
+    fn parse_wrong_waypoints() {
+        let wrong = [
+            ("drive 1\ntimeout 5", 2),
+            ("timeout", 1),
+            ("drive", 1),
+            ("drive 1 2", 1),
+            ("drive one", 1),
+            ("fly 1", 1),
+            ("goto 1", 1),
+            ("turn 90 timeout 0", 1),
+            ("turn 90 timeout -1", 1),
+        ];
+        for (text, line) in wrong.iter() {
+            assert!(
+                matches!(
+                    parse_error(parse_mission(text)),
+                    Some(MissionErrors::WrongWaypoint(wrong_line)) if wrong_line == *line
+                ),
+                "{}",
+                text
+            );
+        }
+        assert!(parse_mission("# nothing\n\n").is_err());
+    }
+
+    struct Simulation {
+        executor: MissionExecutor,
+        pose: Pose,
+        now: Instant,
+        // heading change without wrapping around
+        turned: f64,
+    }
+
+    impl Simulation {
+        fn new(text: &str) -> Simulation {
+            let now = Instant::now();
+            Simulation {
+                executor: MissionExecutor::new(
+                    parse_mission(text).unwrap(),
+                    MissionConfig::default(),
+                    now,
+                ),
+                pose: Pose::default(),
+                now: now,
+                turned: 0.0,
+            }
+        }
+
+        // Drives the robot with the mission velocities until it stops
+        fn run(&mut self) {
+            for _ in 0..MAX_STEPS {
+                let (linear, angular) = match self.executor.update(self.pose, self.now) {
+                    Some(velocity) => velocity,
+                    None => return,
+                };
+                let velocity = BodyVelocity {
+                    linear_x: linear,
+                    linear_y: 0.0,
+                    angular: angular,
+                };
+                self.pose = self.pose.advanced(velocity, STEP.as_secs_f64());
+                self.turned += angular * STEP.as_secs_f64();
+                self.now += STEP;
+            }
+            panic!("the mission did not finish");
+        }
+    }
+
+    #[test]
+    fn drive_completes() {
+        let mut simulation = Simulation::new("drive 0.3\ndrive -0.1");
+        simulation.run();
+        assert_eq!(simulation.executor.status().state, MissionState::Completed);
+        assert_eq!(simulation.executor.status().waypoint, 2);
+        assert_near(simulation.pose.x, 0.2, 0.03);
+        assert_near(simulation.pose.y, 0.0, 0.01);
+    }
+
+    #[test]
+    fn full_turn_is_not_skipped() {
+        let mut simulation = Simulation::new("turn 360");
+        simulation.run();
+        assert_eq!(simulation.executor.status().state, MissionState::Completed);
+        assert_near(simulation.turned, 2.0 * PI, 0.1);
+    }
+
+    #[test]
+    fn long_turn_keeps_its_direction() {
+        let mut simulation = Simulation::new("turn 270");
+        simulation.run();
+        assert_near(simulation.turned, 1.5 * PI, 0.1);
+        let mut simulation = Simulation::new("turn -450");
+        simulation.run();
+        assert_near(simulation.turned, -2.5 * PI, 0.1);
+        assert_near(simulation.pose.heading, -PI / 2.0, 0.1);
+    }
+
+    #[test]
+    fn goto_reaches_position_and_heading() {
+        let mut simulation = Simulation::new("goto 0.3 0.2 90");
+        simulation.run();
+        assert_eq!(simulation.executor.status().state, MissionState::Completed);
+        assert_near(simulation.pose.x, 0.3, 0.03);
+        assert_near(simulation.pose.y, 0.2, 0.03);
+        assert_near(simulation.pose.heading, PI / 2.0, 0.06);
+    }
+
+    #[test]
+    fn waypoint_timeout_fails_the_mission() {
+        let mut simulation = Simulation::new("drive 1 timeout 2\ndrive 1");
+        simulation.run();
+        assert_eq!(simulation.executor.status().state, MissionState::Failed);
+        assert_eq!(simulation.executor.status().waypoint, 0);
+        let events = simulation.executor.take_events();
+        assert_eq!(events.last(), Some(&MissionEvent::WaypointTimedOut(0)));
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let mut simulation = Simulation::new("drive 0.1");
+        assert!(simulation
+            .executor
+            .update(simulation.pose, simulation.now)
+            .is_some());
+        simulation.executor.pause(simulation.now);
+        assert_eq!(
+            simulation.executor.update(simulation.pose, simulation.now),
+            None
+        );
+        simulation.executor.resume(simulation.now);
+        simulation.run();
+        assert_eq!(simulation.executor.status().state, MissionState::Completed);
+        assert_eq!(
+            simulation.executor.take_events(),
+            vec![
+                MissionEvent::WaypointStarted(0),
+                MissionEvent::Paused,
+                MissionEvent::Resumed,
+                MissionEvent::WaypointReached(0),
+                MissionEvent::Completed,
+            ]
+        );
+    }
+}
@@ -0,0 +1,223 @@
+extern crate opencv;
+extern crate tiny_http;
+use super::robot::ImageProcessor;
+use opencv::imgcodecs;
+use std::error::Error;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Method, Request, Response};
+
+const BOUNDARY: &str = "netbotframe";
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy)]
+pub struct MjpegConfig {
+    pub quality: i32,
+    pub fps: u32,
+}
+
+impl Default for MjpegConfig {
+    fn default() -> MjpegConfig {
+        MjpegConfig {
+            quality: 70,
+            fps: 10,
+        }
+    }
+}
+
+pub struct MjpegStreamer {
+    stop_flag: Arc<AtomicBool>,
+    thread_handle: Option<thread::JoinHandle<()>>,
+}
+
+impl MjpegStreamer {
+    pub(crate) fn start(
+        addr: &str,
+        config: MjpegConfig,
+        image_processor: Arc<Mutex<ImageProcessor>>,
+    ) -> Result<MjpegStreamer, Box<dyn Error>> {
+        let server = tiny_http::Server::http(addr).map_err(|err| err.to_string())?;
+        eprintln!("MJPEG streams available on http://{}/", addr);
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let stop_flag_clone = Arc::clone(&stop_flag);
+        let thread_handle = thread::spawn(move || {
+            while !stop_flag_clone.load(Ordering::SeqCst) {
+                match server.recv_timeout(ACCEPT_INTERVAL) {
+                    Ok(Some(request)) => {
+                        process_request(request, config, &image_processor, &stop_flag_clone)
+                    }
+                    Ok(None) => (),
+                    Err(err) => {
+                        eprintln!("MJPEG server failed: {}", err);
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(MjpegStreamer {
+            stop_flag: stop_flag,
+            thread_handle: Some(thread_handle),
+        })
+    }
+
+    pub fn stop(&mut self) {
+        self.stop_flag.store(true, Ordering::SeqCst);
+        self.thread_handle.take().map(thread::JoinHandle::join);
+    }
+}
+
+impl Drop for MjpegStreamer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn process_request(
+    request: Request,
+    config: MjpegConfig,
+    image_processor: &Arc<Mutex<ImageProcessor>>,
+    stop_flag: &Arc<AtomicBool>,
+) {
+    let url = String::from(request.url());
+    let mut url_parts = url.splitn(2, '?');
+    let path = url_parts.next().unwrap_or("");
+    let query = url_parts.next().unwrap_or("");
+
+    if *request.method() != Method::Get {
+        respond(request, Response::from_string("").with_status_code(405));
+        return;
+    }
+    if path == "/" {
+        let camera_ids = image_processor.lock().unwrap().get_camera_ids();
+        let mut page = String::from("<html><head><title>netbot</title></head><body>");
+        for camera_id in camera_ids {
+            page += &format!("<p>camera {0}</p><img src=\"/camera/{0}.mjpg\">", camera_id);
+        }
+        page += "</body></html>";
+        let content_type = Header::from_bytes(&b"Content-Type"[..], &b"text/html"[..]).unwrap();
+        respond(
+            request,
+            Response::from_string(page).with_header(content_type),
+        );
+        return;
+    }
+
+    let camera_id = path
+        .strip_prefix("/camera/")
+        .and_then(|name| name.strip_suffix(".mjpg"))
+        .and_then(|id| id.parse::<u8>().ok())
+        .filter(|id| {
+            image_processor
+                .lock()
+                .unwrap()
+                .get_frame_info(*id)
+                .is_some()
+        });
+    let camera_id = match camera_id {
+        Some(camera_id) => camera_id,
+        None => {
+            respond(
+                request,
+                Response::from_string("unknown camera").with_status_code(404),
+            );
+            return;
+        }
+    };
+
+    let mut stream_config = config;
+    for param in query.split('&') {
+        let mut key_value = param.splitn(2, '=');
+        let key = key_value.next();
+        let value = key_value.next().and_then(|v| v.parse::<u32>().ok());
+        match (key, value) {
+            (Some("quality"), Some(quality)) if quality <= 100 => {
+                stream_config.quality = quality as i32
+            }
+            (Some("fps"), Some(fps)) if fps > 0 => stream_config.fps = fps,
+            _ => (),
+        }
+    }
+
+    let image_processor = Arc::clone(image_processor);
+    let stop_flag = Arc::clone(stop_flag);
+    thread::spawn(move || {
+        stream_camera(
+            request,
+            camera_id,
+            stream_config,
+            &image_processor,
+            &stop_flag,
+        )
+    });
+}
+
+fn stream_camera(
+    request: Request,
+    camera_id: u8,
+    config: MjpegConfig,
+    image_processor: &Arc<Mutex<ImageProcessor>>,
+    stop_flag: &Arc<AtomicBool>,
+) {
+    // the response is written by hand because the chunked encoder buffers frames
+    let mut writer = request.into_writer();
+    let header = format!(
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: multipart/x-mixed-replace; boundary={}\r\n\
+         Cache-Control: no-cache\r\n\
+         Connection: close\r\n\r\n",
+        BOUNDARY
+    );
+    if writer.write_all(header.as_bytes()).is_err() {
+        return;
+    }
+
+    let frame_interval = Duration::from_secs_f64(1.0 / config.fps as f64);
+    let mut last_number: Option<u64> = None;
+    while !stop_flag.load(Ordering::SeqCst) {
+        let frame_start = Instant::now();
+        let frame = {
+            let processor = image_processor.lock().unwrap();
+            let number = processor.get_frame_info(camera_id).map(|info| info.number);
+            if number != last_number {
+                last_number = number;
+                processor
+                    .encode_image(
+                        camera_id,
+                        ".jpg",
+                        &[imgcodecs::IMWRITE_JPEG_QUALITY, config.quality],
+                    )
+                    .unwrap_or(None)
+            } else {
+                None
+            }
+        };
+        if let Some(data) = frame {
+            let part_header = format!(
+                "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                BOUNDARY,
+                data.len()
+            );
+            let sent = writer
+                .write_all(part_header.as_bytes())
+                .and_then(|_| writer.write_all(&data))
+                .and_then(|_| writer.write_all(b"\r\n"))
+                .and_then(|_| writer.flush());
+            if sent.is_err() {
+                // viewer has gone
+                break;
+            }
+        }
+        frame_interval
+            .checked_sub(frame_start.elapsed())
+            .map(thread::sleep);
+    }
+}
+
+fn respond(request: Request, response: Response<std::io::Cursor<Vec<u8>>>) {
+    if let Err(err) = request.respond(response) {
+        eprintln!("Failed to send MJPEG response: {}", err);
+    }
+}
@@ -1,5 +1,7 @@
+use super::kinematics;
 use super::message;
-use message::{Message, MessageId, SendMessage};
+use kinematics::WheelState;
+use message::{Message, MessageId, RecvMessage, SendMessage};
 use std::any::Any;
 
 #[derive(Debug)]
@@ -53,3 +55,258 @@ impl SendMessage for MoveMsg {
         return Some(&self.data[..]);
     }
 }
+
+// The robot moves for duration_ms and stops by itself
+#[derive(Debug)]
+pub struct TimedMoveMsg {
+    pub id: u8,
+    pub move_id: u32,
+    pub left_speed: u8,
+    pub left_dir: u8,
+    pub right_speed: u8,
+    pub right_dir: u8,
+    pub duration_ms: u32,
+    data: Vec<u8>,
+}
+
+impl TimedMoveMsg {
+    pub fn new() -> TimedMoveMsg {
+        let id_value = MessageId::TimedMove as u8;
+        TimedMoveMsg {
+            id: id_value,
+            move_id: 0,
+            left_speed: 0,
+            left_dir: 0,
+            right_speed: 0,
+            right_dir: 0,
+            duration_ms: 0,
+            data: Vec::new(),
+        }
+    }
+}
+
+impl Message for TimedMoveMsg {
+    fn id(&self) -> u8 {
+        return self.id;
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_mut_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl SendMessage for TimedMoveMsg {
+    fn size(&self) -> u32 {
+        return 4 + 1 + 1 + 1 + 1 + 4;
+    }
+
+    fn to_bytes(&mut self) -> Option<&[u8]> {
+        self.data.extend_from_slice(&self.move_id.to_be_bytes());
+        self.data.push(self.left_speed);
+        self.data.push(self.left_dir);
+        self.data.push(self.right_speed);
+        self.data.push(self.right_dir);
+        self.data.extend_from_slice(&self.duration_ms.to_be_bytes());
+        return Some(&self.data[..]);
+    }
+}
+
+// Sent by the robot when a timed move finished or was interrupted by another command
+#[derive(Debug)]
+pub struct MoveDoneMsg {
+    pub id: u8,
+    pub move_id: u32,
+    pub completed: bool,
+}
+
+impl MoveDoneMsg {
+    pub fn new() -> MoveDoneMsg {
+        let id_value = MessageId::MoveDone as u8;
+        MoveDoneMsg {
+            id: id_value,
+            move_id: 0,
+            completed: false,
+        }
+    }
+}
+
+impl Message for MoveDoneMsg {
+    fn id(&self) -> u8 {
+        return self.id;
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_mut_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl RecvMessage for MoveDoneMsg {
+    fn from_bytes(&mut self, buf: &[u8]) {
+        let tmp = slice_as_array!(&buf[0..4], [u8; 4]).expect("MoveDoneMsg wrong data");
+        self.move_id = u32::from_be_bytes(*tmp);
+        self.completed = buf[4] != 0;
+    }
+}
+
+// Exact number of half-steps per wheel, the sign sets the direction,
+// rate is in half-steps per second for the wheel with more steps
+#[derive(Debug)]
+pub struct MoveStepsMsg {
+    pub id: u8,
+    pub move_id: u32,
+    pub left_steps: i32,
+    pub right_steps: i32,
+    pub rate: u16,
+    data: Vec<u8>,
+}
+
+impl MoveStepsMsg {
+    pub fn new() -> MoveStepsMsg {
+        let id_value = MessageId::MoveSteps as u8;
+        MoveStepsMsg {
+            id: id_value,
+            move_id: 0,
+            left_steps: 0,
+            right_steps: 0,
+            rate: 0,
+            data: Vec::new(),
+        }
+    }
+}
+
+impl Message for MoveStepsMsg {
+    fn id(&self) -> u8 {
+        return self.id;
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_mut_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl SendMessage for MoveStepsMsg {
+    fn size(&self) -> u32 {
+        return 4 + 4 + 4 + 2;
+    }
+
+    fn to_bytes(&mut self) -> Option<&[u8]> {
+        self.data.extend_from_slice(&self.move_id.to_be_bytes());
+        self.data.extend_from_slice(&self.left_steps.to_be_bytes());
+        self.data.extend_from_slice(&self.right_steps.to_be_bytes());
+        self.data.extend_from_slice(&self.rate.to_be_bytes());
+        return Some(&self.data[..]);
+    }
+}
+
+pub const STEPS_RUNNING: u8 = 0;
+pub const STEPS_COMPLETED: u8 = 1;
+pub const STEPS_INTERRUPTED: u8 = 2;
+
+// Sent by the robot periodically during a steps move and once at its end
+#[derive(Debug)]
+pub struct StepsProgressMsg {
+    pub id: u8,
+    pub move_id: u32,
+    pub left_steps: u32,
+    pub right_steps: u32,
+    pub status: u8,
+}
+
+impl StepsProgressMsg {
+    pub fn new() -> StepsProgressMsg {
+        let id_value = MessageId::StepsProgress as u8;
+        StepsProgressMsg {
+            id: id_value,
+            move_id: 0,
+            left_steps: 0,
+            right_steps: 0,
+            status: STEPS_RUNNING,
+        }
+    }
+}
+
+impl Message for StepsProgressMsg {
+    fn id(&self) -> u8 {
+        return self.id;
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_mut_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl RecvMessage for StepsProgressMsg {
+    fn from_bytes(&mut self, buf: &[u8]) {
+        let tmp = slice_as_array!(&buf[0..4], [u8; 4]).expect("StepsProgressMsg wrong data");
+        self.move_id = u32::from_be_bytes(*tmp);
+        let tmp = slice_as_array!(&buf[4..8], [u8; 4]).expect("StepsProgressMsg wrong data");
+        self.left_steps = u32::from_be_bytes(*tmp);
+        let tmp = slice_as_array!(&buf[8..12], [u8; 4]).expect("StepsProgressMsg wrong data");
+        self.right_steps = u32::from_be_bytes(*tmp);
+        self.status = buf[12];
+    }
+}
+
+// Speeds and directions of every wheel for chassis with more than two wheels
+#[derive(Debug)]
+pub struct MultiMoveMsg {
+    pub id: u8,
+    pub wheels: Vec<WheelState>,
+    data: Vec<u8>,
+}
+
+impl MultiMoveMsg {
+    pub fn new() -> MultiMoveMsg {
+        let id_value = MessageId::MultiMove as u8;
+        MultiMoveMsg {
+            id: id_value,
+            wheels: Vec::new(),
+            data: Vec::new(),
+        }
+    }
+}
+
+impl Message for MultiMoveMsg {
+    fn id(&self) -> u8 {
+        return self.id;
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_mut_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl SendMessage for MultiMoveMsg {
+    fn size(&self) -> u32 {
+        return 1 + 2 * self.wheels.len() as u32;
+    }
+
+    fn to_bytes(&mut self) -> Option<&[u8]> {
+        self.data.push(self.wheels.len() as u8);
+        for wheel in &self.wheels {
+            self.data.push(wheel.speed);
+            self.data.push(wheel.dir);
+        }
+        return Some(&self.data[..]);
+    }
+}
@@ -0,0 +1,194 @@
+// Dead reckoning: the pose is integrated from the body velocity the robot was commanded
+// to move with, or from the wheel steps it reported for step moves. Wheel slip is not
+// seen, so the estimate drifts over time. The origin is the robot position at the last
+// reset, x points along the heading at that time and y to the left, heading is in
+// radians counterclockwise.
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::Instant;
+
+pub const DEFAULT_POSE_LOG_FILE: &str = "netbot-pose.csv";
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Pose {
+    // meters
+    pub x: f64,
+    pub y: f64,
+    // radians in -pi..pi
+    pub heading: f64,
+}
+
+// linear_x is forward and linear_y to the left in m/s, angular is in rad/s
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BodyVelocity {
+    pub linear_x: f64,
+    pub linear_y: f64,
+    pub angular: f64,
+}
+
+// Angle in -pi..pi
+pub fn normalize_angle(angle: f64) -> f64 {
+    let angle = angle % (2.0 * PI);
+    if angle > PI {
+        angle - 2.0 * PI
+    } else if angle <= -PI {
+        angle + 2.0 * PI
+    } else {
+        angle
+    }
+}
+
+impl Pose {
+    // Pose after moving by forward and left meters and rotating by the angle in the
+    // robot frame, the motion goes along an arc as with constant wheel speeds
+    pub fn moved_by(&self, forward: f64, left: f64, rotation: f64) -> Pose {
+        // sin(a) / a and (1 - cos(a)) / a, both tend to the straight line for small angles
+        let (sin_ratio, cos_ratio) = if rotation.abs() < 1e-9 {
+            (1.0, rotation / 2.0)
+        } else {
+            (rotation.sin() / rotation, (1.0 - rotation.cos()) / rotation)
+        };
+        let dx = forward * sin_ratio - left * cos_ratio;
+        let dy = forward * cos_ratio + left * sin_ratio;
+        let (sin, cos) = self.heading.sin_cos();
+        Pose {
+            x: self.x + dx * cos - dy * sin,
+            y: self.y + dx * sin + dy * cos,
+            heading: normalize_angle(self.heading + rotation),
+        }
+    }
+
+    // The same pose in the frame with the origin at the given pose and x along its
+    // heading
+    pub fn relative_to(&self, origin: Pose) -> Pose {
+        let (sin, cos) = origin.heading.sin_cos();
+        let (dx, dy) = (self.x - origin.x, self.y - origin.y);
+        Pose {
+            x: dx * cos + dy * sin,
+            y: dy * cos - dx * sin,
+            heading: normalize_angle(self.heading - origin.heading),
+        }
+    }
+
+    // Pose after moving with the constant velocity for the time in seconds
+    pub fn advanced(&self, velocity: BodyVelocity, seconds: f64) -> Pose {
+        self.moved_by(
+            velocity.linear_x * seconds,
+            velocity.linear_y * seconds,
+            velocity.angular * seconds,
+        )
+    }
+}
+
+pub struct Odometry {
+    pose: Pose,
+    velocity: BodyVelocity,
+    // the robot stops by itself at this time, used by timed moves
+    motion_end: Option<Instant>,
+    last_update: Instant,
+}
+
+impl Odometry {
+    pub fn new() -> Odometry {
+        Odometry {
+            pose: Pose::default(),
+            velocity: BodyVelocity::default(),
+            motion_end: None,
+            last_update: Instant::now(),
+        }
+    }
+
+    // Moves the origin to the current pose, the current motion goes on
+    pub fn reset(&mut self, now: Instant) {
+        self.update(now);
+        self.pose = Pose::default();
+    }
+
+    pub fn set_velocity(
+        &mut self,
+        velocity: BodyVelocity,
+        motion_end: Option<Instant>,
+        now: Instant,
+    ) {
+        self.update(now);
+        self.velocity = velocity;
+        self.motion_end = motion_end;
+    }
+
+    // Adds the displacement reported by the robot in the robot frame
+    pub fn add_displacement(&mut self, forward: f64, left: f64, rotation: f64, now: Instant) {
+        self.update(now);
+        self.pose = self.pose.moved_by(forward, left, rotation);
+    }
+
+    pub fn update(&mut self, now: Instant) {
+        self.pose = self.pose_at(now);
+        if self.motion_end.map_or(false, |end| end <= now) {
+            self.velocity = BodyVelocity::default();
+            self.motion_end = None;
+        }
+        self.last_update = now;
+    }
+
+    pub fn pose_at(&self, now: Instant) -> Pose {
+        let end = match self.motion_end {
+            Some(end) if end < now => end,
+            _ => now,
+        };
+        let seconds = end
+            .checked_duration_since(self.last_update)
+            .map_or(0.0, |elapsed| elapsed.as_secs_f64());
+        self.pose.advanced(self.velocity, seconds)
+    }
+
+    pub fn velocity_at(&self, now: Instant) -> BodyVelocity {
+        match self.motion_end {
+            Some(end) if end <= now => BodyVelocity::default(),
+            _ => self.velocity,
+        }
+    }
+}
+
+// CSV log with the pose at the time every camera frame was received
+pub struct PoseLog {
+    writer: BufWriter<File>,
+    start: Instant,
+}
+
+impl PoseLog {
+    pub fn create(path: &str) -> Result<PoseLog, Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "time,camera_id,frame,x,y,heading")?;
+        Ok(PoseLog {
+            writer: writer,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn log_frame(
+        &mut self,
+        camera_id: u8,
+        frame_number: u64,
+        pose: Pose,
+        now: Instant,
+    ) -> Result<(), Box<dyn Error>> {
+        writeln!(
+            self.writer,
+            "{:.3},{},{},{:.4},{:.4},{:.4}",
+            now.duration_since(self.start).as_secs_f64(),
+            camera_id,
+            frame_number,
+            pose.x,
+            pose.y,
+            pose.heading
+        )?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+// Robot profile is the chassis drive type, the robot client reports it in the handshake
+// and the console settings can override it. Every drive type has its own kinematics
+// converting the body velocity to wheel speeds. Wheels order for 4-wheel chassis is
+// front left, front right, rear left, rear right.
+use super::kinematics;
+use kinematics::DriveConfig;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriveType {
+    Differential = 1,
+    SkidSteer = 2,
+    Mecanum = 3,
+}
+
+impl DriveType {
+    pub fn from_u8(value: u8) -> Option<DriveType> {
+        match value {
+            1 => Some(DriveType::Differential),
+            2 => Some(DriveType::SkidSteer),
+            3 => Some(DriveType::Mecanum),
+            _ => None,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<DriveType> {
+        match name {
+            "differential" => Some(DriveType::Differential),
+            "skid-steer" => Some(DriveType::SkidSteer),
+            "mecanum" => Some(DriveType::Mecanum),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DriveType::Differential => "differential",
+            DriveType::SkidSteer => "skid-steer",
+            DriveType::Mecanum => "mecanum",
+        }
+    }
+}
+
+impl fmt::Display for DriveType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+pub trait Kinematics {
+    fn wheel_count(&self) -> usize;
+
+    // True when the chassis can move sideways
+    fn is_holonomic(&self) -> bool {
+        false
+    }
+
+    // Wheel angular speeds in rad/s for the body velocity, linear_x is forward and
+    // linear_y is to the left in m/s, angular is counterclockwise in rad/s.
+    // linear_y is ignored by non holonomic chassis.
+    fn wheel_speeds(&self, linear_x: f64, linear_y: f64, angular: f64) -> Vec<f64>;
+
+    // Body velocity (linear_x, linear_y, angular) for the wheel angular speeds in rad/s
+    fn body_velocity(&self, wheel_speeds: &[f64]) -> (f64, f64, f64);
+}
+
+fn wheel(wheel_speeds: &[f64], index: usize) -> f64 {
+    wheel_speeds.get(index).cloned().unwrap_or(0.0)
+}
+
+pub struct DifferentialDrive {
+    config: DriveConfig,
+}
+
+impl Kinematics for DifferentialDrive {
+    fn wheel_count(&self) -> usize {
+        2
+    }
+
+    fn wheel_speeds(&self, linear_x: f64, _linear_y: f64, angular: f64) -> Vec<f64> {
+        let (left, right) = self.config.wheel_speeds(linear_x, angular);
+        vec![left, right]
+    }
+
+    fn body_velocity(&self, wheel_speeds: &[f64]) -> (f64, f64, f64) {
+        let (linear, angular) = self
+            .config
+            .body_velocity(wheel(wheel_speeds, 0), wheel(wheel_speeds, 1));
+        (linear, 0.0, angular)
+    }
+}
+
+// Four wheels, the wheels on one side are driven together and slip when turning
+pub struct SkidSteerDrive {
+    config: DriveConfig,
+}
+
+impl Kinematics for SkidSteerDrive {
+    fn wheel_count(&self) -> usize {
+        4
+    }
+
+    fn wheel_speeds(&self, linear_x: f64, _linear_y: f64, angular: f64) -> Vec<f64> {
+        let (left, right) = self.config.wheel_speeds(linear_x, angular);
+        vec![left, right, left, right]
+    }
+
+    fn body_velocity(&self, wheel_speeds: &[f64]) -> (f64, f64, f64) {
+        let left = (wheel(wheel_speeds, 0) + wheel(wheel_speeds, 2)) / 2.0;
+        let right = (wheel(wheel_speeds, 1) + wheel(wheel_speeds, 3)) / 2.0;
+        let (linear, angular) = self.config.body_velocity(left, right);
+        (linear, 0.0, angular)
+    }
+}
+
+// Four mecanum wheels with rollers at 45 degrees, rollers of the front left wheel
+// point to the front right when looking from above
+pub struct MecanumDrive {
+    config: DriveConfig,
+}
+
+impl Kinematics for MecanumDrive {
+    fn wheel_count(&self) -> usize {
+        4
+    }
+
+    fn is_holonomic(&self) -> bool {
+        true
+    }
+
+    fn wheel_speeds(&self, linear_x: f64, linear_y: f64, angular: f64) -> Vec<f64> {
+        let rotation = angular * (self.config.track_width + self.config.wheel_base) / 2.0;
+        let radius = self.config.wheel_radius;
+        vec![
+            (linear_x - linear_y - rotation) / radius,
+            (linear_x + linear_y + rotation) / radius,
+            (linear_x + linear_y - rotation) / radius,
+            (linear_x - linear_y + rotation) / radius,
+        ]
+    }
+
+    fn body_velocity(&self, wheel_speeds: &[f64]) -> (f64, f64, f64) {
+        let (front_left, front_right) = (wheel(wheel_speeds, 0), wheel(wheel_speeds, 1));
+        let (rear_left, rear_right) = (wheel(wheel_speeds, 2), wheel(wheel_speeds, 3));
+        let radius = self.config.wheel_radius;
+        let half_size = (self.config.track_width + self.config.wheel_base) / 2.0;
+        (
+            (front_left + front_right + rear_left + rear_right) * radius / 4.0,
+            (-front_left + front_right + rear_left - rear_right) * radius / 4.0,
+            (-front_left + front_right - rear_left + rear_right) * radius / (4.0 * half_size),
+        )
+    }
+}
+
+pub fn kinematics_for(drive_type: DriveType, config: DriveConfig) -> Box<dyn Kinematics> {
+    match drive_type {
+        DriveType::Differential => Box::new(DifferentialDrive { config: config }),
+        DriveType::SkidSteer => Box::new(SkidSteerDrive { config: config }),
+        DriveType::Mecanum => Box::new(MecanumDrive { config: config }),
+    }
+}
@@ -0,0 +1,110 @@
+// Moves the wheel speeds toward the target with a limited acceleration, so the
+// steppers do not skip steps when the robot starts, stops or changes direction.
+// Speeds are signed here, positive is forward, and in 0..max_speed units.
+use super::kinematics;
+use kinematics::{WheelCommand, DIR_BACKWARD, DIR_FORWARD};
+use std::time::Instant;
+
+fn to_signed(speed: u8, dir: u8) -> f64 {
+    if dir == DIR_BACKWARD {
+        -(speed as f64)
+    } else {
+        speed as f64
+    }
+}
+
+fn to_command(left: f64, right: f64) -> WheelCommand {
+    let to_dir = |speed: f64| {
+        if speed > 0.0 {
+            DIR_FORWARD
+        } else {
+            DIR_BACKWARD
+        }
+    };
+    WheelCommand {
+        left_speed: left.abs().round() as u8,
+        left_dir: to_dir(left),
+        right_speed: right.abs().round() as u8,
+        right_dir: to_dir(right),
+    }
+}
+
+fn approach(current: f64, target: f64, max_change: f64) -> f64 {
+    if (target - current).abs() <= max_change {
+        target
+    } else if target > current {
+        current + max_change
+    } else {
+        current - max_change
+    }
+}
+
+pub struct SpeedRamp {
+    // speed units per second
+    acceleration: f64,
+    current: (f64, f64),
+    target: (f64, f64),
+    last_sent: WheelCommand,
+    last_update: Instant,
+}
+
+impl SpeedRamp {
+    pub fn new(acceleration: f64) -> SpeedRamp {
+        SpeedRamp {
+            acceleration: acceleration,
+            current: (0.0, 0.0),
+            target: (0.0, 0.0),
+            last_sent: WheelCommand::default(),
+            last_update: Instant::now(),
+        }
+    }
+
+    pub fn set_acceleration(&mut self, acceleration: f64) {
+        self.acceleration = acceleration;
+    }
+
+    pub fn get_acceleration(&self) -> f64 {
+        self.acceleration
+    }
+
+    pub fn set_target(&mut self, cmd: WheelCommand, now: Instant) {
+        if self.is_done() {
+            // the ramp was idle, the time since the last update is not a part of it
+            self.last_update = now;
+        }
+        self.target = (
+            to_signed(cmd.left_speed, cmd.left_dir),
+            to_signed(cmd.right_speed, cmd.right_dir),
+        );
+    }
+
+    // Jumps to the command without ramping, used when the speed was set by other means
+    pub fn reset_to(&mut self, cmd: WheelCommand, now: Instant) {
+        self.set_target(cmd, now);
+        self.current = self.target;
+        self.last_sent = to_command(self.current.0, self.current.1);
+        self.last_update = now;
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.current == self.target
+    }
+
+    // Returns the command to send when the rounded wheel speeds changed
+    pub fn update(&mut self, now: Instant) -> Option<WheelCommand> {
+        let elapsed = now.duration_since(self.last_update).as_secs_f64();
+        self.last_update = now;
+        let max_change = self.acceleration * elapsed;
+        self.current = (
+            approach(self.current.0, self.target.0, max_change),
+            approach(self.current.1, self.target.1, max_change),
+        );
+        let cmd = to_command(self.current.0, self.current.1);
+        if cmd != self.last_sent {
+            self.last_sent = cmd;
+            Some(cmd)
+        } else {
+            None
+        }
+    }
+}
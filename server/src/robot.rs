@@ -1,29 +1,170 @@
 extern crate opencv;
+use super::arbiter;
 use super::camera_msg;
 use super::camera_prop_msg;
+use super::ekf;
+use super::geofence;
 use super::image_msg;
+use super::kinematics;
 use super::message;
+use super::mission;
+use super::mjpeg;
 use super::move_msg;
+use super::odometry;
+use super::profile;
+use super::ramp;
+use super::route;
+use super::script;
 use super::server;
+use super::telemetry_msg;
+use arbiter::{MotionArbiter, MotionCommand, SourceId};
 use camera_msg::{GetCameraListMsg, RecvCameraListMsg};
 use camera_prop_msg::{GetCameraPropMsg, RecvCameraPropMsg, SetCameraPropMsg};
+use ekf::{FilterConfig, PoseEstimate, PoseFilter};
+use geofence::GeofenceConfig;
 use image_msg::RecvImageMsg;
+use kinematics::{DriveConfig, WheelCommand, WheelState, DIR_BACKWARD, DIR_FORWARD};
 use message::{HelloMsg, MessageId, RecvMessage, StopMsg};
-use move_msg::MoveMsg;
+use mission::{MissionConfig, MissionEvent, MissionExecutor, MissionStatus, MissionStep};
+use mjpeg::{MjpegConfig, MjpegStreamer};
+use move_msg::{MoveDoneMsg, MoveMsg, MoveStepsMsg, MultiMoveMsg, StepsProgressMsg, TimedMoveMsg};
+use odometry::{BodyVelocity, Odometry, Pose, PoseLog};
 use opencv::{core, imgcodecs, imgproc, prelude::*};
+use profile::{kinematics_for, DriveType};
+use ramp::SpeedRamp;
+use route::{Route, RoutePlayer, RouteRecorder};
+use script::{RobotCall, ScriptConfig, ScriptEvent, ScriptMotion, ScriptRunner, ScriptValue};
 use server::Server;
 use std::collections::HashMap;
 use std::error::Error;
-use std::net::Ipv4Addr;
+use std::fmt;
+use std::fs;
+use std::net::{Ipv4Addr, SocketAddr};
 use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
+use telemetry_msg::{ImuMsg, WheelEncodersMsg};
 
 type ResolutionsMap = HashMap<u8, Vec<(i32, i32)>>;
 
-struct ImageProcessor {
+pub const MIN_MOVE_SPEED: u8 = 1;
+pub const MAX_MOVE_SPEED: u8 = 100;
+pub const MOVE_SPEED_STEP: u8 = 5;
+// longer than the usual keyboard auto-repeat delay
+pub const DEFAULT_MOTION_WATCHDOG: Duration = Duration::from_millis(750);
+// speed units per second, from stop to the maximum speed in half a second
+pub const DEFAULT_ACCELERATION: f64 = 200.0;
+// missions drive below every operator input source
+pub const MISSION_PRIORITY: u8 = 5;
+pub const SCRIPT_PRIORITY: u8 = 5;
+// older script output is dropped when nobody takes it
+const MAX_SCRIPT_EVENTS: usize = 500;
+// added to the expected steps move duration before giving up waiting for it
+const STEPS_MOVE_TIMEOUT_MARGIN: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+pub enum RobotErrors {
+    Timeout,
+    EmergencyStop,
+    WrongArgument,
+    OutsideGeofence,
+    HandshakeFailed,
+}
+impl fmt::Display for RobotErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error: {:?}", self)
+    }
+}
+impl Error for RobotErrors {}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FrameInfo {
+    pub width: u16,
+    pub height: u16,
+    pub channels: u16,
+    pub encoded: bool,
+    pub number: u64,
+    // estimated robot pose when the frame was received
+    pub pose: Pose,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoveDone {
+    pub move_id: u32,
+    // false when the move was interrupted by another command
+    pub completed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepsStatus {
+    Running,
+    Completed,
+    Interrupted,
+}
+
+// Half-steps done by each wheel so far
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepsProgress {
+    pub move_id: u32,
+    pub left_steps: u32,
+    pub right_steps: u32,
+    pub status: StepsStatus,
+}
+
+// Steps move followed by update, kept after the end until the next one starts
+struct StepsMove {
+    move_id: u32,
+    left_sign: i32,
+    right_sign: i32,
+    // the robot should report the end before this time
+    deadline: Instant,
+    progress: Option<StepsProgress>,
+    timed_out: bool,
+}
+
+impl StepsMove {
+    fn is_running(&self) -> bool {
+        !self.timed_out
+            && self
+                .progress
+                .map_or(true, |progress| progress.status == StepsStatus::Running)
+    }
+}
+
+// Messages for the user, printed to stderr unless a front end collects them to show
+// them itself
+#[derive(Clone)]
+struct Notices {
+    collected: Arc<Mutex<Option<Vec<String>>>>,
+}
+
+impl Notices {
+    fn new() -> Notices {
+        Notices {
+            collected: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn notify(&self, text: String) {
+        match self.collected.lock().unwrap().as_mut() {
+            Some(collected) => collected.push(text),
+            None => eprintln!("{}", text),
+        }
+    }
+}
+
+pub struct Frame {
+    pub data: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+    pub channels: usize,
+}
+
+pub(crate) struct ImageProcessor {
     images: HashMap<u8, Mat>,
     scaled_images: HashMap<u8, Mat>,
+    frame_infos: HashMap<u8, FrameInfo>,
     out_resolution: (i32, i32),
 }
 
@@ -31,25 +172,72 @@ pub struct Robot {
     image_processor: Arc<Mutex<ImageProcessor>>,
     camera_list: Arc<Mutex<Option<Vec<u8>>>>,
     camera_resolutions: Arc<Mutex<ResolutionsMap>>,
+    move_done: Arc<Mutex<Option<MoveDone>>>,
+    steps_progress: Arc<Mutex<Option<StepsProgress>>>,
+    next_move_id: u32,
+    timed_move: Option<u32>,
+    steps_move: Option<StepsMove>,
     move_speed: u8,
+    last_move: (u8, u8, u8, u8),
+    drive_config: DriveConfig,
+    drive_type: DriveType,
+    odometry: Arc<Mutex<Odometry>>,
+    pose_filter: Arc<Mutex<PoseFilter>>,
+    pose_log: Arc<Mutex<Option<PoseLog>>>,
+    arbiter: MotionArbiter,
     server: Server,
     recv_thread_handle: Option<thread::JoinHandle<()>>,
     stop_thread_flag: Arc<AtomicBool>,
+    connected_flag: Arc<AtomicBool>,
+    mjpeg_streamer: Option<MjpegStreamer>,
     bot_is_moving: bool,
+    estop_engaged: bool,
+    ramp: Option<SpeedRamp>,
+    motion_watchdog: Option<Duration>,
+    last_motion_refresh: Instant,
+    mission: Option<MissionExecutor>,
+    mission_source: Option<SourceId>,
+    mission_config: MissionConfig,
+    route_recorder: Option<RouteRecorder>,
+    route_player: Option<RoutePlayer>,
+    script: Option<ScriptRunner>,
+    script_source: Option<SourceId>,
+    script_events: Vec<ScriptEvent>,
+    geofence: Option<GeofenceConfig>,
+    // origin of the geofence frame in the pose frame, moved by the pose resets
+    fence_origin: Pose,
+    notices: Notices,
 }
 
 fn recv_thread(
     stop_flag: Arc<AtomicBool>,
+    connected_flag: Arc<AtomicBool>,
     mut server: Server,
     image_processor: Arc<Mutex<ImageProcessor>>,
     camera_list: Arc<Mutex<Option<Vec<u8>>>>,
     camera_resolutions: Arc<Mutex<ResolutionsMap>>,
+    move_done: Arc<Mutex<Option<MoveDone>>>,
+    steps_progress: Arc<Mutex<Option<StepsProgress>>>,
+    odometry: Arc<Mutex<Odometry>>,
+    pose_filter: Arc<Mutex<PoseFilter>>,
+    pose_log: Arc<Mutex<Option<PoseLog>>>,
+    notices: Notices,
 ) {
-    println!("Robot thread started!");
+    notices.notify(String::from("Robot thread started!"));
     while !stop_flag.as_ref().load(std::sync::atomic::Ordering::SeqCst) {
-        let (id, data) = server
-            .recv()
-            .expect("Failed to receive message from tcp stream");
+        let (id, data) = match server.recv() {
+            Ok(msg) => msg,
+            Err(err) => {
+                if !stop_flag.as_ref().load(std::sync::atomic::Ordering::SeqCst) {
+                    notices.notify(format!(
+                        "Failed to receive message from tcp stream: {}",
+                        err
+                    ));
+                }
+                connected_flag.store(false, std::sync::atomic::Ordering::SeqCst);
+                break;
+            }
+        };
         let msg_id = MessageId::from(id);
         match msg_id {
             MessageId::RecvCameraList => {
@@ -79,22 +267,87 @@ fn recv_thread(
             MessageId::RecvImage => {
                 let mut image_msg = RecvImageMsg::new();
                 image_msg.from_bytes(&data);
-                image_processor
-                    .lock()
-                    .unwrap()
-                    .process_recv_image_msg(image_msg)
-                    .expect("Failed to process image");
+                let camera_id = image_msg.camera_id;
+                let now = Instant::now();
+                let pose = current_pose(&odometry, &pose_filter, now);
+                let number = {
+                    let mut processor = image_processor.lock().unwrap();
+                    processor
+                        .process_recv_image_msg(image_msg, pose)
+                        .expect("Failed to process image");
+                    processor
+                        .get_frame_info(camera_id)
+                        .map_or(0, |info| info.number)
+                };
+                if let Some(log) = pose_log.lock().unwrap().as_mut() {
+                    if let Err(err) = log.log_frame(camera_id, number, pose, now) {
+                        notices.notify(format!("Failed to write pose log: {}", err));
+                    }
+                }
+            }
+            MessageId::MoveDone => {
+                let mut move_done_msg = MoveDoneMsg::new();
+                move_done_msg.from_bytes(&data);
+                *move_done.lock().unwrap() = Some(MoveDone {
+                    move_id: move_done_msg.move_id,
+                    completed: move_done_msg.completed,
+                });
+            }
+            MessageId::StepsProgress => {
+                let mut progress_msg = StepsProgressMsg::new();
+                progress_msg.from_bytes(&data);
+                let status = match progress_msg.status {
+                    move_msg::STEPS_COMPLETED => StepsStatus::Completed,
+                    move_msg::STEPS_INTERRUPTED => StepsStatus::Interrupted,
+                    _ => StepsStatus::Running,
+                };
+                *steps_progress.lock().unwrap() = Some(StepsProgress {
+                    move_id: progress_msg.move_id,
+                    left_steps: progress_msg.left_steps,
+                    right_steps: progress_msg.right_steps,
+                    status: status,
+                });
+            }
+            MessageId::WheelEncoders => {
+                let mut encoders_msg = WheelEncodersMsg::new();
+                encoders_msg.from_bytes(&data);
+                pose_filter.lock().unwrap().update_encoders(
+                    encoders_msg.timestamp_ms,
+                    encoders_msg.left_ticks,
+                    encoders_msg.right_ticks,
+                );
+            }
+            MessageId::Imu => {
+                let mut imu_msg = ImuMsg::new();
+                imu_msg.from_bytes(&data);
+                pose_filter.lock().unwrap().update_imu(
+                    imu_msg.timestamp_ms,
+                    imu_msg.gyro_z as f64 / 1000.0,
+                    imu_msg.accel_x as f64 / 1000.0,
+                    imu_msg.accel_y as f64 / 1000.0,
+                );
             }
             _ => unreachable!(),
         }
     }
 }
 
+// The filtered pose once the robot sends telemetry, dead reckoning otherwise
+fn current_pose(odometry: &Mutex<Odometry>, pose_filter: &Mutex<PoseFilter>, now: Instant) -> Pose {
+    let pose_filter = pose_filter.lock().unwrap();
+    if pose_filter.is_active() {
+        pose_filter.estimate().pose
+    } else {
+        odometry.lock().unwrap().pose_at(now)
+    }
+}
+
 impl ImageProcessor {
     pub fn new() -> Result<ImageProcessor, Box<dyn Error>> {
         Ok(ImageProcessor {
             images: HashMap::new(),
             scaled_images: HashMap::new(),
+            frame_infos: HashMap::new(),
             out_resolution: (640, 489),
         })
     }
@@ -111,14 +364,77 @@ impl ImageProcessor {
         }
     }
 
+    pub fn get_image_data(&self, camera_id: u8) -> Result<Option<Frame>, Box<dyn Error>> {
+        match self.images.get(&camera_id) {
+            Some(mat) => {
+                let channels = mat.channels()?;
+                // single channel view to get plain bytes of a multi channel image
+                let flat_mat = mat.reshape(1, mat.rows())?;
+                Ok(Some(Frame {
+                    data: flat_mat.data_typed::<u8>()?.to_vec(),
+                    width: mat.cols() as usize,
+                    height: mat.rows() as usize,
+                    channels: channels as usize,
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_frame_info(&self, camera_id: u8) -> Option<FrameInfo> {
+        self.frame_infos.get(&camera_id).cloned()
+    }
+
+    pub fn get_camera_ids(&self) -> Vec<u8> {
+        let mut camera_ids: Vec<u8> = self.frame_infos.keys().cloned().collect();
+        camera_ids.sort();
+        camera_ids
+    }
+
+    pub fn encode_image(
+        &self,
+        camera_id: u8,
+        ext: &str,
+        params: &[i32],
+    ) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        match self.images.get(&camera_id) {
+            Some(img_mat) => {
+                // frames are kept in RGB order but OpenCV encoders expect BGR
+                let mut bgr_mat = Mat::default()?;
+                imgproc::cvt_color(img_mat, &mut bgr_mat, imgproc::COLOR_RGB2BGR, 0)?;
+                let mut buf = core::Vector::<u8>::new();
+                let cv_params = core::Vector::<i32>::from(params.to_vec());
+                imgcodecs::imencode(ext, &bgr_mat, &mut buf, &cv_params)?;
+                Ok(Some(buf.to_vec()))
+            }
+            None => Ok(None),
+        }
+    }
+
     pub fn process_recv_image_msg(
         &mut self,
         recv_img_msg: RecvImageMsg,
+        pose: Pose,
     ) -> Result<(), Box<dyn Error>> {
         // println!(
         //     "Recv image : {0} x {1} x {2}",
         //     recv_img_msg.channels, recv_img_msg.frame_width, recv_img_msg.frame_height
         // );
+        let number = self
+            .frame_infos
+            .get(&recv_img_msg.camera_id)
+            .map_or(0, |info| info.number + 1);
+        self.frame_infos.insert(
+            recv_img_msg.camera_id,
+            FrameInfo {
+                width: recv_img_msg.frame_width,
+                height: recv_img_msg.frame_height,
+                channels: recv_img_msg.channels,
+                encoded: recv_img_msg.encoded == 1,
+                number: number,
+                pose: pose,
+            },
+        );
 
         let img_mat = self
             .images
@@ -176,42 +492,102 @@ impl Robot {
             image_processor: Arc::new(Mutex::new(ImageProcessor::new()?)),
             camera_list: Arc::new(Mutex::new(None)),
             camera_resolutions: Arc::new(Mutex::new(HashMap::new())),
+            move_done: Arc::new(Mutex::new(None)),
+            steps_progress: Arc::new(Mutex::new(None)),
+            next_move_id: 1,
+            timed_move: None,
+            steps_move: None,
             move_speed: 10,
+            last_move: (0, 0, 0, 0),
+            drive_config: DriveConfig::default(),
+            drive_type: DriveType::Differential,
+            odometry: Arc::new(Mutex::new(Odometry::new())),
+            pose_filter: Arc::new(Mutex::new(PoseFilter::new(
+                DriveConfig::default(),
+                FilterConfig::default(),
+            ))),
+            pose_log: Arc::new(Mutex::new(None)),
+            arbiter: MotionArbiter::new(),
             server: Server::new(),
             recv_thread_handle: None,
             stop_thread_flag: Arc::new(AtomicBool::new(false)),
+            connected_flag: Arc::new(AtomicBool::new(false)),
+            mjpeg_streamer: None,
             bot_is_moving: false,
+            estop_engaged: false,
+            ramp: None,
+            motion_watchdog: Some(DEFAULT_MOTION_WATCHDOG),
+            last_motion_refresh: Instant::now(),
+            mission: None,
+            mission_source: None,
+            mission_config: MissionConfig::default(),
+            route_recorder: None,
+            route_player: None,
+            script: None,
+            script_source: None,
+            script_events: Vec::new(),
+            geofence: None,
+            fence_origin: Pose::default(),
+            notices: Notices::new(),
         })
     }
 
     pub fn init(&mut self, addr: Ipv4Addr, port: u16) -> Result<(), Box<dyn Error>> {
-        self.server.wait_client(addr, port)?;
-
-        let (id, _) = self
-            .server
-            .recv()
-            .expect("Failed to receive message from tcp stream");
-        if MessageId::from(id) == MessageId::Hello {
-            let msg = Box::new(HelloMsg {});
-            println!("Handshake received!");
-            self.server.send(msg).expect("Failed to send Hello message");
-        } else {
-            panic!("Handshake failed");
+        self.init_timeout(addr, port, None)
+    }
+
+    pub fn init_timeout(
+        &mut self,
+        addr: Ipv4Addr,
+        port: u16,
+        timeout: Option<Duration>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.server.wait_client_timeout(addr, port, timeout)?;
+        self.pose_filter.lock().unwrap().clear();
+
+        // the same timeout covers a client which connects but does not say hello
+        let (id, data) = self.server.recv_timeout(timeout)?;
+        if MessageId::from(id) != MessageId::Hello {
+            return Err(Box::new(RobotErrors::HandshakeFailed));
         }
+        // newer robot clients report the drive type in the handshake
+        if let Some(drive_type) = data.first().and_then(|value| DriveType::from_u8(*value)) {
+            self.drive_type = drive_type;
+        }
+        self.notices
+            .notify(format!("Handshake received, {} drive", self.drive_type));
+        self.server.send(Box::new(HelloMsg {}))?;
+
+        self.connected_flag
+            .store(true, std::sync::atomic::Ordering::SeqCst);
 
         let server_recv = self.server.clone();
         let stop_flag = Arc::clone(&self.stop_thread_flag);
+        let connected_flag = Arc::clone(&self.connected_flag);
         let image_processor_clone = Arc::clone(&self.image_processor);
         let camera_list_clone = Arc::clone(&self.camera_list);
         let camera_resolutions_clone = Arc::clone(&self.camera_resolutions);
+        let move_done_clone = Arc::clone(&self.move_done);
+        let steps_progress_clone = Arc::clone(&self.steps_progress);
+        let odometry_clone = Arc::clone(&self.odometry);
+        let pose_filter_clone = Arc::clone(&self.pose_filter);
+        let pose_log_clone = Arc::clone(&self.pose_log);
+        let notices_clone = self.notices.clone();
 
         self.recv_thread_handle = Some(thread::spawn(move || {
             recv_thread(
                 stop_flag,
+                connected_flag,
                 server_recv,
                 image_processor_clone,
                 camera_list_clone,
                 camera_resolutions_clone,
+                move_done_clone,
+                steps_progress_clone,
+                odometry_clone,
+                pose_filter_clone,
+                pose_log_clone,
+                notices_clone,
             )
         }));
         Ok(())
@@ -220,11 +596,34 @@ impl Robot {
     pub fn stop(&mut self) -> Result<(), Box<dyn Error>> {
         self.stop_thread_flag
             .store(true, std::sync::atomic::Ordering::SeqCst);
-        self.recv_thread_handle.take().map(thread::JoinHandle::join);
+        // the client closes the connection on StopMsg, which also unblocks the recv thread
         self.server.send(Box::new(StopMsg {}))?;
+        self.recv_thread_handle.take().map(thread::JoinHandle::join);
+        self.stop_mjpeg_streaming();
+        self.stop_pose_log();
         Ok(())
     }
 
+    pub fn start_mjpeg_streaming(
+        &mut self,
+        addr: &str,
+        config: MjpegConfig,
+    ) -> Result<(), Box<dyn Error>> {
+        self.stop_mjpeg_streaming();
+        self.mjpeg_streamer = Some(MjpegStreamer::start(
+            addr,
+            config,
+            Arc::clone(&self.image_processor),
+        )?);
+        Ok(())
+    }
+
+    pub fn stop_mjpeg_streaming(&mut self) {
+        self.mjpeg_streamer
+            .take()
+            .map(|mut streamer| streamer.stop());
+    }
+
     pub fn ask_camera_prop(&mut self, camera_id: u8) -> Result<(), Box<dyn Error>> {
         let mut get_msg = GetCameraPropMsg::new();
         get_msg.camera_id = camera_id;
@@ -261,49 +660,1394 @@ impl Robot {
         Ok(())
     }
 
-    pub fn ask_move_bot(&mut self, left_speed: u8, left_dir: u8, right_speed: u8, right_dir: u8) {
+    pub fn ask_move_bot(
+        &mut self,
+        left_speed: u8,
+        left_dir: u8,
+        right_speed: u8,
+        right_dir: u8,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.estop_engaged && (left_speed != 0 || right_speed != 0) {
+            self.notices.notify(String::from(
+                "Move command ignored, emergency stop is engaged",
+            ));
+            return Ok(());
+        }
+        let cmd = self.fence_command(
+            WheelCommand {
+                left_speed: left_speed,
+                left_dir: left_dir,
+                right_speed: right_speed,
+                right_dir: right_dir,
+            },
+            None,
+        );
+        if !cmd.is_stop() {
+            self.last_motion_refresh = Instant::now();
+        }
+        self.last_move = (cmd.left_speed, cmd.left_dir, cmd.right_speed, cmd.right_dir);
+        self.bot_is_moving = true;
+        self.record_route(&cmd.wheels(), Instant::now());
+        match self.ramp.as_mut() {
+            Some(ramp) => {
+                ramp.set_target(cmd, Instant::now());
+                self.update_ramp()
+            }
+            None => self.send_move(cmd),
+        }
+    }
+
+    fn send_move(&mut self, cmd: WheelCommand) -> Result<(), Box<dyn Error>> {
+        let trimmed = self.drive_config.trim(cmd);
         let mut move_msg = MoveMsg::new();
-        move_msg.left_speed = left_speed;
-        move_msg.left_dir = left_dir;
-        move_msg.right_speed = right_speed;
-        move_msg.right_dir = right_dir;
-        self.server
-            .send(Box::new(move_msg))
-            .expect("Failed to send move command");
+        move_msg.left_speed = trimmed.left_speed;
+        move_msg.left_dir = trimmed.left_dir;
+        move_msg.right_speed = trimmed.right_speed;
+        move_msg.right_dir = trimmed.right_dir;
+        self.server.send(Box::new(move_msg))?;
+        // a new command interrupts the timed move on the robot side
+        self.timed_move = None;
+        self.set_odometry_command(cmd, None);
+        Ok(())
+    }
+
+    // The periodic updates and stops have nobody to return the error to
+    fn log_move_error(result: Result<(), Box<dyn Error>>, notices: &Notices) {
+        if let Err(err) = result {
+            notices.notify(format!("Failed to send move command: {}", err));
+        }
+    }
+
+    // The trim only corrects the motors, so the pose follows the untrimmed command
+    fn set_odometry_command(&mut self, cmd: WheelCommand, duration: Option<Duration>) {
+        let velocity = self.command_velocity(cmd);
+        let now = Instant::now();
+        self.odometry.lock().unwrap().set_velocity(
+            velocity,
+            duration.map(|duration| now + duration),
+            now,
+        );
+    }
+
+    fn command_velocity(&self, cmd: WheelCommand) -> BodyVelocity {
+        let (linear, angular) = self.drive_config.command_velocity(cmd, MAX_MOVE_SPEED);
+        BodyVelocity {
+            linear_x: linear,
+            linear_y: 0.0,
+            angular: angular,
+        }
+    }
+
+    fn last_move_command(&self) -> WheelCommand {
+        let (left_speed, left_dir, right_speed, right_dir) = self.last_move;
+        WheelCommand {
+            left_speed: left_speed,
+            left_dir: left_dir,
+            right_speed: right_speed,
+            right_dir: right_dir,
+        }
+    }
+
+    fn update_ramp(&mut self) -> Result<(), Box<dyn Error>> {
+        match self
+            .ramp
+            .as_mut()
+            .and_then(|ramp| ramp.update(Instant::now()))
+        {
+            Some(cmd) => self.send_move(cmd),
+            None => Ok(()),
+        }
+    }
+
+    // Acceleration in speed units per second, None sends speed changes at once.
+    // The intermediate speeds are sent from update, so it should be called periodically.
+    pub fn set_acceleration(&mut self, acceleration: Option<f64>) {
+        match acceleration {
+            Some(acceleration) => match self.ramp.as_mut() {
+                Some(ramp) => ramp.set_acceleration(acceleration),
+                None => {
+                    let mut ramp = SpeedRamp::new(acceleration);
+                    ramp.reset_to(self.last_move_command(), Instant::now());
+                    self.ramp = Some(ramp);
+                }
+            },
+            None => {
+                if let Some(ramp) = self.ramp.take() {
+                    if !ramp.is_done() {
+                        Robot::log_move_error(
+                            self.send_move(self.last_move_command()),
+                            &self.notices,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn get_acceleration(&self) -> Option<f64> {
+        self.ramp.as_ref().map(|ramp| ramp.get_acceleration())
+    }
+
+    // Sets the ramp state when the speed was changed bypassing it
+    fn reset_ramp(&mut self, cmd: WheelCommand) {
+        if let Some(ramp) = self.ramp.as_mut() {
+            ramp.reset_to(cmd, Instant::now());
+        }
+    }
+
+    // The robot executes the command for the duration and stops by itself,
+    // returns the move id to match the MoveDone notification
+    pub fn move_for(
+        &mut self,
+        cmd: WheelCommand,
+        duration: Duration,
+    ) -> Result<u32, Box<dyn Error>> {
+        // nothing is sent, so there would be no MoveDone to wait for
+        if self.estop_engaged && !cmd.is_stop() {
+            return Err(Box::new(RobotErrors::EmergencyStop));
+        }
+        let move_id = self.next_move_id;
+        self.next_move_id = self.next_move_id.wrapping_add(1);
+        let cmd = self.fence_command(cmd, Some(duration));
+        let trimmed = self.drive_config.trim(cmd);
+        let mut move_msg = TimedMoveMsg::new();
+        move_msg.move_id = move_id;
+        move_msg.left_speed = trimmed.left_speed;
+        move_msg.left_dir = trimmed.left_dir;
+        move_msg.right_speed = trimmed.right_speed;
+        move_msg.right_dir = trimmed.right_dir;
+        move_msg.duration_ms = duration.as_millis().min(u32::MAX as u128) as u32;
+        self.server.send(Box::new(move_msg))?;
+        self.last_move = (cmd.left_speed, cmd.left_dir, cmd.right_speed, cmd.right_dir);
+        self.bot_is_moving = !cmd.is_stop();
+        self.timed_move = Some(move_id);
+        self.reset_ramp(cmd);
+        self.set_odometry_command(cmd, Some(duration));
+        let now = Instant::now();
+        self.record_route(&cmd.wheels(), now);
+        self.record_route(&WheelCommand::default().wheels(), now + duration);
+        Ok(move_id)
+    }
+
+    // The latest timed move notification received from the robot
+    pub fn get_move_done(&self) -> Option<MoveDone> {
+        *self.move_done.lock().unwrap()
+    }
+
+    // Returns true if the move completed and false if it was interrupted
+    pub fn wait_move_done(&self, move_id: u32, timeout: Duration) -> Result<bool, Box<dyn Error>> {
+        let start = Instant::now();
+        loop {
+            if let Some(move_done) = self.get_move_done() {
+                if move_done.move_id == move_id {
+                    return Ok(move_done.completed);
+                }
+            }
+            if start.elapsed() > timeout {
+                return Err(Box::new(RobotErrors::Timeout));
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    // Moves the wheels by the exact number of half-steps, negative values move backward.
+    // The rate is in half-steps per second, returns when the move is done. It calls
+    // update while waiting, start_move_steps does not block.
+    pub fn move_steps(
+        &mut self,
+        left_steps: i32,
+        right_steps: i32,
+        rate: u16,
+    ) -> Result<StepsProgress, Box<dyn Error>> {
+        self.move_steps_with_progress(left_steps, right_steps, rate, |_| {})
+    }
+
+    pub fn move_steps_with_progress<F: FnMut(StepsProgress)>(
+        &mut self,
+        left_steps: i32,
+        right_steps: i32,
+        rate: u16,
+        mut on_progress: F,
+    ) -> Result<StepsProgress, Box<dyn Error>> {
+        let move_id = self.start_move_steps(left_steps, right_steps, rate)?;
+        let mut last_progress: Option<StepsProgress> = None;
+        loop {
+            thread::sleep(Duration::from_millis(10));
+            self.update();
+            let steps_move = match self.steps_move.as_ref() {
+                Some(steps_move) if steps_move.move_id == move_id => steps_move,
+                _ => return Err(Box::new(RobotErrors::Timeout)),
+            };
+            if steps_move.timed_out {
+                return Err(Box::new(RobotErrors::Timeout));
+            }
+            if let Some(progress) = steps_move.progress {
+                if last_progress != Some(progress) {
+                    last_progress = Some(progress);
+                    on_progress(progress);
+                }
+                if !steps_move.is_running() {
+                    return Ok(progress);
+                }
+            }
+        }
+    }
+
+    // Starts the steps move and returns its id, update follows the progress reported by
+    // the robot and stops the bot when the robot does not finish the move in time
+    pub fn start_move_steps(
+        &mut self,
+        left_steps: i32,
+        right_steps: i32,
+        rate: u16,
+    ) -> Result<u32, Box<dyn Error>> {
+        if self.estop_engaged {
+            return Err(Box::new(RobotErrors::EmergencyStop));
+        }
+        if rate == 0 {
+            return Err(Box::new(RobotErrors::WrongArgument));
+        }
+        let rate = self.fence_step_rate(rate);
+        if !self.fence_allows_steps(left_steps, right_steps) {
+            self.notices.notify(String::from(
+                "Steps move ignored, it would leave the geofence",
+            ));
+            return Err(Box::new(RobotErrors::OutsideGeofence));
+        }
+        let move_id = self.next_move_id;
+        self.next_move_id = self.next_move_id.wrapping_add(1);
+        let mut move_msg = MoveStepsMsg::new();
+        move_msg.move_id = move_id;
+        move_msg.left_steps = left_steps;
+        move_msg.right_steps = right_steps;
+        move_msg.rate = rate;
+        self.server.send(Box::new(move_msg))?;
+        self.timed_move = None;
+        self.reset_ramp(WheelCommand::default());
+        // the pose follows the steps reported by the robot
+        self.set_odometry_command(WheelCommand::default(), None);
         self.bot_is_moving = true;
+
+        let max_steps = std::cmp::max(left_steps.unsigned_abs(), right_steps.unsigned_abs());
+        let duration = Duration::from_secs_f64(max_steps as f64 / rate as f64);
+        self.steps_move = Some(StepsMove {
+            move_id: move_id,
+            left_sign: left_steps.signum(),
+            right_sign: right_steps.signum(),
+            deadline: Instant::now() + duration + STEPS_MOVE_TIMEOUT_MARGIN,
+            progress: None,
+            timed_out: false,
+        });
+        Ok(move_id)
+    }
+
+    // True while the last steps move runs
+    pub fn is_steps_moving(&self) -> bool {
+        self.steps_move
+            .as_ref()
+            .map_or(false, |steps_move| steps_move.is_running())
+    }
+
+    // Progress of the steps move seen by update, None before the first report or when a
+    // newer steps move started
+    pub fn get_steps_move_progress(&self, move_id: u32) -> Option<StepsProgress> {
+        self.steps_move
+            .as_ref()
+            .filter(|steps_move| steps_move.move_id == move_id)
+            .and_then(|steps_move| steps_move.progress)
+    }
+
+    fn update_steps_move(&mut self) {
+        let (move_id, last_progress, left_sign, right_sign, deadline) =
+            match self.steps_move.as_ref() {
+                Some(steps_move) if steps_move.is_running() => (
+                    steps_move.move_id,
+                    steps_move.progress,
+                    steps_move.left_sign,
+                    steps_move.right_sign,
+                    steps_move.deadline,
+                ),
+                _ => return,
+            };
+        let progress = *self.steps_progress.lock().unwrap();
+        if let Some(progress) = progress.filter(|progress| progress.move_id == move_id) {
+            if last_progress != Some(progress) {
+                self.add_steps_odometry(last_progress.as_ref(), &progress, left_sign, right_sign);
+                if let Some(steps_move) = self.steps_move.as_mut() {
+                    steps_move.progress = Some(progress);
+                }
+                if progress.status != StepsStatus::Running {
+                    self.last_move = (0, 0, 0, 0);
+                    self.bot_is_moving = false;
+                }
+                return;
+            }
+        }
+        if Instant::now() > deadline {
+            self.notices.notify(String::from(
+                "Steps move was not finished in time, stopping the bot",
+            ));
+            if let Some(steps_move) = self.steps_move.as_mut() {
+                steps_move.timed_out = true;
+            }
+            self.stop_moving();
+        }
+    }
+
+    fn add_steps_odometry(
+        &mut self,
+        last_progress: Option<&StepsProgress>,
+        progress: &StepsProgress,
+        left_sign: i32,
+        right_sign: i32,
+    ) {
+        let (last_left, last_right) =
+            last_progress.map_or((0, 0), |last| (last.left_steps, last.right_steps));
+        let left = progress.left_steps.saturating_sub(last_left) as i32 * left_sign;
+        let right = progress.right_steps.saturating_sub(last_right) as i32 * right_sign;
+        let (distance, rotation) = self.drive_config.steps_displacement(left, right);
+        self.odometry
+            .lock()
+            .unwrap()
+            .add_displacement(distance, 0.0, rotation, Instant::now());
+    }
+
+    // Drives straight by the distance in meters, negative moves backward.
+    // Uses the drive config geometry and calibration, returns when the move is done.
+    pub fn drive_distance(&mut self, meters: f64) -> Result<StepsProgress, Box<dyn Error>> {
+        let steps = self.drive_config.distance_steps(meters);
+        self.move_steps(steps, steps, self.drive_config.step_rate)
+    }
+
+    // Turns in place, positive degrees turn left
+    pub fn turn_angle(&mut self, degrees: f64) -> Result<StepsProgress, Box<dyn Error>> {
+        let (left_steps, right_steps) = self.drive_config.turn_steps(degrees);
+        self.move_steps(left_steps, right_steps, self.drive_config.step_rate)
+    }
+
+    pub fn get_steps_progress(&self) -> Option<StepsProgress> {
+        *self.steps_progress.lock().unwrap()
+    }
+
+    fn update_timed_move(&mut self) {
+        if let Some(move_id) = self.timed_move {
+            if self
+                .get_move_done()
+                .map_or(false, |done| done.move_id == move_id)
+            {
+                self.timed_move = None;
+                self.bot_is_moving = false;
+            }
+        }
+    }
+
+    pub fn send_wheel_command(&mut self, cmd: WheelCommand) -> Result<(), Box<dyn Error>> {
+        if cmd.is_stop() {
+            self.stop_moving();
+            Ok(())
+        } else {
+            self.ask_move_bot(cmd.left_speed, cmd.left_dir, cmd.right_speed, cmd.right_dir)
+        }
+    }
+
+    // Input sources drive the robot through the arbiter, higher priority wins
+    pub fn register_input_source(
+        &mut self,
+        name: &str,
+        priority: u8,
+        idle_timeout: Option<Duration>,
+    ) -> SourceId {
+        self.arbiter.register(name, priority, idle_timeout)
+    }
+
+    pub fn submit_motion(
+        &mut self,
+        source: SourceId,
+        cmd: WheelCommand,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.estop_engaged {
+            return Ok(());
+        }
+        self.arbiter.submit(source, cmd, Instant::now());
+        self.refresh_motion();
+        self.update_motion()
+    }
+
+    // Every wheel speed for multi-wheel chassis, like the strafing commands
+    pub fn submit_wheels_motion(
+        &mut self,
+        source: SourceId,
+        wheels: Vec<WheelState>,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.estop_engaged {
+            return Ok(());
+        }
+        self.arbiter.submit_wheels(source, wheels, Instant::now());
+        self.refresh_motion();
+        self.update_motion()
+    }
+
+    // Keeps the source command going without sending it again, the command stays
+    // stopped when the source already went idle
+    pub fn refresh_motion_source(&mut self, source: SourceId) {
+        let now = Instant::now();
+        self.arbiter.refresh(source, now);
+        if self.arbiter.active_source(now) == Some(source) {
+            self.refresh_motion();
+        }
+    }
+
+    pub fn release_motion(&mut self, source: SourceId) -> Result<(), Box<dyn Error>> {
+        self.arbiter.release(source);
+        self.update_motion()
+    }
+
+    pub fn get_control_source(&self) -> Option<String> {
+        self.arbiter
+            .active_source(Instant::now())
+            .map(|source| String::from(self.arbiter.source_name(source)))
+    }
+
+    fn update_motion(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.estop_engaged {
+            return Ok(());
+        }
+        match self.arbiter.update(Instant::now()) {
+            Some(MotionCommand::Sides(cmd)) => self.send_wheel_command(cmd),
+            Some(MotionCommand::Wheels(wheels)) => self.ask_move_wheels(&wheels),
+            None => Ok(()),
+        }
+    }
+
+    // Should be called periodically by the front-end to process time based events
+    pub fn update(&mut self) {
+        self.update_timed_move();
+        self.update_steps_move();
+        self.update_replay();
+        self.update_script();
+        self.update_mission();
+        Robot::log_move_error(self.update_motion(), &self.notices);
+        Robot::log_move_error(self.update_ramp(), &self.notices);
+        self.check_geofence();
+        self.check_motion_watchdog();
+    }
+
+    // The mission drives through the arbiter from update, so the operator can take over.
+    // It should be called periodically while the mission runs.
+    pub fn start_mission(&mut self, steps: Vec<MissionStep>) -> Result<(), Box<dyn Error>> {
+        if self.estop_engaged {
+            return Err(Box::new(RobotErrors::EmergencyStop));
+        }
+        if steps.is_empty() {
+            return Err(Box::new(RobotErrors::WrongArgument));
+        }
+        self.abort_mission();
+        self.stop_replay();
+        self.stop_script();
+        // no idle timeout, the mission submits its command on every update
+        if self.mission_source.is_none() {
+            self.mission_source = Some(self.arbiter.register("mission", MISSION_PRIORITY, None));
+        }
+        self.mission = Some(MissionExecutor::new(
+            steps,
+            self.mission_config,
+            Instant::now(),
+        ));
+        Ok(())
+    }
+
+    pub fn pause_mission(&mut self) {
+        if let Some(mission) = self.mission.as_mut() {
+            mission.pause(Instant::now());
+        }
+        self.update_mission();
+        Robot::log_move_error(self.update_motion(), &self.notices);
+    }
+
+    pub fn resume_mission(&mut self) {
+        if self.estop_engaged {
+            return;
+        }
+        if let Some(mission) = self.mission.as_mut() {
+            mission.resume(Instant::now());
+        }
+    }
+
+    pub fn abort_mission(&mut self) {
+        if let Some(mission) = self.mission.as_mut() {
+            mission.abort();
+        }
+        if let Some(source) = self.mission_source {
+            self.arbiter.release(source);
+        }
+        Robot::log_move_error(self.update_motion(), &self.notices);
+    }
+
+    pub fn get_mission_status(&self) -> Option<MissionStatus> {
+        self.mission.as_ref().map(|mission| mission.status())
+    }
+
+    pub fn get_mission_steps(&self) -> Vec<MissionStep> {
+        self.mission
+            .as_ref()
+            .map_or(Vec::new(), |mission| mission.steps().to_vec())
+    }
+
+    // Progress events since the last call
+    pub fn take_mission_events(&mut self) -> Vec<MissionEvent> {
+        self.mission
+            .as_mut()
+            .map_or(Vec::new(), |mission| mission.take_events())
+    }
+
+    pub fn set_mission_config(&mut self, config: MissionConfig) {
+        self.mission_config = config;
+    }
+
+    pub fn get_mission_config(&self) -> MissionConfig {
+        self.mission_config
+    }
+
+    fn update_mission(&mut self) {
+        let source = match self.mission_source {
+            Some(source) => source,
+            None => return,
+        };
+        let pose = self.pose();
+        let now = Instant::now();
+        let velocity = match self.mission.as_mut() {
+            Some(mission) if !mission.is_finished() => mission.update(pose, now),
+            _ => return,
+        };
+        match velocity {
+            Some((linear, angular)) => {
+                let cmd = self
+                    .drive_config
+                    .wheel_command(linear, angular, MAX_MOVE_SPEED);
+                self.arbiter.submit(source, cmd, now);
+                self.refresh_motion();
+            }
+            None => self.arbiter.release(source),
+        }
+    }
+
+    // Records the motion commands sent from now on, the route starts at the first move
+    pub fn start_route_recording(&mut self) {
+        self.route_recorder = Some(RouteRecorder::new(Instant::now()));
+    }
+
+    pub fn stop_route_recording(&mut self) -> Result<Route, Box<dyn Error>> {
+        match self.route_recorder.take() {
+            Some(recorder) => recorder.finish(Instant::now()),
+            None => Err(Box::new(RobotErrors::WrongArgument)),
+        }
+    }
+
+    pub fn is_route_recording(&self) -> bool {
+        self.route_recorder.is_some()
+    }
+
+    fn record_route(&mut self, wheels: &[WheelState], time: Instant) {
+        if let Some(recorder) = self.route_recorder.as_mut() {
+            recorder.record(wheels, time);
+        }
+    }
+
+    // The replay drives from update, so it should be called periodically. Manual
+    // control through an input source or the emergency stop ends the replay.
+    pub fn start_replay(
+        &mut self,
+        route: Route,
+        reverse: bool,
+        speed_scale: f64,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.estop_engaged {
+            return Err(Box::new(RobotErrors::EmergencyStop));
+        }
+        self.abort_mission();
+        self.stop_script();
+        let route = if reverse { route.reversed() } else { route };
+        self.route_player = Some(RoutePlayer::new(route, speed_scale, Instant::now()));
+        self.update_replay();
+        Ok(())
+    }
+
+    pub fn stop_replay(&mut self) {
+        if self.route_player.take().is_some() {
+            self.stop_moving();
+        }
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.route_player.is_some()
+    }
+
+    pub fn set_replay_speed_scale(&mut self, speed_scale: f64) {
+        if let Some(player) = self.route_player.as_mut() {
+            player.set_speed_scale(speed_scale);
+        }
+    }
+
+    pub fn get_replay_speed_scale(&self) -> Option<f64> {
+        self.route_player
+            .as_ref()
+            .map(|player| player.get_speed_scale())
+    }
+
+    // Played and total seconds of the route at the recorded speed
+    pub fn get_replay_progress(&self) -> Option<(f64, f64)> {
+        self.route_player.as_ref().map(|player| player.progress())
+    }
+
+    fn update_replay(&mut self) {
+        let now = Instant::now();
+        let wheels = match self.route_player.as_mut() {
+            Some(player) => player.update(now),
+            None => return,
+        };
+        if let Some(source) = self.arbiter.active_source(now) {
+            self.notices.notify(format!(
+                "Route replay stopped, {} took over",
+                self.arbiter.source_name(source)
+            ));
+            // the input source command is sent by update_motion
+            self.route_player = None;
+            return;
+        }
+        if let Some(wheels) = wheels {
+            let result = if wheels.len() == 2 {
+                self.send_wheel_command(WheelCommand {
+                    left_speed: wheels[0].speed,
+                    left_dir: wheels[0].dir,
+                    right_speed: wheels[1].speed,
+                    right_dir: wheels[1].dir,
+                })
+            } else {
+                self.ask_move_wheels(&wheels)
+            };
+            Robot::log_move_error(result, &self.notices);
+        }
+        self.refresh_motion();
+        if self
+            .route_player
+            .as_ref()
+            .map_or(false, |player| player.is_finished())
+        {
+            self.route_player = None;
+        }
+    }
+
+    // The script calls the robot from update, so it should be called periodically.
+    // Syntax errors are returned here, the other ones come as script events.
+    pub fn start_script(
+        &mut self,
+        source: &str,
+        config: ScriptConfig,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.estop_engaged {
+            return Err(Box::new(RobotErrors::EmergencyStop));
+        }
+        let runner = ScriptRunner::start(source, config)?;
+        self.abort_mission();
+        self.stop_replay();
+        self.stop_script();
+        // no idle timeout, the script keeps its command while it sleeps and the source
+        // is released when the script ends
+        if self.script_source.is_none() {
+            self.script_source = Some(self.arbiter.register("script", SCRIPT_PRIORITY, None));
+        }
+        self.script = Some(runner);
+        Ok(())
+    }
+
+    pub fn stop_script(&mut self) {
+        if let Some(script) = self.script.as_ref() {
+            script.stop();
+        }
+        if let Some(source) = self.script_source {
+            self.arbiter.release(source);
+        }
+        Robot::log_move_error(self.update_motion(), &self.notices);
+    }
+
+    pub fn is_script_running(&self) -> bool {
+        self.script.is_some()
+    }
+
+    // Script output and the result since the last call
+    pub fn take_script_events(&mut self) -> Vec<ScriptEvent> {
+        std::mem::take(&mut self.script_events)
+    }
+
+    // Keeps the messages for take_notices instead of printing them to stderr, for front
+    // ends which own the terminal. The messages nobody took are printed when it is turned off.
+    pub fn collect_notices(&mut self, collect: bool) {
+        let mut collected = self.notices.collected.lock().unwrap();
+        if collect {
+            if collected.is_none() {
+                *collected = Some(Vec::new());
+            }
+        } else if let Some(left) = collected.take() {
+            for text in left {
+                eprintln!("{}", text);
+            }
+        }
+    }
+
+    // Messages collected since the last call
+    pub fn take_notices(&mut self) -> Vec<String> {
+        match self.notices.collected.lock().unwrap().as_mut() {
+            Some(collected) => std::mem::take(collected),
+            None => Vec::new(),
+        }
+    }
+
+    fn update_script(&mut self) {
+        let calls = match self.script.as_ref() {
+            Some(script) => script.take_calls(),
+            None => return,
+        };
+        for call in calls {
+            let result = self.execute_script_call(&call.call);
+            call.reply(result);
+        }
+        let script = self.script.as_mut().unwrap();
+        let events = script.take_events();
+        let finished = script.is_finished();
+        self.script_events.extend(events);
+        if self.script_events.len() > MAX_SCRIPT_EVENTS {
+            let excess = self.script_events.len() - MAX_SCRIPT_EVENTS;
+            self.script_events.drain(..excess);
+        }
+        let source = self.script_source.unwrap();
+        if finished {
+            // the motion ends with the script, whatever way it ended
+            self.script = None;
+            self.arbiter.release(source);
+        } else if self.arbiter.active_source(Instant::now()) == Some(source) {
+            self.refresh_motion();
+        }
+    }
+
+    fn execute_script_call(&mut self, call: &RobotCall) -> Result<ScriptValue, String> {
+        let source = self.script_source.unwrap();
+        let speed = self.move_speed;
+        let cmd = match *call {
+            RobotCall::Move(motion) => {
+                let (left_speed, left_dir, right_speed, right_dir) = match motion {
+                    ScriptMotion::Forward => (speed, DIR_FORWARD, speed, DIR_FORWARD),
+                    ScriptMotion::Backward => (speed, DIR_BACKWARD, speed, DIR_BACKWARD),
+                    ScriptMotion::Left => (0, DIR_BACKWARD, speed, DIR_FORWARD),
+                    ScriptMotion::Right => (speed, DIR_FORWARD, 0, DIR_BACKWARD),
+                };
+                WheelCommand {
+                    left_speed: left_speed,
+                    left_dir: left_dir,
+                    right_speed: right_speed,
+                    right_dir: right_dir,
+                }
+            }
+            RobotCall::Drive { linear, angular } => {
+                self.drive_config
+                    .wheel_command(linear, angular, MAX_MOVE_SPEED)
+            }
+            RobotCall::Stop => {
+                self.arbiter.release(source);
+                self.update_motion().map_err(|err| err.to_string())?;
+                return Ok(ScriptValue::Unit);
+            }
+            _ => return self.query_for_script(call).map_err(|err| err.to_string()),
+        };
+        if self.estop_engaged {
+            return Err(String::from("emergency stop is engaged"));
+        }
+        self.arbiter.submit(source, cmd, Instant::now());
+        self.refresh_motion();
+        self.update_motion().map_err(|err| err.to_string())?;
+        Ok(ScriptValue::Unit)
+    }
+
+    fn query_for_script(&mut self, call: &RobotCall) -> Result<ScriptValue, Box<dyn Error>> {
+        let value = match call {
+            RobotCall::SetSpeed(speed) => {
+                self.set_speed(*speed);
+                ScriptValue::Unit
+            }
+            RobotCall::GetSpeed => ScriptValue::Int(self.move_speed as i64),
+            RobotCall::GetPose => {
+                let pose = self.pose();
+                ScriptValue::Record(vec![
+                    ("x", ScriptValue::Float(pose.x)),
+                    ("y", ScriptValue::Float(pose.y)),
+                    ("heading", ScriptValue::Float(pose.heading.to_degrees())),
+                ])
+            }
+            RobotCall::GetCameras => ScriptValue::List(
+                self.get_camera_list()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|camera_id| ScriptValue::Int(*camera_id as i64))
+                    .collect(),
+            ),
+            RobotCall::SetCamera {
+                camera_id,
+                width,
+                height,
+                fps,
+                encode,
+            } => {
+                self.ask_set_camera_prop(*camera_id, *width, *height, *fps, *encode)?;
+                ScriptValue::Unit
+            }
+            RobotCall::Snapshot { camera_id, path } => {
+                script::check_snapshot_path(path)?;
+                let ext = match path.rfind('.') {
+                    Some(pos) => &path[pos..],
+                    None => return Err(format!("no image format in {}", path).into()),
+                };
+                let data = self
+                    .get_encoded_image(*camera_id, ext, &[])?
+                    .ok_or_else(|| format!("no frame from camera {}", camera_id))?;
+                fs::write(path, &data)?;
+                ScriptValue::Int(data.len() as i64)
+            }
+            RobotCall::FrameStats(camera_id) => {
+                let info = self
+                    .get_frame_info(*camera_id)
+                    .ok_or_else(|| format!("no frame from camera {}", camera_id))?;
+                // mean channel values of the scaled RGB image
+                let mut sums = [0u64; 3];
+                let mut pixels = 0;
+                if let Some(data) = self.get_image(*camera_id) {
+                    for pixel in data.chunks_exact(3) {
+                        for (sum, value) in sums.iter_mut().zip(pixel) {
+                            *sum += *value as u64;
+                        }
+                    }
+                    pixels = data.len() as u64 / 3;
+                }
+                let mean = |sum: u64| sum as f64 / pixels.max(1) as f64;
+                ScriptValue::Record(vec![
+                    ("width", ScriptValue::Int(info.width as i64)),
+                    ("height", ScriptValue::Int(info.height as i64)),
+                    ("number", ScriptValue::Int(info.number as i64)),
+                    ("encoded", ScriptValue::Bool(info.encoded)),
+                    (
+                        "brightness",
+                        ScriptValue::Float(mean(sums.iter().sum::<u64>()) / 3.0),
+                    ),
+                    ("red", ScriptValue::Float(mean(sums[0]))),
+                    ("green", ScriptValue::Float(mean(sums[1]))),
+                    ("blue", ScriptValue::Float(mean(sums[2]))),
+                ])
+            }
+            _ => ScriptValue::Unit,
+        };
+        Ok(value)
+    }
+
+    // The watchdog stops the bot when motion commands are not refreshed in time,
+    // None disables it. It works only when update is called periodically.
+    pub fn set_motion_watchdog(&mut self, timeout: Option<Duration>) {
+        self.motion_watchdog = timeout;
+        self.refresh_motion();
+    }
+
+    pub fn get_motion_watchdog(&self) -> Option<Duration> {
+        self.motion_watchdog
+    }
+
+    // Keeps the current motion going without sending a new command
+    pub fn refresh_motion(&mut self) {
+        self.last_motion_refresh = Instant::now();
+    }
+
+    fn check_motion_watchdog(&mut self) {
+        if let Some(timeout) = self.motion_watchdog {
+            // timed and steps moves are stopped by the robot itself
+            if self.bot_is_moving
+                && self.timed_move.is_none()
+                && !self.is_steps_moving()
+                && self.last_motion_refresh.elapsed() > timeout
+            {
+                self.notices.notify(String::from(
+                    "Motion commands were not refreshed in time, stopping the bot",
+                ));
+                self.arbiter.reset();
+                self.stop_moving();
+            }
+        }
+    }
+
+    // Limits the motion to the operating area and the maximum speed, None removes the
+    // limits. The area is in the current pose frame and stays in place when the pose is
+    // reset later. The area is checked while the bot moves only when update is called
+    // periodically.
+    pub fn set_geofence(&mut self, geofence: Option<GeofenceConfig>) {
+        self.geofence = geofence;
+        self.fence_origin = Pose::default();
+    }
+
+    pub fn get_geofence(&self) -> Option<GeofenceConfig> {
+        self.geofence.clone()
+    }
+
+    // Where the geofence area origin is in the pose frame
+    pub fn get_geofence_origin(&self) -> Pose {
+        self.fence_origin
+    }
+
+    // The pose in the geofence frame
+    fn fence_pose(&self) -> Pose {
+        self.pose().relative_to(self.fence_origin)
+    }
+
+    // True when the bot is inside the area or there is no area
+    pub fn is_inside_geofence(&self) -> bool {
+        let pose = self.fence_pose();
+        self.geofence
+            .as_ref()
+            .and_then(|geofence| geofence.area.as_ref())
+            .map_or(true, |area| area.contains(pose.x, pose.y))
+    }
+
+    fn fence_allows(&self, velocity: BodyVelocity) -> bool {
+        self.geofence.as_ref().map_or(true, |geofence| {
+            geofence.allows(self.fence_pose(), velocity)
+        })
+    }
+
+    // The command with the speed limit, or a stop when it would leave the area.
+    // Timed moves are checked for their whole duration.
+    fn fence_command(&self, cmd: WheelCommand, duration: Option<Duration>) -> WheelCommand {
+        let geofence = match self.geofence.as_ref() {
+            Some(geofence) if !cmd.is_stop() => geofence,
+            _ => return cmd,
+        };
+        let cmd = geofence.limit_command(cmd);
+        let seconds = duration.map_or(geofence.lookahead, |duration| {
+            duration.as_secs_f64().max(geofence.lookahead)
+        });
+        if geofence.allows_for(self.fence_pose(), self.command_velocity(cmd), seconds) {
+            cmd
+        } else {
+            self.notices.notify(String::from(
+                "Move command stopped, it would leave the geofence",
+            ));
+            WheelCommand::default()
+        }
+    }
+
+    fn fence_wheels(&self, wheels: &[WheelState]) -> Vec<WheelState> {
+        let geofence = match self.geofence.as_ref() {
+            Some(geofence) if wheels.iter().any(|wheel| wheel.speed != 0) => geofence,
+            _ => return wheels.to_vec(),
+        };
+        let wheels = geofence.limit_wheels(wheels);
+        if self.fence_allows(self.wheels_velocity(&wheels)) {
+            wheels
+        } else {
+            self.notices.notify(String::from(
+                "Move command stopped, it would leave the geofence",
+            ));
+            vec![WheelState::default(); wheels.len()]
+        }
+    }
+
+    // Steps moves are checked by the pose where they end
+    fn fence_allows_steps(&self, left_steps: i32, right_steps: i32) -> bool {
+        let area = match self
+            .geofence
+            .as_ref()
+            .and_then(|geofence| geofence.area.as_ref())
+        {
+            Some(area) => area,
+            None => return true,
+        };
+        let pose = self.fence_pose();
+        let (distance, rotation) = self
+            .drive_config
+            .steps_displacement(left_steps, right_steps);
+        let end = pose.moved_by(distance, 0.0, rotation);
+        let clearance = area.clearance(end.x, end.y);
+        clearance >= 0.0 || clearance >= area.clearance(pose.x, pose.y)
+    }
+
+    // Steps moves are no faster than the wheels at the maximum speed
+    fn fence_step_rate(&self, rate: u16) -> u16 {
+        match self
+            .geofence
+            .as_ref()
+            .and_then(|geofence| geofence.max_speed)
+        {
+            Some(max_speed) => {
+                let wheel_speed =
+                    self.drive_config
+                        .wheel_speed(max_speed, DIR_FORWARD, MAX_MOVE_SPEED);
+                let max_rate = self.drive_config.wheel_step_rate(wheel_speed);
+                rate.min(max_rate.round().clamp(1.0, u16::MAX as f64) as u16)
+            }
+            None => rate,
+        }
+    }
+
+    // Stops the current motion before it leaves the area
+    fn check_geofence(&mut self) {
+        if self.bot_is_moving && !self.fence_allows(self.get_body_velocity()) {
+            self.notices
+                .notify(String::from("Stopping the bot at the geofence"));
+            self.arbiter.reset();
+            self.stop_moving();
+        }
+    }
+
+    pub fn set_drive_config(&mut self, config: DriveConfig) {
+        self.drive_config = config;
+        self.pose_filter.lock().unwrap().set_drive_config(config);
+    }
+
+    pub fn get_drive_config(&self) -> DriveConfig {
+        self.drive_config
+    }
+
+    // linear is in m/s, angular is in rad/s and positive to the left
+    pub fn set_velocity(&mut self, linear: f64, angular: f64) -> Result<(), Box<dyn Error>> {
+        let cmd = self
+            .drive_config
+            .wheel_command(linear, angular, MAX_MOVE_SPEED);
+        self.send_wheel_command(cmd)
+    }
+
+    pub fn get_drive_type(&self) -> DriveType {
+        self.drive_type
+    }
+
+    // Overrides the drive type reported by the robot client
+    pub fn set_drive_type(&mut self, drive_type: DriveType) {
+        self.drive_type = drive_type;
+    }
+
+    pub fn is_holonomic(&self) -> bool {
+        kinematics_for(self.drive_type, self.drive_config).is_holonomic()
+    }
+
+    // Body velocity for any drive type, linear_x is forward and linear_y is to the left
+    // in m/s, angular is in rad/s and positive to the left. linear_y is used only by
+    // holonomic chassis.
+    pub fn set_body_velocity(
+        &mut self,
+        linear_x: f64,
+        linear_y: f64,
+        angular: f64,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.drive_type == DriveType::Differential {
+            return self.set_velocity(linear_x, angular);
+        }
+        let wheels = self.body_velocity_wheels(linear_x, linear_y, angular);
+        self.ask_move_wheels(&wheels)
+    }
+
+    fn body_velocity_wheels(&self, linear_x: f64, linear_y: f64, angular: f64) -> Vec<WheelState> {
+        let kinematics = kinematics_for(self.drive_type, self.drive_config);
+        let wheel_speeds = kinematics.wheel_speeds(linear_x, linear_y, angular);
+        self.drive_config
+            .wheel_states(&wheel_speeds, MAX_MOVE_SPEED)
+    }
+
+    fn wheels_velocity(&self, wheels: &[WheelState]) -> BodyVelocity {
+        let kinematics = kinematics_for(self.drive_type, self.drive_config);
+        let wheel_speeds: Vec<f64> = wheels
+            .iter()
+            .map(|wheel| {
+                self.drive_config
+                    .wheel_speed(wheel.speed, wheel.dir, MAX_MOVE_SPEED)
+            })
+            .collect();
+        let (linear_x, linear_y, angular) = kinematics.body_velocity(&wheel_speeds);
+        BodyVelocity {
+            linear_x: linear_x,
+            linear_y: linear_y,
+            angular: angular,
+        }
+    }
+
+    // Sends every wheel speed for chassis with more than two wheels
+    pub fn ask_move_wheels(&mut self, wheels: &[WheelState]) -> Result<(), Box<dyn Error>> {
+        let is_stop = wheels.iter().all(|wheel| wheel.speed == 0);
+        if self.estop_engaged && !is_stop {
+            self.notices.notify(String::from(
+                "Move command ignored, emergency stop is engaged",
+            ));
+            return Ok(());
+        }
+        let wheels = &self.fence_wheels(wheels);
+        let is_stop = wheels.iter().all(|wheel| wheel.speed == 0);
+        self.record_route(wheels, Instant::now());
+        let mut move_msg = MultiMoveMsg::new();
+        move_msg.wheels = self.drive_config.trim_wheels(wheels);
+        self.server.send(Box::new(move_msg))?;
+        self.timed_move = None;
+        if !is_stop {
+            self.last_motion_refresh = Instant::now();
+        }
+        let velocity = self.wheels_velocity(wheels);
+        let now = Instant::now();
+        self.odometry
+            .lock()
+            .unwrap()
+            .set_velocity(velocity, None, now);
+        // the front wheels stand for the sides, so stop and speed changes reach all wheels
+        if let (Some(left), Some(right)) = (wheels.get(0), wheels.get(1)) {
+            self.last_move = (left.speed, left.dir, right.speed, right.dir);
+            self.reset_ramp(self.last_move_command());
+        }
+        self.bot_is_moving = !is_stop;
+        Ok(())
+    }
+
+    // Moves sideways with the current speed, only holonomic chassis can do it
+    pub fn strafe_left(&mut self) {
+        self.strafe(1.0);
+    }
+
+    pub fn strafe_right(&mut self) {
+        self.strafe(-1.0);
+    }
+
+    // Strafes through the arbiter, like the other commands of the input source
+    pub fn submit_strafe(&mut self, source: SourceId, left: bool) -> Result<(), Box<dyn Error>> {
+        match self.strafe_wheels(if left { 1.0 } else { -1.0 }) {
+            Some(wheels) => self.submit_wheels_motion(source, wheels),
+            None => Ok(()),
+        }
+    }
+
+    fn strafe(&mut self, direction: f64) {
+        if let Some(wheels) = self.strafe_wheels(direction) {
+            Robot::log_move_error(self.ask_move_wheels(&wheels), &self.notices);
+        }
+    }
+
+    fn strafe_wheels(&self, direction: f64) -> Option<Vec<WheelState>> {
+        if !self.is_holonomic() {
+            self.notices.notify(format!(
+                "The {} chassis can not move sideways",
+                self.drive_type
+            ));
+            return None;
+        }
+        let wheel_speed = self
+            .drive_config
+            .wheel_speed(self.move_speed, 1, MAX_MOVE_SPEED);
+        let linear_y = direction * wheel_speed * self.drive_config.wheel_radius;
+        Some(self.body_velocity_wheels(0.0, linear_y, 0.0))
+    }
+
+    // Estimated pose since the last reset, fused from the encoders and IMU telemetry
+    // when the robot sends it, otherwise integrated from the wheel commands
+    pub fn pose(&self) -> Pose {
+        current_pose(&self.odometry, &self.pose_filter, Instant::now())
+    }
+
+    // The filtered pose with its covariance, None until the robot sends telemetry
+    pub fn pose_estimate(&self) -> Option<PoseEstimate> {
+        let pose_filter = self.pose_filter.lock().unwrap();
+        if pose_filter.is_active() {
+            Some(pose_filter.estimate())
+        } else {
+            None
+        }
+    }
+
+    pub fn set_filter_config(&mut self, config: FilterConfig) {
+        self.pose_filter.lock().unwrap().set_config(config);
+    }
+
+    pub fn get_filter_config(&self) -> FilterConfig {
+        self.pose_filter.lock().unwrap().get_config()
+    }
+
+    pub fn get_body_velocity(&self) -> BodyVelocity {
+        self.odometry.lock().unwrap().velocity_at(Instant::now())
+    }
+
+    // Makes the current robot position the origin with zero heading, the geofence stays
+    // where it was
+    pub fn reset_pose(&mut self) {
+        self.fence_origin = self.fence_origin.relative_to(self.pose());
+        self.odometry.lock().unwrap().reset(Instant::now());
+        self.pose_filter.lock().unwrap().reset();
+    }
+
+    // Writes the pose for every received camera frame to the CSV file
+    pub fn start_pose_log(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        let log = PoseLog::create(path)?;
+        self.stop_pose_log();
+        *self.pose_log.lock().unwrap() = Some(log);
+        Ok(())
+    }
+
+    pub fn stop_pose_log(&mut self) {
+        if let Some(mut log) = self.pose_log.lock().unwrap().take() {
+            if let Err(err) = log.flush() {
+                self.notices
+                    .notify(format!("Failed to write pose log: {}", err));
+            }
+        }
+    }
+
+    pub fn is_pose_logging(&self) -> bool {
+        self.pose_log.lock().unwrap().is_some()
+    }
+
+    pub fn get_speed(&self) -> u8 {
+        self.move_speed
+    }
+
+    pub fn set_speed(&mut self, speed: u8) {
+        let speed = speed.clamp(MIN_MOVE_SPEED, MAX_MOVE_SPEED);
+        let old_speed = self.move_speed;
+        self.move_speed = speed;
+        if !self.bot_is_moving || speed == old_speed {
+            return;
+        }
+        // missions, scripts and replays drive with their own speeds, timed and steps moves
+        // keep theirs until they end, so they still stop by themselves
+        let now = Instant::now();
+        let active_source = self.arbiter.active_source(now);
+        if self.route_player.is_some()
+            || self.timed_move.is_some()
+            || self.is_steps_moving()
+            || active_source.map_or(false, |source| {
+                Some(source) == self.mission_source || Some(source) == self.script_source
+            })
+        {
+            return;
+        }
+        // keep the wheels ratio of the current move
+        let scale = |wheel_speed: u8| {
+            (wheel_speed as u32 * speed as u32 / old_speed as u32).min(MAX_MOVE_SPEED as u32) as u8
+        };
+        match active_source {
+            // the source command is issued again, so the arbiter sends the new speed
+            Some(source) => {
+                let cmd = match self.arbiter.command(now) {
+                    MotionCommand::Sides(cmd) => MotionCommand::Sides(WheelCommand {
+                        left_speed: scale(cmd.left_speed),
+                        left_dir: cmd.left_dir,
+                        right_speed: scale(cmd.right_speed),
+                        right_dir: cmd.right_dir,
+                    }),
+                    MotionCommand::Wheels(wheels) => MotionCommand::Wheels(
+                        wheels
+                            .iter()
+                            .map(|wheel| WheelState {
+                                speed: scale(wheel.speed),
+                                dir: wheel.dir,
+                            })
+                            .collect(),
+                    ),
+                };
+                self.arbiter.submit_command(source, cmd, now);
+                Robot::log_move_error(self.update_motion(), &self.notices);
+            }
+            None => {
+                let (left_speed, left_dir, right_speed, right_dir) = self.last_move;
+                Robot::log_move_error(
+                    self.ask_move_bot(scale(left_speed), left_dir, scale(right_speed), right_dir),
+                    &self.notices,
+                );
+            }
+        }
+    }
+
+    pub fn increase_speed(&mut self) {
+        self.set_speed(self.move_speed.saturating_add(MOVE_SPEED_STEP));
+    }
+
+    pub fn decrease_speed(&mut self) {
+        self.set_speed(self.move_speed.saturating_sub(MOVE_SPEED_STEP));
     }
 
     pub fn rotate_left(&mut self) {
         if !self.bot_is_moving {
-            self.ask_move_bot(0, 0, self.move_speed, 1);
+            Robot::log_move_error(self.ask_move_bot(0, 0, self.move_speed, 1), &self.notices);
         }
     }
 
     pub fn rotate_right(&mut self) {
         if !self.bot_is_moving {
-            self.ask_move_bot(self.move_speed, 1, 0, 0);
+            Robot::log_move_error(self.ask_move_bot(self.move_speed, 1, 0, 0), &self.notices);
         }
     }
 
     pub fn move_forward(&mut self) {
         if !self.bot_is_moving {
-            self.ask_move_bot(self.move_speed, 1, self.move_speed, 1);
+            Robot::log_move_error(
+                self.ask_move_bot(self.move_speed, 1, self.move_speed, 1),
+                &self.notices,
+            );
         }
     }
 
     pub fn move_backward(&mut self) {
         if !self.bot_is_moving {
-            self.ask_move_bot(self.move_speed, 0, self.move_speed, 0);
+            Robot::log_move_error(
+                self.ask_move_bot(self.move_speed, 0, self.move_speed, 0),
+                &self.notices,
+            );
         }
     }
 
     pub fn stop_moving(&mut self) {
         if self.bot_is_moving {
-            self.ask_move_bot(0, 0, 0, 0);
+            Robot::log_move_error(self.ask_move_bot(0, 0, 0, 0), &self.notices);
             self.bot_is_moving = false;
         }
     }
 
+    // Stops the bot right away and blocks any motion until reset_emergency_stop is called
+    pub fn emergency_stop(&mut self) {
+        self.estop_engaged = true;
+        if let Some(mission) = self.mission.as_mut() {
+            mission.abort();
+        }
+        if self.route_player.take().is_some() {
+            self.notices
+                .notify(String::from("Route replay stopped by the emergency stop"));
+        }
+        if let Some(script) = self.script.as_ref() {
+            script.stop();
+        }
+        self.record_route(&WheelCommand::default().wheels(), Instant::now());
+        self.arbiter.reset();
+        // sent even if the bot is believed to be stopped, bypassing the speed ramp
+        let move_msg = MoveMsg::new();
+        if let Err(err) = self.server.send(Box::new(move_msg)) {
+            self.notices
+                .notify(format!("Failed to send emergency stop: {}", err));
+        }
+        self.last_move = (0, 0, 0, 0);
+        self.timed_move = None;
+        self.reset_ramp(WheelCommand::default());
+        self.set_odometry_command(WheelCommand::default(), None);
+        self.bot_is_moving = false;
+    }
+
+    // Input sources have to send their commands again to move the bot after the reset
+    pub fn reset_emergency_stop(&mut self) {
+        self.arbiter.reset();
+        self.estop_engaged = false;
+    }
+
+    pub fn is_emergency_stopped(&self) -> bool {
+        self.estop_engaged
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.bot_is_moving
+    }
+
     pub fn set_out_resolution(&mut self, width: i32, height: i32) {
         self.image_processor
             .lock()
@@ -329,10 +2073,92 @@ impl Robot {
         self.camera_list.lock().unwrap().clone()
     }
 
+    pub fn is_connected(&self) -> bool {
+        self.connected_flag
+            .load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    pub fn get_peer_addr(&self) -> Option<SocketAddr> {
+        self.server.peer_addr()
+    }
+
+    pub fn get_frame_info(&self, camera_id: u8) -> Option<FrameInfo> {
+        self.image_processor
+            .lock()
+            .unwrap()
+            .get_frame_info(camera_id)
+    }
+
     pub fn get_image(&self, camera_id: u8) -> Option<Vec<u8>> {
         self.image_processor
             .lock()
             .unwrap()
             .get_scaled_image_data(camera_id)
     }
+
+    pub fn get_frame(&self, camera_id: u8) -> Result<Option<Frame>, Box<dyn Error>> {
+        self.image_processor
+            .lock()
+            .unwrap()
+            .get_image_data(camera_id)
+    }
+
+    pub fn get_encoded_image(
+        &self,
+        camera_id: u8,
+        ext: &str,
+        params: &[i32],
+    ) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        self.image_processor
+            .lock()
+            .unwrap()
+            .encode_image(camera_id, ext, params)
+    }
+
+    pub fn wait_camera_list(&self, timeout: Duration) -> Result<Vec<u8>, Box<dyn Error>> {
+        let start = Instant::now();
+        loop {
+            if let Some(camera_list) = self.get_camera_list() {
+                return Ok(camera_list);
+            }
+            if start.elapsed() > timeout {
+                return Err(Box::new(RobotErrors::Timeout));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    pub fn wait_camera_resolutions(
+        &self,
+        camera_id: u8,
+        timeout: Duration,
+    ) -> Result<Vec<(i32, i32)>, Box<dyn Error>> {
+        let start = Instant::now();
+        loop {
+            if let Some(resolutions) = self.get_camera_resolutions(camera_id) {
+                return Ok(resolutions);
+            }
+            if start.elapsed() > timeout {
+                return Err(Box::new(RobotErrors::Timeout));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    pub fn wait_frame_info(
+        &self,
+        camera_id: u8,
+        timeout: Duration,
+    ) -> Result<FrameInfo, Box<dyn Error>> {
+        let start = Instant::now();
+        loop {
+            if let Some(info) = self.get_frame_info(camera_id) {
+                return Ok(info);
+            }
+            if start.elapsed() > timeout {
+                return Err(Box::new(RobotErrors::Timeout));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
 }
@@ -0,0 +1,442 @@
+// Teach and repeat: the motion commands sent during a manual drive are recorded with
+// their times and can be replayed later, forward or in reverse to return to the start.
+// Route files are CSV with the time in seconds followed by the speed and direction of
+// every wheel, two wheels for the sides commands and more for the multi-wheel ones.
+use super::kinematics;
+use super::robot;
+use kinematics::{WheelState, DIR_BACKWARD, DIR_FORWARD};
+use robot::MAX_MOVE_SPEED;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_ROUTE_FILE: &str = "netbot-route.csv";
+pub const MIN_SPEED_SCALE: f64 = 0.1;
+pub const MAX_SPEED_SCALE: f64 = 2.0;
+
+#[derive(Debug)]
+pub enum RouteErrors {
+    // line number starting from 1
+    WrongEntry(usize),
+    EmptyRoute,
+}
+impl fmt::Display for RouteErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error: {:?}", self)
+    }
+}
+impl Error for RouteErrors {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteEntry {
+    // since the first command
+    pub time: Duration,
+    pub wheels: Vec<WheelState>,
+}
+
+fn is_stop(wheels: &[WheelState]) -> bool {
+    wheels.iter().all(|wheel| wheel.speed == 0)
+}
+
+fn stop_wheels(count: usize) -> Vec<WheelState> {
+    vec![WheelState::default(); count]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    entries: Vec<RouteEntry>,
+}
+
+impl Route {
+    pub fn entries(&self) -> &[RouteEntry] {
+        &self.entries
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.entries
+            .last()
+            .map_or(Duration::from_secs(0), |entry| entry.time)
+    }
+
+    pub fn parse(text: &str) -> Result<Route, Box<dyn Error>> {
+        let mut entries: Vec<RouteEntry> = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let wrong_entry = || Box::new(RouteErrors::WrongEntry(i + 1)) as Box<dyn Error>;
+            let values: Vec<&str> = line.split(',').map(|value| value.trim()).collect();
+            // the time and at least two wheels
+            if values.len() < 5 || values.len() % 2 == 0 {
+                return Err(wrong_entry());
+            }
+            let seconds = values[0]
+                .parse::<f64>()
+                .ok()
+                .filter(|seconds| *seconds >= 0.0 && seconds.is_finite())
+                .ok_or_else(wrong_entry)?;
+            let time = Duration::from_secs_f64(seconds);
+            if entries.last().map_or(false, |last| last.time > time) {
+                return Err(wrong_entry());
+            }
+            let mut wheels = Vec::new();
+            for pair in values[1..].chunks(2) {
+                let speed = pair[0].parse::<u8>().map_err(|_| wrong_entry())?;
+                let dir = pair[1]
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|dir| *dir == DIR_FORWARD || *dir == DIR_BACKWARD)
+                    .ok_or_else(wrong_entry)?;
+                wheels.push(WheelState {
+                    speed: speed,
+                    dir: dir,
+                });
+            }
+            entries.push(RouteEntry {
+                time: time,
+                wheels: wheels,
+            });
+        }
+        if entries.is_empty() {
+            return Err(Box::new(RouteErrors::EmptyRoute));
+        }
+        Ok(Route { entries: entries })
+    }
+
+    pub fn load(path: &str) -> Result<Route, Box<dyn Error>> {
+        Route::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let mut text = String::from("# time, then speed and direction of every wheel\n");
+        for entry in &self.entries {
+            text.push_str(&format!("{:.3}", entry.time.as_secs_f64()));
+            for wheel in &entry.wheels {
+                text.push_str(&format!(",{},{}", wheel.speed, wheel.dir));
+            }
+            text.push('\n');
+        }
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    // The same path driven backward from the end to the start: the commands go in the
+    // reverse order with every wheel turning the other way
+    pub fn reversed(&self) -> Route {
+        let end = self.duration();
+        let mut entries = Vec::new();
+        for pair in self.entries.windows(2).rev() {
+            entries.push(RouteEntry {
+                time: end - pair[1].time,
+                wheels: pair[0]
+                    .wheels
+                    .iter()
+                    .map(|wheel| WheelState {
+                        speed: wheel.speed,
+                        dir: if wheel.dir == DIR_FORWARD {
+                            DIR_BACKWARD
+                        } else {
+                            DIR_FORWARD
+                        },
+                    })
+                    .collect(),
+            });
+        }
+        if let Some(first) = self.entries.first() {
+            entries.push(RouteEntry {
+                time: end - first.time,
+                wheels: stop_wheels(first.wheels.len()),
+            });
+        }
+        Route { entries: entries }
+    }
+}
+
+pub struct RouteRecorder {
+    entries: Vec<RouteEntry>,
+    start: Instant,
+}
+
+impl RouteRecorder {
+    pub fn new(now: Instant) -> RouteRecorder {
+        RouteRecorder {
+            entries: Vec::new(),
+            start: now,
+        }
+    }
+
+    // The time can be in the future for the stop at the end of a timed move, it is
+    // dropped when another command comes earlier
+    pub fn record(&mut self, wheels: &[WheelState], time: Instant) {
+        let time = time.saturating_duration_since(self.start);
+        while self.entries.last().map_or(false, |last| last.time > time) {
+            self.entries.pop();
+        }
+        if self
+            .entries
+            .last()
+            .map_or(false, |last| last.wheels == wheels)
+        {
+            return;
+        }
+        // the idle time before the first move is not recorded
+        if self.entries.is_empty() && is_stop(wheels) {
+            return;
+        }
+        self.entries.push(RouteEntry {
+            time: time,
+            wheels: wheels.to_vec(),
+        });
+    }
+
+    // The route starts at the first command and ends with a stop
+    pub fn finish(mut self, now: Instant) -> Result<Route, Box<dyn Error>> {
+        let wheels_count = match self.entries.last() {
+            Some(last) if !is_stop(&last.wheels) => last.wheels.len(),
+            Some(_) => 0,
+            None => return Err(Box::new(RouteErrors::EmptyRoute)),
+        };
+        if wheels_count > 0 {
+            self.record(&stop_wheels(wheels_count), now);
+        }
+        let first_time = self.entries[0].time;
+        for entry in self.entries.iter_mut() {
+            entry.time -= first_time;
+        }
+        Ok(Route {
+            entries: self.entries,
+        })
+    }
+}
+
+// Plays the route back in real time. The speed scale multiplies the wheel speeds and
+// divides the times, so the robot drives the same path faster or slower.
+pub struct RoutePlayer {
+    route: Route,
+    speed_scale: f64,
+    // seconds of the route played so far
+    position: f64,
+    next: usize,
+    last_update: Instant,
+    // the current command is sent again with the new scale
+    scale_changed: bool,
+}
+
+impl RoutePlayer {
+    pub fn new(route: Route, speed_scale: f64, now: Instant) -> RoutePlayer {
+        RoutePlayer {
+            route: route,
+            speed_scale: speed_scale.clamp(MIN_SPEED_SCALE, MAX_SPEED_SCALE),
+            position: 0.0,
+            next: 0,
+            last_update: now,
+            scale_changed: false,
+        }
+    }
+
+    pub fn set_speed_scale(&mut self, speed_scale: f64) {
+        let speed_scale = speed_scale.clamp(MIN_SPEED_SCALE, MAX_SPEED_SCALE);
+        self.scale_changed = speed_scale != self.speed_scale;
+        self.speed_scale = speed_scale;
+    }
+
+    pub fn get_speed_scale(&self) -> f64 {
+        self.speed_scale
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.route.entries.len()
+    }
+
+    // Played and total seconds of the route at the recorded speed
+    pub fn progress(&self) -> (f64, f64) {
+        (self.position, self.route.duration().as_secs_f64())
+    }
+
+    // Returns the wheels to send when a new command is due
+    pub fn update(&mut self, now: Instant) -> Option<Vec<WheelState>> {
+        let elapsed = now
+            .saturating_duration_since(self.last_update)
+            .as_secs_f64();
+        self.last_update = now;
+        self.position += elapsed * self.speed_scale;
+        let mut due = if self.scale_changed && self.next > 0 {
+            Some(&self.route.entries[self.next - 1].wheels)
+        } else {
+            None
+        };
+        self.scale_changed = false;
+        while let Some(entry) = self.route.entries.get(self.next) {
+            if entry.time.as_secs_f64() > self.position {
+                break;
+            }
+            due = Some(&entry.wheels);
+            self.next += 1;
+        }
+        let speed_scale = self.speed_scale;
+        due.map(|wheels| {
+            wheels
+                .iter()
+                .map(|wheel| WheelState {
+                    // slow scales do not turn a slow wheel into a stop
+                    speed: if wheel.speed == 0 {
+                        0
+                    } else {
+                        (wheel.speed as f64 * speed_scale)
+                            .round()
+                            .clamp(1.0, MAX_MOVE_SPEED as f64) as u8
+                    },
+                    dir: wheel.dir,
+                })
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::parse_error;
+    use super::*;
+
+    fn wheels(speeds: &[(u8, u8)]) -> Vec<WheelState> {
+        speeds
+            .iter()
+            .map(|(speed, dir)| WheelState {
+                speed: *speed,
+                dir: *dir,
+            })
+            .collect()
+    }
+
+    fn entry(seconds: f64, speeds: &[(u8, u8)]) -> RouteEntry {
+        RouteEntry {
+            time: Duration::from_secs_f64(seconds),
+            wheels: wheels(speeds),
+        }
+    }
+
+    #[test]
+    fn parse_entries() {
+        let route = Route::parse(
+            "# time, then speed and direction of every wheel\n\n0.000,40,1,40,1\n 1.5 , 30,1,50,0\n2,0,1,0,1,0,1,0,1\n",
+        )
+        .unwrap();
+        assert_eq!(
+            route.entries(),
+            &[
+                entry(0.0, &[(40, DIR_FORWARD), (40, DIR_FORWARD)]),
+                entry(1.5, &[(30, DIR_FORWARD), (50, DIR_BACKWARD)]),
+                entry(2.0, &[(0, DIR_FORWARD); 4]),
+            ][..]
+        );
+        assert_eq!(route.duration(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn parse_wrong_entries() {
+        let wrong = [
+            ("0,40,1", 1),
+            ("0,40,1,40", 1),
+            ("0,40,1,40,2", 1),
+            ("0,300,1,40,1", 1),
+            ("-1,40,1,40,1", 1),
+            ("now,40,1,40,1", 1),
+            ("# start\n2,40,1,40,1\n1,0,1,0,1", 3),
+        ];
+        for (text, line) in wrong.iter() {
+            assert!(
+                matches!(
+                    parse_error(Route::parse(text)),
+                    Some(RouteErrors::WrongEntry(wrong_line)) if wrong_line == *line
+                ),
+                "{}",
+                text
+            );
+        }
+        match Route::parse("# nothing\n") {
+            Err(err) => assert!(matches!(
+                err.downcast_ref::<RouteErrors>(),
+                Some(RouteErrors::EmptyRoute)
+            )),
+            Ok(_) => panic!("an empty route was parsed"),
+        }
+    }
+
+    #[test]
+    fn reversed_route() {
+        let route = Route::parse("0,40,1,40,1\n1,30,1,50,0\n3,0,1,0,1").unwrap();
+        let reversed = route.reversed();
+        assert_eq!(
+            reversed.entries(),
+            &[
+                entry(0.0, &[(30, DIR_BACKWARD), (50, DIR_FORWARD)]),
+                entry(2.0, &[(40, DIR_BACKWARD), (40, DIR_BACKWARD)]),
+                RouteEntry {
+                    time: Duration::from_secs(3),
+                    wheels: stop_wheels(2),
+                },
+            ][..]
+        );
+        assert_eq!(reversed.duration(), route.duration());
+        // driving back and forth again gives the directions of the route
+        assert_eq!(reversed.reversed().entries()[..2], route.entries()[..2]);
+    }
+
+    #[test]
+    fn player_timing() {
+        let route = Route::parse("0,40,1,40,1\n1,30,1,50,0\n2,0,1,0,1").unwrap();
+        let start = Instant::now();
+        let mut player = RoutePlayer::new(route, 1.0, start);
+        assert_eq!(
+            player.update(start),
+            Some(wheels(&[(40, DIR_FORWARD), (40, DIR_FORWARD)]))
+        );
+        assert_eq!(player.update(start + Duration::from_millis(500)), None);
+        assert_eq!(
+            player.update(start + Duration::from_millis(1000)),
+            Some(wheels(&[(30, DIR_FORWARD), (50, DIR_BACKWARD)]))
+        );
+        assert!(!player.is_finished());
+        // the last due command wins after a long pause
+        assert_eq!(
+            player.update(start + Duration::from_secs(5)),
+            Some(wheels(&[(0, DIR_FORWARD), (0, DIR_FORWARD)]))
+        );
+        assert!(player.is_finished());
+        assert_eq!(player.progress(), (5.0, 2.0));
+    }
+
+    #[test]
+    fn player_scaling() {
+        let route = Route::parse("0,40,1,1,0\n2,90,1,90,1\n4,0,1,0,1").unwrap();
+        let start = Instant::now();
+        let mut player = RoutePlayer::new(route, 0.5, start);
+        // a slow wheel does not stop
+        assert_eq!(
+            player.update(start),
+            Some(wheels(&[(20, DIR_FORWARD), (1, DIR_BACKWARD)]))
+        );
+        // the times are divided by the scale
+        assert_eq!(player.update(start + Duration::from_secs(2)), None);
+        assert_eq!(
+            player.update(start + Duration::from_secs(4)),
+            Some(wheels(&[(45, DIR_FORWARD), (45, DIR_FORWARD)]))
+        );
+        // the current command is sent again with the new scale, capped to the move speed
+        player.set_speed_scale(MAX_SPEED_SCALE);
+        assert_eq!(player.get_speed_scale(), MAX_SPEED_SCALE);
+        assert_eq!(
+            player.update(start + Duration::from_secs(4)),
+            Some(wheels(&[
+                (MAX_MOVE_SPEED, DIR_FORWARD),
+                (MAX_MOVE_SPEED, DIR_FORWARD)
+            ]))
+        );
+        assert_eq!(player.update(start + Duration::from_secs(4)), None);
+        player.set_speed_scale(10.0);
+        assert_eq!(player.get_speed_scale(), MAX_SPEED_SCALE);
+        assert_eq!(player.update(start + Duration::from_secs(4)), None);
+    }
+}
@@ -0,0 +1,556 @@
+// Robot behaviours written in Rhai scripts. The script runs in its own thread and calls
+// the robot through a channel, the calls are executed by Robot::update on the thread
+// which owns the robot, so the front-ends stay responsive. The sandbox has no module
+// imports or eval, stops the script after the time limit and saves snapshots only by
+// file name into the working directory.
+//
+// Script functions:
+//   forward(), backward(), left(), right(), stop()    drive with the robot speed
+//   drive(linear, angular)                            m/s and rad/s, positive to the left
+//   drive_by(meters), turn_by(degrees)                move by the estimated pose and stop
+//   set_speed(speed), speed()
+//   pose()                                            #{x, y, heading}, heading in degrees
+//   sleep(ms), millis()                               milliseconds since the script start
+//   cameras(), set_camera(camera, width, height, fps, encode)
+//   snapshot(camera, file_name)                       returns the file size
+//   frame_stats(camera)                               #{width, height, number, encoded,
+//                                                     brightness, red, green, blue}
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Position, FLOAT, INT};
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_TIME_LIMIT: Duration = Duration::from_secs(60);
+// the stop flag and the time limit are checked this often while the script waits
+const WAIT_SLICE: Duration = Duration::from_millis(10);
+const MOVE_BY_POLL_INTERVAL: Duration = Duration::from_millis(20);
+// drive_by and turn_by speeds, m/s and rad/s
+const MOVE_BY_LINEAR_SPEED: f64 = 0.05;
+const MOVE_BY_ANGULAR_SPEED: f64 = 0.5;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_EXPR_DEPTH: usize = 64;
+const MAX_STRING_SIZE: usize = 64 * 1024;
+const MAX_ARRAY_SIZE: usize = 10000;
+const MAX_MAP_SIZE: usize = 1000;
+
+#[derive(Debug)]
+pub enum ScriptErrors {
+    // only plain file names in the working directory
+    WrongSnapshotPath,
+}
+impl fmt::Display for ScriptErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error: {:?}", self)
+    }
+}
+impl Error for ScriptErrors {}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptConfig {
+    pub time_limit: Duration,
+}
+
+impl Default for ScriptConfig {
+    fn default() -> ScriptConfig {
+        ScriptConfig {
+            time_limit: DEFAULT_TIME_LIMIT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptMotion {
+    Forward,
+    Backward,
+    Left,
+    Right,
+}
+
+// Robot functions the script can call
+#[derive(Debug, Clone, PartialEq)]
+pub enum RobotCall {
+    Move(ScriptMotion),
+    Drive {
+        linear: f64,
+        angular: f64,
+    },
+    Stop,
+    SetSpeed(u8),
+    GetSpeed,
+    GetPose,
+    GetCameras,
+    SetCamera {
+        camera_id: u8,
+        width: u16,
+        height: u16,
+        fps: u8,
+        encode: bool,
+    },
+    Snapshot {
+        camera_id: u8,
+        path: String,
+    },
+    FrameStats(u8),
+}
+
+// Values passed back to the script
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptValue {
+    Unit,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    List(Vec<ScriptValue>),
+    Record(Vec<(&'static str, ScriptValue)>),
+}
+
+impl ScriptValue {
+    fn into_dynamic(self) -> Dynamic {
+        match self {
+            ScriptValue::Unit => Dynamic::UNIT,
+            ScriptValue::Bool(value) => Dynamic::from(value),
+            ScriptValue::Int(value) => Dynamic::from(value as INT),
+            ScriptValue::Float(value) => Dynamic::from(value as FLOAT),
+            ScriptValue::List(values) => {
+                let array: Array = values.into_iter().map(ScriptValue::into_dynamic).collect();
+                Dynamic::from(array)
+            }
+            ScriptValue::Record(fields) => {
+                let mut map = Map::new();
+                for (name, value) in fields {
+                    map.insert(name.into(), value.into_dynamic());
+                }
+                Dynamic::from(map)
+            }
+        }
+    }
+}
+
+pub struct ScriptCall {
+    pub call: RobotCall,
+    reply: Sender<Result<ScriptValue, String>>,
+}
+
+impl ScriptCall {
+    pub fn reply(self, result: Result<ScriptValue, String>) {
+        // the script may be stopped already
+        let _ = self.reply.send(result);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptEvent {
+    Output(String),
+    Finished,
+    Failed(String),
+    TimedOut,
+    Stopped,
+}
+
+impl ScriptEvent {
+    // The script thread ends after these events
+    pub fn is_final(&self) -> bool {
+        !matches!(self, ScriptEvent::Output(_))
+    }
+}
+
+impl fmt::Display for ScriptEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptEvent::Output(text) => write!(f, "{}", text),
+            ScriptEvent::Finished => write!(f, "script finished"),
+            ScriptEvent::Failed(err) => write!(f, "script failed: {}", err),
+            ScriptEvent::TimedOut => write!(f, "script stopped, the time limit is over"),
+            ScriptEvent::Stopped => write!(f, "script stopped"),
+        }
+    }
+}
+
+// Snapshots are written only by file name into the working directory
+pub fn check_snapshot_path(path: &str) -> Result<(), Box<dyn Error>> {
+    let is_plain_name = !path.is_empty()
+        && !path.starts_with('.')
+        && !path.contains(|c| c == '/' || c == '\\' || c == ':');
+    if is_plain_name {
+        Ok(())
+    } else {
+        Err(Box::new(ScriptErrors::WrongSnapshotPath))
+    }
+}
+
+// Why the script has to end, checked whenever it runs or waits
+#[derive(Clone)]
+struct Sandbox {
+    stop_flag: Arc<AtomicBool>,
+    start: Instant,
+    time_limit: Duration,
+}
+
+const STOPPED_TOKEN: &str = "stopped";
+const TIMED_OUT_TOKEN: &str = "timed out";
+
+impl Sandbox {
+    fn check(&self) -> Result<(), Box<EvalAltResult>> {
+        match self.termination() {
+            Some(token) => Err(Box::new(EvalAltResult::ErrorTerminated(
+                Dynamic::from(token),
+                Position::NONE,
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    fn termination(&self) -> Option<&'static str> {
+        if self.stop_flag.load(Ordering::SeqCst) {
+            Some(STOPPED_TOKEN)
+        } else if self.start.elapsed() > self.time_limit {
+            Some(TIMED_OUT_TOKEN)
+        } else {
+            None
+        }
+    }
+
+    fn sleep(&self, duration: Duration) -> Result<(), Box<EvalAltResult>> {
+        let end = Instant::now() + duration;
+        loop {
+            self.check()?;
+            let now = Instant::now();
+            if now >= end {
+                return Ok(());
+            }
+            thread::sleep((end - now).min(WAIT_SLICE));
+        }
+    }
+}
+
+// The script side of the robot calls
+#[derive(Clone)]
+struct RobotLink {
+    calls: Sender<ScriptCall>,
+    sandbox: Sandbox,
+}
+
+impl RobotLink {
+    fn call(&self, call: RobotCall) -> Result<ScriptValue, Box<EvalAltResult>> {
+        self.sandbox.check()?;
+        let (reply_sender, reply_receiver) = channel();
+        self.calls
+            .send(ScriptCall {
+                call: call,
+                reply: reply_sender,
+            })
+            .map_err(|_| "the robot is gone")?;
+        loop {
+            match reply_receiver.recv_timeout(WAIT_SLICE) {
+                Ok(result) => return result.map_err(|err| err.into()),
+                Err(RecvTimeoutError::Timeout) => self.sandbox.check()?,
+                Err(RecvTimeoutError::Disconnected) => return Err("the robot is gone".into()),
+            }
+        }
+    }
+
+    fn call_unit(&self, call: RobotCall) -> Result<(), Box<EvalAltResult>> {
+        self.call(call).map(|_| ())
+    }
+
+    fn pose(&self) -> Result<(f64, f64, f64), Box<EvalAltResult>> {
+        match self.call(RobotCall::GetPose)? {
+            ScriptValue::Record(fields) => {
+                let field = |name: &str| {
+                    fields
+                        .iter()
+                        .find(|field| field.0 == name)
+                        .map_or(0.0, |field| match field.1 {
+                            ScriptValue::Float(value) => value,
+                            _ => 0.0,
+                        })
+                };
+                Ok((field("x"), field("y"), field("heading").to_radians()))
+            }
+            _ => Err("wrong pose value".into()),
+        }
+    }
+
+    fn drive_by(&self, meters: f64) -> Result<(), Box<EvalAltResult>> {
+        let (start_x, start_y, _) = self.pose()?;
+        let speed = MOVE_BY_LINEAR_SPEED.copysign(meters);
+        self.call_unit(RobotCall::Drive {
+            linear: speed,
+            angular: 0.0,
+        })?;
+        loop {
+            let (x, y, _) = self.pose()?;
+            if (x - start_x).hypot(y - start_y) >= meters.abs() {
+                break;
+            }
+            self.sandbox.sleep(MOVE_BY_POLL_INTERVAL)?;
+        }
+        self.call_unit(RobotCall::Stop)
+    }
+
+    fn turn_by(&self, degrees: f64) -> Result<(), Box<EvalAltResult>> {
+        let target = degrees.to_radians();
+        let speed = MOVE_BY_ANGULAR_SPEED.copysign(target);
+        let (_, _, mut last_heading) = self.pose()?;
+        let mut turned: f64 = 0.0;
+        self.call_unit(RobotCall::Drive {
+            linear: 0.0,
+            angular: speed,
+        })?;
+        while turned.abs() < target.abs() {
+            self.sandbox.sleep(MOVE_BY_POLL_INTERVAL)?;
+            let (_, _, heading) = self.pose()?;
+            // the heading wraps around at pi
+            let mut step = heading - last_heading;
+            if step > PI {
+                step -= 2.0 * PI;
+            } else if step < -PI {
+                step += 2.0 * PI;
+            }
+            turned += step;
+            last_heading = heading;
+        }
+        self.call_unit(RobotCall::Stop)
+    }
+}
+
+fn to_u8(value: INT, name: &str) -> Result<u8, Box<EvalAltResult>> {
+    if value >= 0 && value <= u8::MAX as INT {
+        Ok(value as u8)
+    } else {
+        Err(format!("wrong {} {}", name, value).into())
+    }
+}
+
+fn to_u16(value: INT, name: &str) -> Result<u16, Box<EvalAltResult>> {
+    if value >= 0 && value <= u16::MAX as INT {
+        Ok(value as u16)
+    } else {
+        Err(format!("wrong {} {}", name, value).into())
+    }
+}
+
+fn create_engine(link: RobotLink, events: Sender<ScriptEvent>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH);
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_ARRAY_SIZE);
+    engine.set_max_map_size(MAX_MAP_SIZE);
+    {
+        let sandbox = link.sandbox.clone();
+        engine.on_progress(move |_| sandbox.termination().map(Dynamic::from));
+    }
+    {
+        let events = events.clone();
+        engine.on_print(move |text| {
+            let _ = events.send(ScriptEvent::Output(String::from(text)));
+        });
+    }
+    engine.on_debug(move |text, _, _| {
+        let _ = events.send(ScriptEvent::Output(String::from(text)));
+    });
+
+    let motions = [
+        ("forward", ScriptMotion::Forward),
+        ("backward", ScriptMotion::Backward),
+        ("left", ScriptMotion::Left),
+        ("right", ScriptMotion::Right),
+    ];
+    for (name, motion) in motions.iter() {
+        let link = link.clone();
+        let motion = *motion;
+        engine.register_fn(*name, move || link.call_unit(RobotCall::Move(motion)));
+    }
+    {
+        let link = link.clone();
+        engine.register_fn("stop", move || link.call_unit(RobotCall::Stop));
+    }
+    {
+        let link = link.clone();
+        engine.register_fn("drive", move |linear: FLOAT, angular: FLOAT| {
+            link.call_unit(RobotCall::Drive {
+                linear: linear,
+                angular: angular,
+            })
+        });
+    }
+    {
+        let link = link.clone();
+        engine.register_fn("drive_by", move |meters: FLOAT| link.drive_by(meters));
+    }
+    {
+        let link = link.clone();
+        engine.register_fn("turn_by", move |degrees: FLOAT| link.turn_by(degrees));
+    }
+    {
+        let link = link.clone();
+        engine.register_fn("set_speed", move |speed: INT| {
+            link.call_unit(RobotCall::SetSpeed(to_u8(speed, "speed")?))
+        });
+    }
+    {
+        let link = link.clone();
+        engine.register_fn("speed", move || {
+            link.call(RobotCall::GetSpeed)
+                .map(ScriptValue::into_dynamic)
+        });
+    }
+    {
+        let link = link.clone();
+        engine.register_fn("pose", move || {
+            link.call(RobotCall::GetPose).map(ScriptValue::into_dynamic)
+        });
+    }
+    {
+        let sandbox = link.sandbox.clone();
+        engine.register_fn("sleep", move |ms: INT| {
+            sandbox.sleep(Duration::from_millis(ms.max(0) as u64))
+        });
+    }
+    {
+        let sandbox = link.sandbox.clone();
+        engine.register_fn("millis", move || sandbox.start.elapsed().as_millis() as INT);
+    }
+    {
+        let link = link.clone();
+        engine.register_fn("cameras", move || {
+            link.call(RobotCall::GetCameras)
+                .map(ScriptValue::into_dynamic)
+        });
+    }
+    {
+        let link = link.clone();
+        engine.register_fn(
+            "set_camera",
+            move |camera_id: INT, width: INT, height: INT, fps: INT, encode: bool| {
+                link.call_unit(RobotCall::SetCamera {
+                    camera_id: to_u8(camera_id, "camera")?,
+                    width: to_u16(width, "width")?,
+                    height: to_u16(height, "height")?,
+                    fps: to_u8(fps, "fps")?,
+                    encode: encode,
+                })
+            },
+        );
+    }
+    {
+        let link = link.clone();
+        engine.register_fn("snapshot", move |camera_id: INT, path: &str| {
+            check_snapshot_path(path).map_err(|err| err.to_string())?;
+            link.call(RobotCall::Snapshot {
+                camera_id: to_u8(camera_id, "camera")?,
+                path: String::from(path),
+            })
+            .map(ScriptValue::into_dynamic)
+        });
+    }
+    engine.register_fn("frame_stats", move |camera_id: INT| {
+        link.call(RobotCall::FrameStats(to_u8(camera_id, "camera")?))
+            .map(ScriptValue::into_dynamic)
+    });
+    engine
+}
+
+fn run_script(source: &str, link: RobotLink, events: Sender<ScriptEvent>) {
+    let sandbox = link.sandbox.clone();
+    let engine = create_engine(link, events.clone());
+    let result = engine
+        .compile(source)
+        .map_err(|err| err.to_string())
+        .and_then(|ast| {
+            engine.run_ast(&ast).map_err(|err| match *err {
+                EvalAltResult::ErrorTerminated(..) => String::new(),
+                err => err.to_string(),
+            })
+        });
+    let event = match (result, sandbox.termination()) {
+        (Ok(()), _) => ScriptEvent::Finished,
+        (Err(_), Some(STOPPED_TOKEN)) => ScriptEvent::Stopped,
+        (Err(_), Some(_)) => ScriptEvent::TimedOut,
+        (Err(err), None) => ScriptEvent::Failed(err),
+    };
+    let _ = events.send(event);
+}
+
+// Owner side of a running script, Robot executes its calls
+pub struct ScriptRunner {
+    calls: Receiver<ScriptCall>,
+    events: Receiver<ScriptEvent>,
+    stop_flag: Arc<AtomicBool>,
+    thread_handle: Option<thread::JoinHandle<()>>,
+    finished: bool,
+}
+
+impl ScriptRunner {
+    // Syntax errors are reported here, before the script starts
+    pub fn start(source: &str, config: ScriptConfig) -> Result<ScriptRunner, Box<dyn Error>> {
+        Engine::new_raw().compile(source)?;
+        let (call_sender, call_receiver) = channel();
+        let (event_sender, event_receiver) = channel();
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let link = RobotLink {
+            calls: call_sender,
+            sandbox: Sandbox {
+                stop_flag: Arc::clone(&stop_flag),
+                start: Instant::now(),
+                time_limit: config.time_limit,
+            },
+        };
+        let source = String::from(source);
+        let thread_handle = thread::Builder::new()
+            .name(String::from("netbot-script"))
+            .spawn(move || run_script(&source, link, event_sender))?;
+        Ok(ScriptRunner {
+            calls: call_receiver,
+            events: event_receiver,
+            stop_flag: stop_flag,
+            thread_handle: Some(thread_handle),
+            finished: false,
+        })
+    }
+
+    pub fn load(path: &str, config: ScriptConfig) -> Result<ScriptRunner, Box<dyn Error>> {
+        ScriptRunner::start(&fs::read_to_string(path)?, config)
+    }
+
+    // The script ends at its next function call or loop iteration
+    pub fn stop(&self) {
+        self.stop_flag.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn take_calls(&self) -> Vec<ScriptCall> {
+        self.calls.try_iter().collect()
+    }
+
+    pub fn take_events(&mut self) -> Vec<ScriptEvent> {
+        let events: Vec<ScriptEvent> = self.events.try_iter().collect();
+        if events.iter().any(ScriptEvent::is_final) {
+            self.finished = true;
+            if let Some(handle) = self.thread_handle.take() {
+                let _ = handle.join();
+            }
+        }
+        events
+    }
+}
+
+impl Drop for ScriptRunner {
+    fn drop(&mut self) {
+        // the thread is left to end by itself, it may wait for a robot call
+        self.stop();
+    }
+}
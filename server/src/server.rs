@@ -2,15 +2,23 @@ use std::error::Error;
 use std::fmt;
 use std::io::{Read, Write};
 use std::net::TcpStream;
-use std::net::{Ipv4Addr, SocketAddrV4, TcpListener};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpListener};
 use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use super::message;
 use message::SendMessage;
 
+// larger sizes come only from broken or foreign clients
+const MAX_MESSAGE_SIZE: u32 = 64 * 1024 * 1024;
+
 #[derive(Debug)]
-enum ServerErrors {
+pub enum ServerErrors {
     MissedConnection,
+    ConnectionTimeout,
+    ReceiveTimeout,
+    WrongMessageSize,
 }
 impl fmt::Display for ServerErrors {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -39,16 +47,51 @@ impl Server {
         Server { tcp_stream: None }
     }
     pub fn wait_client(&mut self, addr: Ipv4Addr, port: u16) -> Result<(), Box<dyn Error>> {
+        self.wait_client_timeout(addr, port, None)
+    }
+
+    pub fn wait_client_timeout(
+        &mut self,
+        addr: Ipv4Addr,
+        port: u16,
+        timeout: Option<Duration>,
+    ) -> Result<(), Box<dyn Error>> {
         let socket_addr = SocketAddrV4::new(addr, port);
         let listener = Rc::new(TcpListener::bind(socket_addr)?);
         let port = listener.local_addr()?;
-        println!("Listening on {}, access this port from a client", port);
-        let (tcp_stream, client_addr) = listener.accept()?; //block  until requested
+        eprintln!("Listening on {}, access this port from a client", port);
+        let (tcp_stream, client_addr) = match timeout {
+            None => listener.accept()?, //block  until requested
+            Some(timeout) => {
+                listener.set_nonblocking(true)?;
+                let start = Instant::now();
+                loop {
+                    match listener.accept() {
+                        Ok(connection) => break connection,
+                        Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                            if start.elapsed() > timeout {
+                                return Err(Box::new(ServerErrors::ConnectionTimeout));
+                            }
+                            thread::sleep(Duration::from_millis(50));
+                        }
+                        Err(err) => return Err(Box::new(err)),
+                    }
+                }
+            }
+        };
+        tcp_stream.set_nonblocking(false)?;
         self.tcp_stream = Some(tcp_stream);
-        println!("Connection received! {:?}", client_addr);
+        eprintln!("Connection received! {:?}", client_addr);
         Ok(())
     }
 
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        match &self.tcp_stream {
+            Some(stream) => stream.peer_addr().ok(),
+            None => None,
+        }
+    }
+
     pub fn send(&mut self, msg: Box<dyn SendMessage>) -> Result<(), Box<dyn Error>> {
         let mut msg = msg;
         match &mut self.tcp_stream {
@@ -71,6 +114,30 @@ impl Server {
         }
     }
 
+    // None waits for the message as long as needed
+    pub fn recv_timeout(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<(u8, Vec<u8>), Box<dyn Error>> {
+        match &self.tcp_stream {
+            Some(stream) => stream.set_read_timeout(timeout)?,
+            None => return Err(Box::new(ServerErrors::MissedConnection)),
+        }
+        let result = self.recv();
+        if let Some(stream) = &self.tcp_stream {
+            stream.set_read_timeout(None)?;
+        }
+        result.map_err(|err| match err.downcast_ref::<std::io::Error>() {
+            Some(io_err)
+                if io_err.kind() == std::io::ErrorKind::WouldBlock
+                    || io_err.kind() == std::io::ErrorKind::TimedOut =>
+            {
+                Box::new(ServerErrors::ReceiveTimeout)
+            }
+            _ => err,
+        })
+    }
+
     pub fn recv(&mut self) -> Result<(u8, Vec<u8>), Box<dyn Error>> {
         match &mut self.tcp_stream {
             Some(stream) => {
@@ -79,6 +146,9 @@ impl Server {
                 let tmp = slice_as_array!(&id_size[1..], [u8; 4])
                     .expect("Server::recv wrong header data");
                 let size = u32::from_be_bytes(*tmp);
+                if size > MAX_MESSAGE_SIZE {
+                    return Err(Box::new(ServerErrors::WrongMessageSize));
+                }
                 let id = id_size[0];
                 let mut buf: Vec<u8> = Vec::new();
                 buf.resize(size as usize, 0);
@@ -0,0 +1,101 @@
+use super::message;
+use message::{Message, MessageId, RecvMessage};
+use std::any::Any;
+
+// Wheel encoder counts in half-steps since the robot client started, negative backward.
+// The timestamp is in ms of the robot clock, shared with the IMU readings.
+#[derive(Debug)]
+pub struct WheelEncodersMsg {
+    pub id: u8,
+    pub timestamp_ms: u32,
+    pub left_ticks: i32,
+    pub right_ticks: i32,
+}
+
+impl WheelEncodersMsg {
+    pub fn new() -> WheelEncodersMsg {
+        let id_value = MessageId::WheelEncoders as u8;
+        WheelEncodersMsg {
+            id: id_value,
+            timestamp_ms: 0,
+            left_ticks: 0,
+            right_ticks: 0,
+        }
+    }
+}
+
+impl Message for WheelEncodersMsg {
+    fn id(&self) -> u8 {
+        return self.id;
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_mut_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl RecvMessage for WheelEncodersMsg {
+    fn from_bytes(&mut self, buf: &[u8]) {
+        let tmp = slice_as_array!(&buf[0..4], [u8; 4]).expect("WheelEncodersMsg wrong data");
+        self.timestamp_ms = u32::from_be_bytes(*tmp);
+        let tmp = slice_as_array!(&buf[4..8], [u8; 4]).expect("WheelEncodersMsg wrong data");
+        self.left_ticks = i32::from_be_bytes(*tmp);
+        let tmp = slice_as_array!(&buf[8..12], [u8; 4]).expect("WheelEncodersMsg wrong data");
+        self.right_ticks = i32::from_be_bytes(*tmp);
+    }
+}
+
+// Yaw rate in mrad/s, counterclockwise, and the accelerations in mm/s^2, x forward
+// and y to the left
+#[derive(Debug)]
+pub struct ImuMsg {
+    pub id: u8,
+    pub timestamp_ms: u32,
+    pub gyro_z: i32,
+    pub accel_x: i32,
+    pub accel_y: i32,
+}
+
+impl ImuMsg {
+    pub fn new() -> ImuMsg {
+        let id_value = MessageId::Imu as u8;
+        ImuMsg {
+            id: id_value,
+            timestamp_ms: 0,
+            gyro_z: 0,
+            accel_x: 0,
+            accel_y: 0,
+        }
+    }
+}
+
+impl Message for ImuMsg {
+    fn id(&self) -> u8 {
+        return self.id;
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_mut_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl RecvMessage for ImuMsg {
+    fn from_bytes(&mut self, buf: &[u8]) {
+        let tmp = slice_as_array!(&buf[0..4], [u8; 4]).expect("ImuMsg wrong data");
+        self.timestamp_ms = u32::from_be_bytes(*tmp);
+        let tmp = slice_as_array!(&buf[4..8], [u8; 4]).expect("ImuMsg wrong data");
+        self.gyro_z = i32::from_be_bytes(*tmp);
+        let tmp = slice_as_array!(&buf[8..12], [u8; 4]).expect("ImuMsg wrong data");
+        self.accel_x = i32::from_be_bytes(*tmp);
+        let tmp = slice_as_array!(&buf[12..16], [u8; 4]).expect("ImuMsg wrong data");
+        self.accel_y = i32::from_be_bytes(*tmp);
+    }
+}
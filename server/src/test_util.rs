@@ -0,0 +1,19 @@
+// helpers shared by the unit tests of several modules
+use std::error::Error;
+
+pub fn assert_near(value: f64, expected: f64, tolerance: f64) {
+    assert!(
+        (value - expected).abs() <= tolerance,
+        "{} is not near {}",
+        value,
+        expected
+    );
+}
+
+// the error of a failed parse, when it has the expected type
+pub fn parse_error<T, E: Error + 'static>(result: Result<T, Box<dyn Error>>) -> Option<E> {
+    match result {
+        Err(err) => err.downcast::<E>().ok().map(|err| *err),
+        Ok(_) => None,
+    }
+}
@@ -2,9 +2,28 @@ extern crate gtk;
 
 use gdk_pixbuf::Pixbuf;
 use gtk::prelude::*;
+use netbot::geofence::FenceArea;
+use netbot::mission::{MissionState, MissionStatus};
+use netbot::odometry::Pose;
+use netbot::route::{MAX_SPEED_SCALE, MIN_SPEED_SCALE};
+use netbot::script::DEFAULT_TIME_LIMIT;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::error::Error;
+use std::f64::consts::PI;
 use std::fmt;
+use std::rc::Rc;
+
+const MINIMAP_SIZE: i32 = 240;
+// pixels per meter
+const MINIMAP_DEFAULT_SCALE: f64 = 100.0;
+const MINIMAP_MIN_SCALE: f64 = 5.0;
+const MINIMAP_MAX_SCALE: f64 = 2000.0;
+const MINIMAP_ZOOM_STEP: f64 = 1.25;
+// grid lines are drawn at least this far apart, pixels
+const MINIMAP_MIN_GRID_STEP: f64 = 30.0;
+// a new path point is added after the robot moved this far, meters
+const MINIMAP_PATH_STEP: f64 = 0.01;
 
 #[derive(Debug)]
 enum UiErrors {
@@ -18,12 +37,672 @@ impl fmt::Display for UiErrors {
 }
 impl Error for UiErrors {}
 
+// Guided trim calibration: drive straight, measure how far the robot drifted aside
+pub struct TrimDialog {
+    pub dialog: gtk::Dialog,
+    pub drive_button: gtk::Button,
+    pub apply_button: gtk::Button,
+    pub distance_spin: gtk::SpinButton,
+    pub drift_spin: gtk::SpinButton,
+    trim_label: gtk::Label,
+}
+
+impl TrimDialog {
+    fn new(window: &gtk::ApplicationWindow) -> TrimDialog {
+        let dialog = gtk::Dialog::new();
+        dialog.set_title("Trim calibration");
+        dialog.set_transient_for(Some(window));
+        dialog.set_modal(true);
+        dialog.connect_delete_event(|dialog, _| dialog.hide_on_delete());
+
+        let grid = gtk::Grid::new();
+        grid.set_row_spacing(10);
+        grid.set_column_spacing(10);
+        grid.set_border_width(10);
+        let help_label = gtk::Label::new(Some(
+            "1. Put the robot on a straight line and press Drive straight.\n\
+             2. Measure the distance driven and how far the robot ended aside\n\
+             of the line, to the right is positive, then press Apply.\n\
+             Repeat until the drift is small enough.",
+        ));
+        grid.attach(&help_label, 0, 0, 2, 1);
+        let drive_button = gtk::Button::with_label("Drive straight");
+        grid.attach(&drive_button, 0, 1, 2, 1);
+        grid.attach(&gtk::Label::new(Some("Distance, cm")), 0, 2, 1, 1);
+        let distance_spin = gtk::SpinButton::with_range(1.0, 1000.0, 1.0);
+        distance_spin.set_value(100.0);
+        grid.attach(&distance_spin, 1, 2, 1, 1);
+        grid.attach(&gtk::Label::new(Some("Drift, cm")), 0, 3, 1, 1);
+        let drift_spin = gtk::SpinButton::with_range(-100.0, 100.0, 0.5);
+        grid.attach(&drift_spin, 1, 3, 1, 1);
+        let trim_label = gtk::Label::new(None);
+        grid.attach(&trim_label, 0, 4, 2, 1);
+        let apply_button = gtk::Button::with_label("Apply");
+        grid.attach(&apply_button, 0, 5, 2, 1);
+        dialog.get_content_area().add(&grid);
+
+        TrimDialog {
+            dialog: dialog,
+            drive_button: drive_button,
+            apply_button: apply_button,
+            distance_spin: distance_spin,
+            drift_spin: drift_spin,
+            trim_label: trim_label,
+        }
+    }
+
+    pub fn set_trim(&self, left_trim: f64, right_trim: f64) {
+        self.trim_label.set_text(&format!(
+            "Trim: left {:.3} right {:.3}",
+            left_trim, right_trim
+        ));
+    }
+
+    pub fn show(&self) {
+        self.drift_spin.set_value(0.0);
+        self.dialog.show_all();
+    }
+}
+
+fn choose_file(parent: &gtk::Dialog, title: &str) -> Option<std::path::PathBuf> {
+    let chooser = gtk::FileChooserDialog::with_buttons(
+        Some(title),
+        Some(parent),
+        gtk::FileChooserAction::Open,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Open", gtk::ResponseType::Accept),
+        ],
+    );
+    let path = if chooser.run() == gtk::ResponseType::Accept {
+        chooser.get_filename()
+    } else {
+        None
+    };
+    chooser.close();
+    path
+}
+
+fn text_view_text(text_view: &gtk::TextView) -> String {
+    let buffer = text_view.get_buffer().unwrap();
+    let (start, end) = buffer.get_bounds();
+    buffer
+        .get_text(&start, &end, false)
+        .map_or(String::new(), |text| text.to_string())
+}
+
+// Script console: the editor, the output of the running script and the time limit.
+// A separate window as the mission editor, so typing does not drive the robot.
+pub struct ScriptDialog {
+    pub dialog: gtk::Dialog,
+    pub load_button: gtk::Button,
+    pub run_button: gtk::Button,
+    pub stop_button: gtk::Button,
+    pub time_limit_spin: gtk::SpinButton,
+    text_view: gtk::TextView,
+    output_view: gtk::TextView,
+}
+
+impl ScriptDialog {
+    fn new(window: &gtk::ApplicationWindow) -> ScriptDialog {
+        let dialog = gtk::Dialog::new();
+        dialog.set_title("Script console");
+        dialog.set_transient_for(Some(window));
+        dialog.connect_delete_event(|dialog, _| dialog.hide_on_delete());
+
+        let grid = gtk::Grid::new();
+        grid.set_row_spacing(10);
+        grid.set_column_spacing(10);
+        grid.set_border_width(10);
+        let text_view = gtk::TextView::new();
+        text_view.set_property_monospace(true);
+        let text_window =
+            gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        text_window.set_size_request(480, 240);
+        text_window.set_vexpand(true);
+        text_window.set_hexpand(true);
+        text_window.add(&text_view);
+        grid.attach(&text_window, 0, 0, 5, 1);
+        let load_button = gtk::Button::with_label("Load file");
+        grid.attach(&load_button, 0, 1, 1, 1);
+        let run_button = gtk::Button::with_label("Run");
+        grid.attach(&run_button, 1, 1, 1, 1);
+        let stop_button = gtk::Button::with_label("Stop");
+        stop_button.set_sensitive(false);
+        grid.attach(&stop_button, 2, 1, 1, 1);
+        grid.attach(&gtk::Label::new(Some("Time limit, s")), 3, 1, 1, 1);
+        let time_limit_spin = gtk::SpinButton::with_range(1.0, 3600.0, 1.0);
+        time_limit_spin.set_value(DEFAULT_TIME_LIMIT.as_secs_f64());
+        grid.attach(&time_limit_spin, 4, 1, 1, 1);
+        let output_view = gtk::TextView::new();
+        output_view.set_property_monospace(true);
+        output_view.set_editable(false);
+        let output_window =
+            gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        output_window.set_size_request(-1, 120);
+        output_window.add(&output_view);
+        grid.attach(&output_window, 0, 2, 5, 1);
+        dialog.get_content_area().add(&grid);
+
+        ScriptDialog {
+            dialog: dialog,
+            load_button: load_button,
+            run_button: run_button,
+            stop_button: stop_button,
+            time_limit_spin: time_limit_spin,
+            text_view: text_view,
+            output_view: output_view,
+        }
+    }
+
+    pub fn get_text(&self) -> String {
+        text_view_text(&self.text_view)
+    }
+
+    pub fn set_text(&self, text: &str) {
+        self.text_view.get_buffer().unwrap().set_text(text);
+    }
+
+    pub fn choose_file(&self) -> Option<std::path::PathBuf> {
+        choose_file(&self.dialog, "Load script")
+    }
+
+    pub fn clear_output(&self) {
+        self.output_view.get_buffer().unwrap().set_text("");
+    }
+
+    pub fn append_output(&self, line: &str) {
+        let buffer = self.output_view.get_buffer().unwrap();
+        let mut end = buffer.get_end_iter();
+        buffer.insert(&mut end, line);
+        buffer.insert(&mut end, "\n");
+        self.output_view
+            .scroll_to_mark(&buffer.get_insert().unwrap(), 0.0, false, 0.0, 1.0);
+    }
+
+    pub fn set_running(&self, running: bool) {
+        self.run_button.set_sensitive(!running);
+        self.load_button.set_sensitive(!running);
+        self.stop_button.set_sensitive(running);
+    }
+
+    pub fn show(&self) {
+        self.dialog.show_all();
+    }
+}
+
+// Waypoint missions, one waypoint per line as in the mission files. The editor is a
+// separate window so that the arrow keys in it do not drive the robot.
+pub struct MissionDialog {
+    pub dialog: gtk::Dialog,
+    pub load_button: gtk::Button,
+    pub start_button: gtk::Button,
+    pub pause_button: gtk::Button,
+    pub resume_button: gtk::Button,
+    pub abort_button: gtk::Button,
+    text_view: gtk::TextView,
+    status_label: gtk::Label,
+}
+
+impl MissionDialog {
+    fn new(window: &gtk::ApplicationWindow) -> MissionDialog {
+        let dialog = gtk::Dialog::new();
+        dialog.set_title("Mission");
+        dialog.set_transient_for(Some(window));
+        dialog.connect_delete_event(|dialog, _| dialog.hide_on_delete());
+
+        let grid = gtk::Grid::new();
+        grid.set_row_spacing(10);
+        grid.set_column_spacing(10);
+        grid.set_border_width(10);
+        let help_label = gtk::Label::new(Some(
+            "One waypoint per line, distances in meters and angles in degrees:\n\
+             drive 0.5, turn 90 or goto X Y [HEADING] in the minimap frame.\n\
+             Add timeout SECONDS to give up on a waypoint, # starts a comment.",
+        ));
+        grid.attach(&help_label, 0, 0, 4, 1);
+        let text_view = gtk::TextView::new();
+        text_view.set_property_monospace(true);
+        let scrolled_window =
+            gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        scrolled_window.set_size_request(-1, 200);
+        scrolled_window.set_vexpand(true);
+        scrolled_window.add(&text_view);
+        grid.attach(&scrolled_window, 0, 1, 4, 1);
+        let load_button = gtk::Button::with_label("Load file");
+        grid.attach(&load_button, 0, 2, 4, 1);
+        let start_button = gtk::Button::with_label("Start");
+        grid.attach(&start_button, 0, 3, 1, 1);
+        let pause_button = gtk::Button::with_label("Pause");
+        grid.attach(&pause_button, 1, 3, 1, 1);
+        let resume_button = gtk::Button::with_label("Resume");
+        grid.attach(&resume_button, 2, 3, 1, 1);
+        let abort_button = gtk::Button::with_label("Abort");
+        grid.attach(&abort_button, 3, 3, 1, 1);
+        let status_label = gtk::Label::new(None);
+        grid.attach(&status_label, 0, 4, 4, 1);
+        dialog.get_content_area().add(&grid);
+
+        let mission_dialog = MissionDialog {
+            dialog: dialog,
+            load_button: load_button,
+            start_button: start_button,
+            pause_button: pause_button,
+            resume_button: resume_button,
+            abort_button: abort_button,
+            text_view: text_view,
+            status_label: status_label,
+        };
+        mission_dialog.set_status(None);
+        mission_dialog
+    }
+
+    pub fn get_text(&self) -> String {
+        text_view_text(&self.text_view)
+    }
+
+    pub fn set_text(&self, text: &str) {
+        self.text_view.get_buffer().unwrap().set_text(text);
+    }
+
+    // Asks for a mission file to open
+    pub fn choose_file(&self) -> Option<std::path::PathBuf> {
+        choose_file(&self.dialog, "Load mission")
+    }
+
+    pub fn set_status(&self, status: Option<MissionStatus>) {
+        let state = status.map(|status| status.state);
+        let text = match status {
+            Some(status) if status.state == MissionState::Completed => {
+                format!("Mission completed, {} waypoints", status.waypoint_count)
+            }
+            Some(status) => format!(
+                "Mission {}, waypoint {} of {}",
+                status.state.name(),
+                status.waypoint + 1,
+                status.waypoint_count
+            ),
+            None => String::from("No mission"),
+        };
+        self.status_label.set_text(&text);
+        let active = state == Some(MissionState::Running) || state == Some(MissionState::Paused);
+        self.start_button.set_sensitive(!active);
+        self.load_button.set_sensitive(!active);
+        self.pause_button
+            .set_sensitive(state == Some(MissionState::Running));
+        self.resume_button
+            .set_sensitive(state == Some(MissionState::Paused));
+        self.abort_button.set_sensitive(active);
+    }
+
+    pub fn show(&self) {
+        self.dialog.show_all();
+    }
+}
+
+struct MinimapState {
+    path: Vec<(f64, f64)>,
+    pose: Pose,
+    // x and y covariance of the filtered pose
+    position_covariance: Option<[[f64; 2]; 2]>,
+    fence: Option<FenceArea>,
+    // origin of the fence area in the pose frame
+    fence_origin: Pose,
+    scale: f64,
+    // origin offset from the view center, pixels
+    pan: (f64, f64),
+    drag_position: Option<(f64, f64)>,
+}
+
+impl MinimapState {
+    // The start heading points up, so the world x axis is up and y is to the left
+    fn to_screen(&self, width: f64, height: f64, x: f64, y: f64) -> (f64, f64) {
+        (
+            width / 2.0 + self.pan.0 - y * self.scale,
+            height / 2.0 + self.pan.1 - x * self.scale,
+        )
+    }
+
+    // The smallest of 1, 2 and 5 times a power of ten which is not too dense
+    fn grid_step(&self) -> f64 {
+        for exponent in -2..4 {
+            for mantissa in [1.0, 2.0, 5.0].iter() {
+                let step = mantissa * 10f64.powi(exponent);
+                if step * self.scale >= MINIMAP_MIN_GRID_STEP {
+                    return step;
+                }
+            }
+        }
+        10f64.powi(4)
+    }
+
+    // Two standard deviations ellipse of the position
+    fn draw_uncertainty(&self, cr: &cairo::Context, x: f64, y: f64, covariance: [[f64; 2]; 2]) {
+        let (a, b, c) = (covariance[0][0], covariance[0][1], covariance[1][1]);
+        let mean = (a + c) / 2.0;
+        let spread = (((a - c) / 2.0).powi(2) + b * b).sqrt();
+        let major = 2.0 * (mean + spread).max(0.0).sqrt() * self.scale;
+        let minor = 2.0 * (mean - spread).max(0.0).sqrt() * self.scale;
+        if major < 1.0 {
+            return;
+        }
+        let angle = 0.5 * (2.0 * b).atan2(a - c);
+        cr.save();
+        cr.translate(x, y);
+        cr.rotate((-angle.cos()).atan2(-angle.sin()));
+        cr.scale(major, minor.max(1.0));
+        cr.arc(0.0, 0.0, 1.0, 0.0, 2.0 * PI);
+        cr.restore();
+        cr.set_source_rgba(1.0, 0.8, 0.2, 0.3);
+        cr.fill_preserve();
+        cr.set_source_rgb(1.0, 0.8, 0.2);
+        cr.set_line_width(1.0);
+        cr.stroke();
+    }
+
+    fn draw_fence(&self, cr: &cairo::Context, width: f64, height: f64, fence: &FenceArea) {
+        let origin = self.fence_origin;
+        match fence {
+            FenceArea::Circle { radius } => {
+                let (x, y) = self.to_screen(width, height, origin.x, origin.y);
+                cr.arc(x, y, radius * self.scale, 0.0, 2.0 * PI);
+            }
+            FenceArea::Polygon(corners) => {
+                for (i, (x, y)) in corners.iter().enumerate() {
+                    let corner = origin.moved_by(*x, *y, 0.0);
+                    let (sx, sy) = self.to_screen(width, height, corner.x, corner.y);
+                    if i == 0 {
+                        cr.move_to(sx, sy);
+                    } else {
+                        cr.line_to(sx, sy);
+                    }
+                }
+                cr.close_path();
+            }
+        }
+        cr.set_source_rgba(0.9, 0.2, 0.2, 0.1);
+        cr.fill_preserve();
+        cr.set_source_rgb(0.9, 0.2, 0.2);
+        cr.set_line_width(2.0);
+        cr.set_dash(&[6.0, 4.0], 0.0);
+        cr.stroke();
+        cr.set_dash(&[], 0.0);
+    }
+
+    fn draw(&self, cr: &cairo::Context, width: f64, height: f64) {
+        cr.set_source_rgb(0.15, 0.15, 0.15);
+        cr.paint();
+
+        // grid lines through the world coordinates multiple of the step
+        let step = self.grid_step();
+        let (origin_x, origin_y) = self.to_screen(width, height, 0.0, 0.0);
+        let pixel_step = step * self.scale;
+        cr.set_source_rgb(0.3, 0.3, 0.3);
+        cr.set_line_width(1.0);
+        let mut x = origin_x % pixel_step;
+        while x < width {
+            cr.move_to(x, 0.0);
+            cr.line_to(x, height);
+            x += pixel_step;
+        }
+        let mut y = origin_y % pixel_step;
+        while y < height {
+            cr.move_to(0.0, y);
+            cr.line_to(width, y);
+            y += pixel_step;
+        }
+        cr.stroke();
+
+        if let Some(fence) = self.fence.as_ref() {
+            self.draw_fence(cr, width, height, fence);
+        }
+
+        // start point
+        cr.set_source_rgb(0.2, 0.8, 0.2);
+        cr.arc(origin_x, origin_y, 5.0, 0.0, 2.0 * PI);
+        cr.fill();
+
+        // driven path up to the current pose
+        if !self.path.is_empty() {
+            cr.set_source_rgb(0.3, 0.6, 1.0);
+            cr.set_line_width(2.0);
+            let points = self
+                .path
+                .iter()
+                .cloned()
+                .chain(std::iter::once((self.pose.x, self.pose.y)));
+            for (i, (x, y)) in points.enumerate() {
+                let (sx, sy) = self.to_screen(width, height, x, y);
+                if i == 0 {
+                    cr.move_to(sx, sy);
+                } else {
+                    cr.line_to(sx, sy);
+                }
+            }
+            cr.stroke();
+        }
+
+        let (rx, ry) = self.to_screen(width, height, self.pose.x, self.pose.y);
+        if let Some(covariance) = self.position_covariance {
+            self.draw_uncertainty(cr, rx, ry, covariance);
+        }
+
+        // robot heading arrow, the world direction (cos, sin) is (-sin, -cos) on the screen
+        let (sin, cos) = self.pose.heading.sin_cos();
+        let (dx, dy) = (-sin, -cos);
+        cr.set_source_rgb(1.0, 0.3, 0.2);
+        cr.move_to(rx + dx * 12.0, ry + dy * 12.0);
+        cr.line_to(rx - dx * 6.0 - dy * 7.0, ry - dy * 6.0 + dx * 7.0);
+        cr.line_to(rx - dx * 6.0 + dy * 7.0, ry - dy * 6.0 - dx * 7.0);
+        cr.close_path();
+        cr.fill();
+
+        cr.set_source_rgb(0.9, 0.9, 0.9);
+        cr.set_font_size(11.0);
+        cr.move_to(5.0, height - 5.0);
+        cr.show_text(&format!("grid {} m", step));
+        cr.move_to(5.0, 14.0);
+        cr.show_text(&format!(
+            "x {:.2}  y {:.2}  {:.0}\u{b0}",
+            self.pose.x,
+            self.pose.y,
+            self.pose.heading.to_degrees()
+        ));
+        if let Some(fence) = self.fence.as_ref() {
+            let pose = self.pose.relative_to(self.fence_origin);
+            if !fence.contains(pose.x, pose.y) {
+                cr.set_source_rgb(1.0, 0.3, 0.2);
+                cr.move_to(5.0, 28.0);
+                cr.show_text("outside the geofence");
+            }
+        }
+    }
+}
+
+// Robot path and the geofence seen from above, estimated by integrating the wheel
+// commands. Scroll to zoom, drag to pan.
+pub struct Minimap {
+    pub drawing_area: gtk::DrawingArea,
+    pub reset_button: gtk::Button,
+    center_button: gtk::Button,
+    state: Rc<RefCell<MinimapState>>,
+    container: gtk::Box,
+}
+
+impl Minimap {
+    fn new() -> Minimap {
+        let state = Rc::new(RefCell::new(MinimapState {
+            path: Vec::new(),
+            pose: Pose::default(),
+            position_covariance: None,
+            fence: None,
+            fence_origin: Pose::default(),
+            scale: MINIMAP_DEFAULT_SCALE,
+            pan: (0.0, 0.0),
+            drag_position: None,
+        }));
+        let drawing_area = gtk::DrawingArea::new();
+        drawing_area.set_size_request(MINIMAP_SIZE, MINIMAP_SIZE);
+        drawing_area.set_vexpand(true);
+        drawing_area.add_events(
+            gdk::EventMask::SCROLL_MASK
+                | gdk::EventMask::BUTTON_PRESS_MASK
+                | gdk::EventMask::BUTTON_RELEASE_MASK
+                | gdk::EventMask::BUTTON_MOTION_MASK,
+        );
+        {
+            let state = Rc::clone(&state);
+            drawing_area.connect_draw(move |area, cr| {
+                let width = area.get_allocated_width() as f64;
+                let height = area.get_allocated_height() as f64;
+                state.borrow().draw(cr, width, height);
+                Inhibit(false)
+            });
+        }
+        {
+            let state = Rc::clone(&state);
+            drawing_area.connect_scroll_event(move |area, event| {
+                let zoom = match event.get_direction() {
+                    gdk::ScrollDirection::Up => MINIMAP_ZOOM_STEP,
+                    gdk::ScrollDirection::Down => 1.0 / MINIMAP_ZOOM_STEP,
+                    _ => return Inhibit(false),
+                };
+                let mut state = state.borrow_mut();
+                let scale = (state.scale * zoom).clamp(MINIMAP_MIN_SCALE, MINIMAP_MAX_SCALE);
+                // the origin keeps its place relative to the view center
+                let ratio = scale / state.scale;
+                state.pan = (state.pan.0 * ratio, state.pan.1 * ratio);
+                state.scale = scale;
+                area.queue_draw();
+                Inhibit(true)
+            });
+        }
+        {
+            let state = Rc::clone(&state);
+            drawing_area.connect_button_press_event(move |_, event| {
+                state.borrow_mut().drag_position = Some(event.get_position());
+                Inhibit(true)
+            });
+        }
+        {
+            let state = Rc::clone(&state);
+            drawing_area.connect_button_release_event(move |_, _| {
+                state.borrow_mut().drag_position = None;
+                Inhibit(true)
+            });
+        }
+        {
+            let state = Rc::clone(&state);
+            drawing_area.connect_motion_notify_event(move |area, event| {
+                let mut state = state.borrow_mut();
+                if let Some((last_x, last_y)) = state.drag_position {
+                    let (x, y) = event.get_position();
+                    state.pan = (state.pan.0 + x - last_x, state.pan.1 + y - last_y);
+                    state.drag_position = Some((x, y));
+                    area.queue_draw();
+                }
+                Inhibit(true)
+            });
+        }
+
+        let reset_button = gtk::Button::with_label("Reset origin");
+        let center_button = gtk::Button::with_label("Center");
+        {
+            let state = Rc::clone(&state);
+            let area = drawing_area.clone();
+            center_button.connect_clicked(move |_| {
+                let mut state = state.borrow_mut();
+                let pose = state.pose;
+                state.pan = (pose.y * state.scale, pose.x * state.scale);
+                area.queue_draw();
+            });
+        }
+
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 5);
+        container.pack_start(&drawing_area, true, true, 0);
+        let buttons_box = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+        buttons_box.pack_start(&reset_button, true, true, 0);
+        buttons_box.pack_start(&center_button, true, true, 0);
+        container.pack_start(&buttons_box, false, false, 0);
+        Minimap {
+            drawing_area: drawing_area,
+            reset_button: reset_button,
+            center_button: center_button,
+            state: state,
+            container: container,
+        }
+    }
+
+    pub fn set_pose(&mut self, pose: Pose) {
+        let mut state = self.state.borrow_mut();
+        if pose == state.pose {
+            return;
+        }
+        let moved = match state.path.last() {
+            Some((x, y)) => (pose.x - x).hypot(pose.y - y) >= MINIMAP_PATH_STEP,
+            None => true,
+        };
+        if moved {
+            state.path.push((pose.x, pose.y));
+        }
+        state.pose = pose;
+        self.drawing_area.queue_draw();
+    }
+
+    // None hides the uncertainty, when the pose is not filtered
+    pub fn set_position_covariance(&mut self, covariance: Option<[[f64; 2]; 2]>) {
+        let mut state = self.state.borrow_mut();
+        if covariance != state.position_covariance {
+            state.position_covariance = covariance;
+            self.drawing_area.queue_draw();
+        }
+    }
+
+    // Operating area in the frame of the origin pose, None hides it
+    pub fn set_geofence(&mut self, fence: Option<FenceArea>, origin: Pose) {
+        let mut state = self.state.borrow_mut();
+        if fence != state.fence || origin != state.fence_origin {
+            state.fence = fence;
+            state.fence_origin = origin;
+            self.drawing_area.queue_draw();
+        }
+    }
+
+    // Forgets the driven path, should be called with the robot pose reset
+    pub fn reset(&mut self) {
+        let mut state = self.state.borrow_mut();
+        state.path.clear();
+        state.pose = Pose::default();
+        state.pan = (0.0, 0.0);
+        self.drawing_area.queue_draw();
+    }
+}
+
 pub struct WindowUi {
     ui_frame_width: i32,
     ui_frame_height: i32,
     pub camera_views: HashMap<u8, gtk::Image>,
     pub camera_res_combos: HashMap<u8, gtk::ComboBox>,
     pub camera_encoding_checks: HashMap<u8, gtk::CheckButton>,
+    speed_label: gtk::Label,
+    control_label: gtk::Label,
+    estop_label: gtk::Label,
+    pub estop_button: gtk::Button,
+    pub estop_reset_button: gtk::Button,
+    pub trim_button: gtk::Button,
+    pub mission_button: gtk::Button,
+    pub script_button: gtk::Button,
+    pub record_button: gtk::ToggleButton,
+    pub replay_button: gtk::Button,
+    pub return_button: gtk::Button,
+    pub replay_scale_spin: gtk::SpinButton,
+    route_label: gtk::Label,
+    pub strafe_left_button: gtk::Button,
+    pub strafe_right_button: gtk::Button,
+    pub trim_dialog: TrimDialog,
+    pub mission_dialog: MissionDialog,
+    pub script_dialog: ScriptDialog,
+    pub minimap: Minimap,
     container: gtk::Grid,
     pub window: gtk::ApplicationWindow,
 }
@@ -105,6 +784,52 @@ impl<'a> WindowUi {
             );
         }
 
+        let status_box = gtk::Box::new(gtk::Orientation::Horizontal, 20);
+        let speed_label = gtk::Label::new(None);
+        status_box.pack_start(&speed_label, false, false, 0);
+        let control_label = gtk::Label::new(Some("Control: none"));
+        status_box.pack_start(&control_label, false, false, 0);
+        let trim_button = gtk::Button::with_label("Calibrate trim");
+        status_box.pack_end(&trim_button, false, false, 0);
+        let mission_button = gtk::Button::with_label("Mission");
+        status_box.pack_end(&mission_button, false, false, 0);
+        let script_button = gtk::Button::with_label("Script");
+        status_box.pack_end(&script_button, false, false, 0);
+        let strafe_right_button = gtk::Button::with_label("Strafe right (D)");
+        status_box.pack_end(&strafe_right_button, false, false, 0);
+        let strafe_left_button = gtk::Button::with_label("Strafe left (A)");
+        status_box.pack_end(&strafe_left_button, false, false, 0);
+        container.attach(&status_box, 0, 3, max_cols_num as i32, 1);
+
+        let estop_box = gtk::Box::new(gtk::Orientation::Horizontal, 20);
+        let estop_button = gtk::Button::with_label("EMERGENCY STOP (Esc)");
+        estop_button.set_size_request(-1, 60);
+        estop_box.pack_start(&estop_button, true, true, 0);
+        let estop_label = gtk::Label::new(None);
+        estop_box.pack_start(&estop_label, false, false, 0);
+        let estop_reset_button = gtk::Button::with_label("Reset E-stop");
+        estop_reset_button.set_sensitive(false);
+        estop_box.pack_start(&estop_reset_button, false, false, 0);
+        container.attach(&estop_box, 0, 4, max_cols_num as i32, 1);
+
+        let route_box = gtk::Box::new(gtk::Orientation::Horizontal, 20);
+        let record_button = gtk::ToggleButton::with_label("Record route");
+        route_box.pack_start(&record_button, false, false, 0);
+        let replay_button = gtk::Button::with_label("Replay route");
+        route_box.pack_start(&replay_button, false, false, 0);
+        let return_button = gtk::Button::with_label("Return to start");
+        route_box.pack_start(&return_button, false, false, 0);
+        route_box.pack_start(&gtk::Label::new(Some("Replay speed")), false, false, 0);
+        let replay_scale_spin = gtk::SpinButton::with_range(MIN_SPEED_SCALE, MAX_SPEED_SCALE, 0.1);
+        replay_scale_spin.set_value(1.0);
+        route_box.pack_start(&replay_scale_spin, false, false, 0);
+        let route_label = gtk::Label::new(None);
+        route_box.pack_start(&route_label, false, false, 0);
+        container.attach(&route_box, 0, 5, max_cols_num as i32, 1);
+
+        let minimap = Minimap::new();
+        container.attach(&minimap.container, max_cols_num as i32, 0, 1, 4);
+
         let window = gtk::ApplicationWindow::new(application);
         window.set_icon_name(Some("package-x-generic"));
         window.set_property_window_position(gtk::WindowPosition::Center);
@@ -114,12 +839,34 @@ impl<'a> WindowUi {
             window.close();
             Inhibit(false)
         });
+        let trim_dialog = TrimDialog::new(&window);
+        let mission_dialog = MissionDialog::new(&window);
+        let script_dialog = ScriptDialog::new(&window);
         WindowUi {
             ui_frame_width: frame_width,
             ui_frame_height: frame_height,
             camera_views: camera_views,
             camera_res_combos: camera_res_combos,
             camera_encoding_checks: camera_encoding_checks,
+            speed_label: speed_label,
+            control_label: control_label,
+            estop_label: estop_label,
+            estop_button: estop_button,
+            estop_reset_button: estop_reset_button,
+            trim_button: trim_button,
+            mission_button: mission_button,
+            script_button: script_button,
+            record_button: record_button,
+            replay_button: replay_button,
+            return_button: return_button,
+            replay_scale_spin: replay_scale_spin,
+            route_label: route_label,
+            strafe_left_button: strafe_left_button,
+            strafe_right_button: strafe_right_button,
+            trim_dialog: trim_dialog,
+            mission_dialog: mission_dialog,
+            script_dialog: script_dialog,
+            minimap: minimap,
             container: container,
             window: window,
         }
@@ -137,6 +884,45 @@ impl<'a> WindowUi {
         }
     }
 
+    pub fn set_speed(&mut self, speed: u8) {
+        self.speed_label
+            .set_text(&format!("Speed: {} (+/- to change)", speed));
+    }
+
+    pub fn set_control_source(&mut self, source: Option<&str>) {
+        self.control_label
+            .set_text(&format!("Control: {}", source.unwrap_or("none")));
+    }
+
+    // Strafing controls are shown only for chassis which can move sideways
+    pub fn set_holonomic(&mut self, holonomic: bool) {
+        self.strafe_left_button.set_visible(holonomic);
+        self.strafe_right_button.set_visible(holonomic);
+    }
+
+    pub fn set_emergency_stop(&mut self, engaged: bool) {
+        if engaged {
+            self.estop_label.set_markup(
+                "<span size='x-large' weight='bold' foreground='white' background='red'> E-STOP ENGAGED </span>",
+            );
+        } else {
+            self.estop_label.set_text("");
+        }
+        self.estop_reset_button.set_sensitive(engaged);
+    }
+
+    // Replay progress is in played and total seconds of the route
+    pub fn set_route_status(&mut self, recording: bool, replay_progress: Option<(f64, f64)>) {
+        let text = match replay_progress {
+            Some((played, total)) => format!("Replaying {:.1} of {:.1} s", played, total),
+            None if recording => String::from("Recording"),
+            None => String::new(),
+        };
+        self.route_label.set_text(&text);
+        self.replay_button.set_sensitive(!recording);
+        self.return_button.set_sensitive(!recording);
+    }
+
     pub fn update_image(&mut self, camera_id: u8, image_data: &mut Vec<u8>) {
         let view = (&self.camera_views).get(&camera_id).unwrap();
         let pixbuf = Pixbuf::from_mut_slice(